@@ -0,0 +1,220 @@
+use bincode;
+use db_state::{DbSettings, DbState};
+use error::{Error, Result};
+use internal_types::LSN;
+use log::{LogHeader, LogMgr, RecoveryTarget};
+use meta::Meta;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use storage::{BufKey, BufMgr, BufPage, BufType};
+use tuple::TuplePtr;
+use utils;
+
+#[cfg(test)]
+mod tests;
+
+const LABEL_FILE: &str = "backup_label";
+
+/// Written last to a backup. A restore redoes the log from start_cp,
+/// the data files are only consistent once end_lsn is redone.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupLabel {
+    pub start_cp: TuplePtr,
+    pub end_lsn: LSN,
+    /// Milliseconds since the Unix epoch, see LogHeader::timestamp
+    pub end_time: u64,
+}
+
+/// Copy the data files and the log to dir while the database runs
+pub fn backup(dir: &str, db_state: &mut DbState) -> Result<BackupLabel> {
+    if utils::file_exists(dir) {
+        return Err(Error::internal(format!("{} already exists", dir)));
+    }
+
+    db_state.log_mgr.start_backup()?;
+    let label = copy_files(dir, db_state);
+    db_state.log_mgr.stop_backup();
+    label
+}
+
+/// Restore the backup in backup_dir to the data dir of settings, which
+/// must not exist yet, then redo the log up to target. Segments in
+/// archive_dir continue the log of the backup.
+pub fn restore(
+    backup_dir: &str,
+    archive_dir: Option<&str>,
+    target: RecoveryTarget,
+    settings: DbSettings,
+) -> Result<BackupLabel> {
+    let label: BackupLabel = bincode::deserialize(&fs::read(format!(
+        "{}/{}",
+        backup_dir, LABEL_FILE
+    ))?)?;
+    if target.lsn.is_some_and(|lsn| lsn < label.end_lsn)
+        || target.time.is_some_and(|time| time < label.end_time)
+    {
+        return Err(Error::internal(
+            "The recovery target is before the end of the backup",
+        ));
+    }
+
+    // The backup has the meta page of the database
+    DbState::check_page_size(&settings.clone().data_dir(backup_dir))?;
+    let data_dir = settings.get_data_dir();
+    if utils::file_exists(&data_dir) {
+        return Err(Error::internal(format!("{} already exists", data_dir)));
+    }
+    fs::create_dir_all(format!("{}/temp", data_dir))?;
+    copy_data_files(backup_dir, &data_dir)?;
+    copy_log(backup_dir, archive_dir, &data_dir)?;
+
+    let mut buf_mgr = BufMgr::new(settings.clone());
+    let mut log_mgr = LogMgr::load(&mut buf_mgr)?;
+    buf_mgr.set_log_mgr(&log_mgr);
+    log_mgr.reset_checkpoint(label.start_cp, &mut buf_mgr)?;
+    let meta = Meta::create_and_load(&mut buf_mgr)?;
+    let mut db_state = DbState {
+        buf_mgr,
+        log_mgr,
+        meta,
+        settings,
+    };
+    db_state.log_mgr.clone().recover_to(target, &mut db_state)?;
+    db_state.shutdown()?;
+    Ok(label)
+}
+
+fn copy_files(dir: &str, db_state: &mut DbState) -> Result<BackupLabel> {
+    // Everything logged before the checkpoint is in the data files,
+    // pages changed after it are redone from the log
+    let cp = db_state.log_mgr.create_checkpoint(&mut db_state.buf_mgr)?;
+    db_state.meta.persist_counters()?;
+    db_state.buf_mgr.persist()?;
+    db_state
+        .log_mgr
+        .confirm_checkpoint(cp, &mut db_state.buf_mgr)?;
+    let start_cp = if cp == LogMgr::default_checkpoint() {
+        db_state.log_mgr.last_checkpoint()
+    } else {
+        cp
+    };
+
+    // Pages changed after the checkpoint are redone and indices are
+    // rebuilt when the backup is restored
+    fs::create_dir_all(dir)?;
+    copy_data_pages(dir, db_state)?;
+
+    let label = BackupLabel {
+        start_cp,
+        end_lsn: db_state.meta.cur_lsn(),
+        end_time: LogHeader::now(),
+    };
+    db_state.log_mgr.copy_segments(
+        start_cp.buf_key.segment(),
+        dir,
+        &db_state.buf_mgr,
+    )?;
+    fs::write(
+        format!("{}/{}", dir, LABEL_FILE),
+        bincode::serialize(&label)?,
+    )?;
+    Ok(label)
+}
+
+/// Copy the .dat files page by page from the buffer pool, so no page
+/// is copied while it is written. Files dropped meanwhile are skipped.
+fn copy_data_pages(dir: &str, db_state: &mut DbState) -> Result<()> {
+    let page_size = db_state.buf_mgr.page_size();
+    for file_id in utils::data_file_ids(&db_state.settings.get_data_dir())? {
+        let key = BufKey::new(file_id, 0, BufType::Data);
+        let filename = db_state.buf_mgr.key_to_filename(key);
+        let num_pages = match utils::file_len(&filename) {
+            Ok(len) => len / page_size as u64,
+            Err(ref e) if e.io_kind() == Some(ErrorKind::NotFound) => continue,
+            Err(e) => return Err(e),
+        };
+        let mut data = Vec::with_capacity(num_pages as usize * page_size);
+        for offset in 0..num_pages {
+            let key = BufKey::new(file_id, offset, BufType::Data);
+            let page = match db_state.buf_mgr.get_buf(&key) {
+                Ok(page) => page,
+                Err(ref e) if e.io_kind().is_some_and(|kind| {
+                    kind == ErrorKind::NotFound
+                        || kind == ErrorKind::UnexpectedEof
+                }) => break,
+                Err(e) => return Err(e),
+            };
+            let start = data.len();
+            data.extend_from_slice(page.read().unwrap().buf());
+            BufPage::set_checksum(&mut data[start..]);
+        }
+        fs::write(format!("{}/{}.dat", dir, file_id), data)?;
+    }
+    Ok(())
+}
+
+/// Copy the .dat files of from_dir
+fn copy_data_files(from_dir: &str, to_dir: &str) -> Result<()> {
+    for entry in fs::read_dir(from_dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let is_data = name
+            .to_str()
+            .is_some_and(|name| name.ends_with(".dat"));
+        if !is_data {
+            continue;
+        }
+        if let Err(e) = fs::copy(entry.path(), format!(
+            "{}/{}",
+            to_dir,
+            name.to_string_lossy()
+        )) {
+            if e.kind() != ErrorKind::NotFound {
+                return Err(Error::from(e));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Copy the log of the backup, continued by the archived segments.
+/// An archived segment is complete, so it replaces the backup's copy.
+fn copy_log(
+    backup_dir: &str,
+    archive_dir: Option<&str>,
+    data_dir: &str,
+) -> Result<()> {
+    use std::collections::BTreeMap;
+
+    let backup_wal = format!("{}/wal", backup_dir);
+    let mut sources = BTreeMap::new();
+    for segment in LogMgr::segments_in(&backup_wal)? {
+        sources.insert(segment, backup_wal.clone());
+    }
+    let first = match sources.keys().next() {
+        Some(first) => *first,
+        None => return Err(Error::internal("The backup has no log")),
+    };
+    if let Some(archive_dir) = archive_dir {
+        for segment in LogMgr::segments_in(archive_dir)? {
+            if segment >= first {
+                sources.insert(segment, archive_dir.to_string());
+            }
+        }
+    }
+
+    let data_wal = format!("{}/wal", data_dir);
+    fs::create_dir_all(&data_wal)?;
+    // Redo cannot go past a missing segment
+    for (next, (segment, dir)) in (first..).zip(sources.iter()) {
+        if *segment != next {
+            break;
+        }
+        fs::copy(
+            format!("{}/{:08}.log", dir, segment),
+            format!("{}/{:08}.log", data_wal, segment),
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,219 @@
+use backup::{backup, restore};
+use bincode;
+use data_type::DataType;
+use db_state::{DbSettings, DbState};
+use log::{LogEntry, OpType, RecoveryTarget};
+use nom_sql::Literal;
+use rel::Rel;
+use std::fs;
+use storage::{BufKey, BufType, DEFAULT_PAGE_SIZE};
+use test_utils::{setup_no_persist, teardown};
+use tuple::TupleDesc;
+
+#[test]
+fn test_backup_restore() {
+    let data_dir = "test_backup_restore";
+    let backup_dir = "test_backup_restore_backup";
+    let restore_dir = "test_backup_restore_restored";
+    let mut db_state = setup_no_persist(data_dir);
+    let rel = new_rel(&mut db_state);
+    insert(&rel, 1, &mut db_state);
+
+    let label = backup(backup_dir, &mut db_state).unwrap();
+    // Not in the backup
+    insert(&rel, 2, &mut db_state);
+    teardown(db_state);
+
+    let settings = DbSettings::default().data_dir(restore_dir);
+    restore(backup_dir, None, RecoveryTarget::default(), settings).unwrap();
+    let mut db_state = setup_no_persist(restore_dir);
+    let values = scan(&rel, &mut db_state);
+    teardown(db_state);
+    fs::remove_dir_all(backup_dir).unwrap();
+
+    assert!(label.end_lsn > 0);
+    assert_eq!(values, vec!["1"]);
+}
+
+#[test]
+fn test_restore_to_lsn() {
+    let data_dir = "test_restore_to_lsn";
+    let backup_dir = "test_restore_to_lsn_backup";
+    let archive_dir = "test_restore_to_lsn_archive";
+    let restore_dirs =
+        ["test_restore_to_lsn_1", "test_restore_to_lsn_2"];
+    let mut db_state = setup_no_persist(data_dir);
+    let rel = new_rel(&mut db_state);
+    insert(&rel, 1, &mut db_state);
+
+    let label = backup(backup_dir, &mut db_state).unwrap();
+    insert(&rel, 2, &mut db_state);
+    let target_lsn = db_state.meta.cur_lsn() - 1;
+    insert(&rel, 3, &mut db_state);
+    archive_log(data_dir, archive_dir);
+    teardown(db_state);
+
+    let early = RecoveryTarget {
+        lsn: Some(label.end_lsn - 1),
+        time: None,
+    };
+    let early_res = restore(
+        backup_dir,
+        Some(archive_dir),
+        early,
+        DbSettings::default().data_dir(restore_dirs[0]),
+    );
+
+    let targets = [
+        RecoveryTarget {
+            lsn: Some(target_lsn),
+            time: None,
+        },
+        RecoveryTarget::default(),
+    ];
+    let mut restored = vec![];
+    for (dir, target) in restore_dirs.iter().zip(targets.iter()) {
+        let settings = DbSettings::default().data_dir(*dir);
+        restore(backup_dir, Some(archive_dir), *target, settings).unwrap();
+        let mut db_state = setup_no_persist(dir);
+        restored.push(scan(&rel, &mut db_state));
+        teardown(db_state);
+    }
+    fs::remove_dir_all(backup_dir).unwrap();
+    fs::remove_dir_all(archive_dir).unwrap();
+
+    assert!(early_res.is_err());
+    assert_eq!(restored[0], vec!["1", "2"]);
+    assert_eq!(restored[1], vec!["1", "2", "3"]);
+}
+
+#[test]
+fn test_restore_interleaved_lsns() {
+    let data_dir = "test_restore_interleaved_lsns";
+    let backup_dir = "test_restore_interleaved_lsns_backup";
+    let archive_dir = "test_restore_interleaved_lsns_archive";
+    let restore_dir = "test_restore_interleaved_lsns_restored";
+    let mut db_state = setup_no_persist(data_dir);
+    let first = new_rel(&mut db_state);
+    let second = new_named_rel("second", &mut db_state);
+    insert(&first, 1, &mut db_state);
+
+    backup(backup_dir, &mut db_state).unwrap();
+    // Concurrent writers can append a lower LSN after a higher one
+    let entry = |rel: &Rel, value: u32, db_state: &mut DbState| {
+        let key = BufKey::new(rel.rel_id, 1, BufType::Data);
+        let data = bincode::serialize(&value).unwrap();
+        LogEntry::new(key, OpType::InsertTuple, data, db_state).unwrap()
+    };
+    let low = entry(&second, 2, &mut db_state);
+    let high = entry(&first, 3, &mut db_state);
+    let target_lsn = low.header.lsn;
+    db_state
+        .log_mgr
+        .write_entries(vec![high, low], &mut db_state.buf_mgr)
+        .unwrap();
+    archive_log(data_dir, archive_dir);
+    teardown(db_state);
+
+    let target = RecoveryTarget {
+        lsn: Some(target_lsn),
+        time: None,
+    };
+    let settings = DbSettings::default().data_dir(restore_dir);
+    restore(backup_dir, Some(archive_dir), target, settings).unwrap();
+    let mut db_state = setup_no_persist(restore_dir);
+    let first_values = scan(&first, &mut db_state);
+    let second_values = scan(&second, &mut db_state);
+    teardown(db_state);
+    fs::remove_dir_all(backup_dir).unwrap();
+    fs::remove_dir_all(archive_dir).unwrap();
+
+    assert_eq!(first_values, vec!["1"]);
+    assert_eq!(second_values, vec!["2"]);
+}
+
+#[test]
+fn test_restore_index() {
+    use index::{HashIndex, Index, IndexType};
+
+    let data_dir = "test_restore_index";
+    let backup_dir = "test_restore_index_backup";
+    let restore_dir = "test_restore_index_restored";
+    let mut db_state = setup_no_persist(data_dir);
+    let mut rel = new_rel(&mut db_state);
+    rel.new_index(vec![0], IndexType::Hash, &mut db_state).unwrap();
+    insert(&rel, 1, &mut db_state);
+    backup(backup_dir, &mut db_state).unwrap();
+    teardown(db_state);
+
+    // The page size of the backup is kept
+    let settings = DbSettings::default().data_dir(restore_dir);
+    let other_size = restore(
+        backup_dir,
+        None,
+        RecoveryTarget::default(),
+        settings.clone().page_size(2 * DEFAULT_PAGE_SIZE),
+    );
+    restore(backup_dir, None, RecoveryTarget::default(), settings).unwrap();
+    let mut db_state = setup_no_persist(restore_dir);
+    let rel = Rel::load(rel.rel_id, BufType::Data, &mut db_state).unwrap();
+    let index =
+        HashIndex::load(rel.indices()[0].file_id, &mut db_state).unwrap();
+    let found = index
+        .get(&bincode::serialize(&1u32).unwrap(), &mut db_state)
+        .unwrap();
+    teardown(db_state);
+    fs::remove_dir_all(backup_dir).unwrap();
+
+    assert!(other_size.is_err());
+    assert_eq!(found.len(), 1);
+}
+
+/// Copy the live log to archive_dir, as if its segments had been removed
+fn archive_log(data_dir: &str, archive_dir: &str) {
+    fs::create_dir_all(archive_dir).unwrap();
+    for entry in fs::read_dir(format!("{}/wal", data_dir)).unwrap() {
+        let entry = entry.unwrap();
+        fs::copy(
+            entry.path(),
+            format!("{}/{}", archive_dir, entry.file_name().to_string_lossy()),
+        )
+        .unwrap();
+    }
+}
+
+fn new_rel(db_state: &mut DbState) -> Rel {
+    new_named_rel("rel", db_state)
+}
+
+fn new_named_rel(name: &str, db_state: &mut DbState) -> Rel {
+    let desc = TupleDesc::new(vec![DataType::U32], vec!["u32"]);
+    let rel = Rel::new(name, desc, db_state).unwrap();
+    db_state.buf_mgr.persist().unwrap();
+    rel
+}
+
+fn insert(rel: &Rel, value: i64, db_state: &mut DbState) {
+    let tuples = rel
+        .literal_to_data(vec![vec![Literal::Integer(value)]])
+        .unwrap();
+    rel.write_tuples(&mut tuples.into_iter(), db_state).unwrap();
+}
+
+fn scan(rel: &Rel, db_state: &mut DbState) -> Vec<String> {
+    let rel = Rel::load(rel.rel_id, BufType::Data, db_state).unwrap();
+    let mut values = vec![];
+    rel.scan(
+        db_state,
+        |_| Ok(true),
+        |data, _db_state| {
+            values.push(data.to_vec());
+            Ok(())
+        },
+    )
+    .unwrap();
+    values
+        .iter()
+        .map(|data| rel.data_to_strings(data, None).unwrap().remove(0))
+        .collect()
+}
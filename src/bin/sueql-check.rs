@@ -0,0 +1,33 @@
+extern crate sueql;
+
+use sueql::check;
+use sueql::db_state::DbSettings;
+use sueql::error;
+
+/// Check a database that is not running, exits with 1 if any problem
+/// is found. Usage: sueql-check [data_dir]
+fn main() -> error::Result<()> {
+    let mut settings = DbSettings::default();
+    if let Some(dir) = std::env::args().nth(1) {
+        settings = settings.data_dir(dir);
+    }
+
+    let mut db_state = check::open(settings)?;
+    let report = check::check(&mut db_state)?;
+    for problem in report.problems.iter() {
+        println!("{}", problem);
+    }
+    println!(
+        "Checked {} pages, {} tuples, {} index items and {} log entries, \
+         found {} problems",
+        report.pages,
+        report.tuples,
+        report.index_items,
+        report.log_entries,
+        report.problems.len()
+    );
+    if !report.is_ok() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,25 @@
+extern crate sueql;
+
+use sueql::check;
+use sueql::db_state::DbSettings;
+use sueql::error::{Error, Result};
+use sueql::inspect;
+
+/// Print the pages of a data file of a database that is not running.
+/// Usage: sueql-dump data_dir file_id, the log is file 3
+fn main() -> Result<()> {
+    let usage = "Usage: sueql-dump data_dir file_id";
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (data_dir, file_id) = match args.as_slice() {
+        [data_dir, file_id] => match file_id.parse() {
+            Ok(file_id) => (data_dir, file_id),
+            Err(_) => return Err(Error::internal(usage)),
+        },
+        _ => return Err(Error::internal(usage)),
+    };
+
+    let settings = DbSettings::default().data_dir(data_dir.as_str());
+    let mut db_state = check::open(settings)?;
+    let stdout = std::io::stdout();
+    inspect::dump(file_id, &mut db_state, &mut stdout.lock())
+}
@@ -0,0 +1,220 @@
+use db_state::{DbSettings, DbState};
+use error::Result;
+use index::{HashIndex, Index, IndexType};
+use internal_types::ID;
+use log::{LogEntry, LogMgr, OpType, LOG_REL_ID};
+use meta::Meta;
+use rel::Rel;
+use std::collections::HashSet;
+use std::fs::read_dir;
+use storage::{BufKey, BufMgr, BufType, LOG_SEGMENT_PAGES};
+use tuple::TuplePtr;
+use utils;
+
+#[cfg(test)]
+mod tests;
+
+/// What was checked, problems are empty if the database is consistent
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    pub pages: usize,
+    pub tuples: usize,
+    pub index_items: usize,
+    pub log_entries: usize,
+    pub problems: Vec<String>,
+}
+
+impl CheckReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    fn problem<S: Into<String>>(&mut self, problem: S) {
+        self.problems.push(problem.into());
+    }
+}
+
+/// Open a database without recovering it, so it is checked as it is on
+/// disk. Nothing is persisted, the database must not be running.
+pub fn open(settings: DbSettings) -> Result<DbState> {
+    let mut buf_mgr = BufMgr::new(settings.clone());
+    let log_mgr = LogMgr::load(&mut buf_mgr)?;
+    buf_mgr.set_log_mgr(&log_mgr);
+    let meta = Meta::load(&mut buf_mgr)?;
+    Ok(DbState {
+        buf_mgr,
+        log_mgr,
+        meta,
+        settings,
+    })
+}
+
+/// Verify the pages of every data file, the tuples of every table
+/// against its indexes, and the entries of the WAL
+pub fn check(db_state: &mut DbState) -> Result<CheckReport> {
+    let mut report = CheckReport::default();
+    check_pages(db_state, &mut report)?;
+    for rel_id in utils::get_all_table_ids(db_state)? {
+        check_rel(rel_id, db_state, &mut report)?;
+    }
+    check_log(db_state, &mut report)?;
+    Ok(report)
+}
+
+/// Every page of the data files must pass its checksum and have a
+/// valid header and slots
+fn check_pages(db_state: &mut DbState, report: &mut CheckReport) -> Result<()> {
+    let data_dir = db_state.settings.get_data_dir();
+    let mut file_ids = vec![];
+    for entry in read_dir(&data_dir)? {
+        let id = entry?
+            .file_name()
+            .to_str()
+            .and_then(|name| name.strip_suffix(".dat"))
+            .and_then(|id| id.parse::<ID>().ok());
+        file_ids.extend(id);
+    }
+    file_ids.sort();
+
+    let page_size = db_state.buf_mgr.page_size();
+    for file_id in file_ids {
+        let key = BufKey::new(file_id, 0, BufType::Data);
+        let filename = db_state.buf_mgr.key_to_filename(key);
+        if utils::file_len(&filename)? % page_size as u64 != 0 {
+            report.problem(format!("{} is not a whole number of pages",
+                                   filename));
+        }
+        for offset in 0..utils::num_pages(&filename, page_size)? + 1 {
+            let key = BufKey::new(file_id, offset, BufType::Data);
+            report.pages += 1;
+            let result = db_state
+                .buf_mgr
+                .get_buf(&key)
+                .and_then(|page| page.read().unwrap().validate());
+            if let Err(e) = result {
+                report.problem(format!("Page {:?}: {:?}", key, e));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Every tuple must decode with the table's TupleDesc, and its indexes
+/// must have exactly the table's tuples
+fn check_rel(
+    rel_id: ID,
+    db_state: &mut DbState,
+    report: &mut CheckReport,
+) -> Result<()> {
+    let rel = Rel::load(rel_id, BufType::Data, db_state)?;
+    let name = rel.name().unwrap_or_else(|| rel_id.to_string());
+    let desc = rel.tuple_desc();
+
+    let mut tuples = vec![];
+    for offset in 1..rel.num_pages(&mut db_state.buf_mgr)? + 1 {
+        let key = BufKey::new(rel_id, offset, BufType::Data);
+        // Bad pages are already reported by check_pages
+        let page = match db_state.buf_mgr.get_buf(&key) {
+            Ok(page) => page,
+            Err(_) => continue,
+        };
+        let guard = page.read().unwrap();
+        for ptr in guard.get_all_ptrs() {
+            let data = match guard.get_tuple_data(&ptr) {
+                Ok(data) => data,
+                Err(_) => continue,
+            };
+            report.tuples += 1;
+            match desc.decode(data) {
+                Ok(_) => tuples.push((ptr, data.to_vec())),
+                Err(e) => report.problem(format!(
+                    "Table {}: tuple {:?} does not match its columns: {:?}",
+                    name, ptr, e
+                )),
+            }
+        }
+    }
+
+    let heap_ptrs: HashSet<TuplePtr> =
+        tuples.iter().map(|(ptr, _)| *ptr).collect();
+    for info in rel.indices() {
+        let index = match info.index_type {
+            IndexType::Hash => HashIndex::load(info.file_id, db_state)?,
+        };
+        for (ptr, data) in tuples.iter() {
+            let key = desc.data_subset(data, &info.key)?;
+            if !index.get(&key, db_state)?.contains(ptr) {
+                report.problem(format!(
+                    "Table {}: index {} has no item for tuple {:?}",
+                    name, info.file_id, ptr
+                ));
+            }
+        }
+        for ptr in index.all_ptrs(db_state)? {
+            report.index_items += 1;
+            if !heap_ptrs.contains(&ptr) {
+                report.problem(format!(
+                    "Table {}: index {} points to missing tuple {:?}",
+                    name, info.file_id, ptr
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The segments must be contiguous, every entry must pass its checksum
+/// and the last checkpoint must point to a checkpoint entry
+fn check_log(db_state: &mut DbState, report: &mut CheckReport) -> Result<()> {
+    let data_dir = db_state.buf_mgr.data_dir();
+    let segments = LogMgr::segments_in(&format!("{}/wal", data_dir))?;
+    for pair in segments.windows(2) {
+        if pair[0] + 1 != pair[1] {
+            report.problem(format!(
+                "Log segments {} to {} are missing",
+                pair[0] + 1, pair[1] - 1
+            ));
+        }
+    }
+
+    let last_cp = db_state.log_mgr.last_checkpoint();
+    let mut found_cp = last_cp == LogMgr::default_checkpoint();
+    for segment in segments {
+        let filename = BufKey::segment_filename(data_dir.clone(), segment);
+        let num_pages = utils::file_len(&filename)?
+            / db_state.buf_mgr.page_size() as u64;
+        for page_idx in 0..num_pages {
+            let key = BufKey::new(
+                LOG_REL_ID,
+                segment * LOG_SEGMENT_PAGES + page_idx,
+                BufType::Log,
+            );
+            report.pages += 1;
+            let page = match db_state.buf_mgr.get_buf(&key) {
+                Ok(page) => page,
+                Err(e) => {
+                    report.problem(format!("Page {:?}: {:?}", key, e));
+                    continue;
+                }
+            };
+            let guard = page.read().unwrap();
+            for (i, data) in guard.iter().enumerate() {
+                let ptr = TuplePtr::new(key, i);
+                report.log_entries += 1;
+                match LogEntry::load(data, &ptr) {
+                    Ok(entry) => if ptr == last_cp {
+                        found_cp = entry.header.op == OpType::Checkpoint;
+                    },
+                    Err(e) => {
+                        report.problem(format!("Log entry {:?}: {:?}", ptr, e))
+                    }
+                }
+            }
+        }
+    }
+    if !found_cp {
+        report.problem(format!(
+            "Last checkpoint {:?} is not a checkpoint entry", last_cp));
+    }
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use check::{check, open};
+use data_type::DataType;
+use db_state::DbSettings;
+use index::{HashIndex, Index, IndexType};
+use nom_sql::Literal;
+use rel::Rel;
+use std::fs::remove_dir_all;
+use test_utils::setup_no_persist;
+use tuple::TupleDesc;
+
+#[test]
+fn test_check() {
+    let data_dir = "test_check";
+    let mut db_state = setup_no_persist(data_dir);
+    let desc = TupleDesc::new(vec![DataType::U32], vec!["u32"]);
+    let mut rel = Rel::new("rel", desc, &mut db_state).unwrap();
+    let info = rel
+        .new_index(vec![0], IndexType::Hash, &mut db_state)
+        .unwrap();
+    let tuples = rel
+        .literal_to_data((0..10).map(|i| vec![Literal::Integer(i)]).collect())
+        .unwrap();
+    rel.write_tuples(&mut tuples.into_iter(), &mut db_state).unwrap();
+    db_state.shutdown().unwrap();
+
+    let mut db_state = open(DbSettings::default().data_dir(data_dir)).unwrap();
+    let report = check(&mut db_state).unwrap();
+
+    // Remove the index item of one tuple
+    let index = HashIndex::load(info.file_id, &mut db_state).unwrap();
+    let key = bincode::serialize(&3u32).unwrap();
+    let ptrs = index.get(&key, &mut db_state).unwrap();
+    index
+        .remove(&mut vec![(key, ptrs[0])].into_iter(), &mut db_state)
+        .unwrap();
+    let broken_report = check(&mut db_state).unwrap();
+    remove_dir_all(data_dir).unwrap();
+
+    assert!(report.is_ok(), "{:?}", report.problems);
+    assert_eq!(report.tuples, 10);
+    assert_eq!(report.index_items, 10);
+    assert!(report.log_entries > 0);
+    assert_eq!(broken_report.problems.len(), 1);
+    assert!(broken_report.problems[0].contains("has no item"));
+}
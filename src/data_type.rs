@@ -77,6 +77,38 @@ impl DataType {
         }
     }
 
+    /// Reverse of literal_to_data
+    pub fn data_to_literal(&self, bytes: &[u8]) -> Result<Literal> {
+        match self {
+            &DataType::Char | &DataType::VarChar => {
+                Ok(Literal::String(self.data_to_string(bytes)?))
+            }
+            &DataType::U32 => {
+                Ok(Literal::Integer(bincode::deserialize::<u32>(bytes)? as i64))
+            }
+            &DataType::I32 => {
+                Ok(Literal::Integer(bincode::deserialize::<i32>(bytes)? as i64))
+            }
+            &DataType::U64 => {
+                // Literal integers are i64, larger values would wrap
+                let int = bincode::deserialize::<u64>(bytes)?;
+                if int > i64::MAX as u64 {
+                    return Err(Error::Internal(format!(
+                        "{} does not fit in an integer literal",
+                        int
+                    )));
+                }
+                Ok(Literal::Integer(int as i64))
+            }
+            &DataType::I64 => {
+                Ok(Literal::Integer(bincode::deserialize::<i64>(bytes)?))
+            }
+            &DataType::Bool => {
+                Ok(Literal::Integer(bincode::deserialize::<u8>(bytes)? as i64))
+            }
+        }
+    }
+
     pub fn data_size(&self, bytes: Option<&[u8]>) -> Result<usize> {
         match (self, bytes) {
             (&DataType::Char, _) => Ok(1),
@@ -130,6 +162,15 @@ impl DataType {
         }
     }
 
+    /// Value of a column added to existing tuples without a DEFAULT
+    pub fn default_data(&self) -> Result<Vec<u8>> {
+        match self {
+            DataType::Char => Ok(b" ".to_vec()),
+            DataType::VarChar => Ok(bincode::serialize("")?),
+            _ => self.string_to_data("0"),
+        }
+    }
+
     pub fn data_to_string(&self, bytes: &[u8]) -> Result<String> {
         match self {
             &DataType::Char => match String::from_utf8(bytes.to_vec()) {
@@ -1,8 +1,10 @@
 use error::{Error, Result};
-use log::LogMgr;
-use meta::Meta;
+use log::{LogMgr, LOG_META_KEY};
+use meta::{Meta, META_BUF_KEY};
+use metrics;
 use serde::{Deserialize, Serialize};
-use storage::BufMgr;
+use storage::buf_page::PAGE_FORMAT_VERSION;
+use storage::{BufMgr, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE, MIN_PAGE_SIZE};
 
 #[derive(Clone, Debug)]
 pub struct DbState {
@@ -15,22 +17,45 @@ pub struct DbState {
 impl DbState {
     pub fn start_db(settings: DbSettings) -> Result<DbState> {
         let data_dir = settings.get_data_dir();
+        DbState::check_page_size(&settings)?;
         DbState::create_data_dir(data_dir)?;
 
         dbg_log!("Starting SueQL database");
         let mut buf_mgr = BufMgr::new(settings.clone());
         let log_mgr = LogMgr::create_and_load(&mut buf_mgr)?;
+        buf_mgr.set_log_mgr(&log_mgr);
         let meta = Meta::create_and_load(&mut buf_mgr)?;
-        meta.set_state(State::Up)?;
-
-        buf_mgr.start_persist(&meta, &log_mgr)?;
-
-        Ok(DbState {
+        let mut db_state = DbState {
             buf_mgr,
             log_mgr,
             meta,
             settings,
-        })
+        };
+        // Redoing some ops needs the whole DbState, e.g. DROP TABLE
+        db_state.log_mgr.clone().recover(&mut db_state)?;
+        db_state.meta.set_state(State::Up)?;
+
+        let (meta, log_mgr) =
+            (db_state.meta.clone(), db_state.log_mgr.clone());
+        db_state.buf_mgr.start_persist(&meta, &log_mgr)?;
+        if let Some(port) = db_state.settings.metrics_port {
+            metrics::serve(port, db_state.buf_mgr.clone())?;
+        }
+
+        Ok(db_state)
+    }
+
+    /// A handle for one statement, its temp rels and temp page limit are
+    /// not shared with other statements, see TempSpace
+    pub fn for_query(&self) -> DbState {
+        let mut db_state = self.clone();
+        db_state.buf_mgr.start_query();
+        db_state
+    }
+
+    /// Make the log entries written so far durable, see SyncMode
+    pub fn commit(&mut self) -> Result<()> {
+        self.log_mgr.commit(&self.buf_mgr)
     }
 
     pub fn shutdown(&mut self) -> Result<()> {
@@ -38,12 +63,68 @@ impl DbState {
         self.meta.set_state(State::Down)?;
         // Persist one last time
         // NOTE: might be slow and extra here if BufMgr is already persisting
+        self.meta.persist_counters()?;
         self.buf_mgr.persist()?;
+        self.debug_assert_no_pins();
         Ok(())
     }
 
+    /// Only the meta pages are pinned for the lifetime of the db, the
+    /// current log page is skipped since the persist thread pins it
+    fn debug_assert_no_pins(&self) {
+        if cfg!(debug_assertions) {
+            let exempt =
+                [META_BUF_KEY, LOG_META_KEY, self.log_mgr.cur_page_key()];
+            let leaked: Vec<_> = self
+                .buf_mgr
+                .pinned_keys()
+                .into_iter()
+                .filter(|key| !exempt.contains(key))
+                .collect();
+            debug_assert!(leaked.is_empty(), "Leaked pins: {:?}", leaked);
+        }
+    }
+
+    /// Page sizes are powers of two, a database keeps the page size it
+    /// was created with
+    /// The page size of settings must be valid and match the database
+    pub fn check_page_size(settings: &DbSettings) -> Result<()> {
+        let page_size = settings.get_page_size();
+        if !page_size.is_power_of_two()
+            || !(MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size)
+        {
+            return Err(Error::Internal(format!(
+                "Page size must be a power of two from {} to {}",
+                MIN_PAGE_SIZE, MAX_PAGE_SIZE
+            )));
+        }
+        let data_dir = settings.get_data_dir();
+        // Meta pages are written whole, so this is not a torn write
+        let on_disk = match Meta::page_size_on_disk(&data_dir) {
+            Err(Error::CorruptedPage(_)) => {
+                return Err(Error::Internal(format!(
+                    "The meta page of {} is corrupted or older than page \
+                     format version {}",
+                    data_dir, PAGE_FORMAT_VERSION
+                )));
+            }
+            result => result?,
+        };
+        match on_disk {
+            Some(existing)
+                if settings.page_size.is_some() && existing != page_size =>
+            {
+                Err(Error::Internal(format!(
+                    "Database was created with page size {}",
+                    existing
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
     fn create_data_dir<S: Into<String>>(data_dir: S) -> Result<()> {
-        use std::fs::create_dir;
+        use std::fs::{create_dir, remove_dir_all};
         use std::io::ErrorKind;
 
         let data_dir = data_dir.into();
@@ -57,15 +138,17 @@ impl DbState {
                 }
             },
         };
-        match create_dir(temp_dir) {
+        // Temp rels of the last run were not dropped if it crashed
+        match remove_dir_all(&temp_dir) {
             Ok(_) => {}
             Err(e) => match e.kind() {
-                ErrorKind::AlreadyExists => {}
+                ErrorKind::NotFound => {}
                 _ => {
                     return Err(Error::from(e));
                 }
             },
         };
+        create_dir(temp_dir)?;
 
         Ok(())
     }
@@ -75,6 +158,20 @@ impl DbState {
 pub struct DbSettings {
     pub buf_mgr_size: Option<usize>,
     pub data_dir: Option<String>,
+    pub sync_mode: Option<SyncMode>,
+    /// Log segments are copied here before they are removed
+    pub archive_dir: Option<String>,
+    pub replacement: Option<Replacement>,
+    /// Local port of the Prometheus metrics endpoint, see metrics::serve
+    pub metrics_port: Option<u16>,
+    /// Pages scans load ahead of the caller, see BufMgr::read_ahead
+    pub read_ahead: Option<usize>,
+    /// Open data files with O_DIRECT so pages are not cached twice
+    pub direct_io: Option<bool>,
+    /// Only used when the database is created
+    pub page_size: Option<usize>,
+    /// Max pages of temp rels a query can create
+    pub temp_limit: Option<u64>,
 }
 
 impl DbSettings {
@@ -82,6 +179,14 @@ impl DbSettings {
         DbSettings {
             buf_mgr_size: None,
             data_dir: None,
+            sync_mode: None,
+            archive_dir: None,
+            replacement: None,
+            metrics_port: None,
+            read_ahead: None,
+            direct_io: None,
+            page_size: None,
+            temp_limit: None,
         }
     }
 
@@ -93,9 +198,92 @@ impl DbSettings {
         self
     }
 
+    pub fn sync_mode(mut self, mode: SyncMode) -> DbSettings {
+        self.sync_mode = Some(mode);
+        self
+    }
+
+    pub fn archive_dir<S>(mut self, dir: S) -> DbSettings
+    where
+        S: Into<String>,
+    {
+        self.archive_dir = Some(dir.into());
+        self
+    }
+
+    pub fn replacement(mut self, replacement: Replacement) -> DbSettings {
+        self.replacement = Some(replacement);
+        self
+    }
+
+    pub fn metrics_port(mut self, port: u16) -> DbSettings {
+        self.metrics_port = Some(port);
+        self
+    }
+
+    pub fn read_ahead(mut self, pages: usize) -> DbSettings {
+        self.read_ahead = Some(pages);
+        self
+    }
+
+    pub fn direct_io(mut self, direct_io: bool) -> DbSettings {
+        self.direct_io = Some(direct_io);
+        self
+    }
+
+    pub fn page_size(mut self, page_size: usize) -> DbSettings {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    pub fn temp_limit(mut self, pages: u64) -> DbSettings {
+        self.temp_limit = Some(pages);
+        self
+    }
+
     pub fn get_data_dir(&self) -> String {
         self.data_dir.clone().unwrap_or("data".to_string())
     }
+
+    pub fn get_sync_mode(&self) -> SyncMode {
+        self.sync_mode.unwrap_or(SyncMode::Normal)
+    }
+
+    pub fn get_replacement(&self) -> Replacement {
+        self.replacement.unwrap_or(Replacement::Clock)
+    }
+
+    pub fn get_read_ahead(&self) -> usize {
+        self.read_ahead.unwrap_or(16)
+    }
+
+    pub fn get_direct_io(&self) -> bool {
+        self.direct_io.unwrap_or(false)
+    }
+
+    pub fn get_page_size(&self) -> usize {
+        self.page_size.unwrap_or(DEFAULT_PAGE_SIZE)
+    }
+}
+
+/// When files are fsynced
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SyncMode {
+    /// Never fsync, the OS decides when writes reach the disk
+    Off,
+    /// Fsync the log on commit and data files on checkpoints
+    Normal,
+    /// Also fsync the log every time entries are written
+    Full,
+}
+
+/// How BufMgr picks the page to evict, see ReplacementPolicy
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Replacement {
+    Clock,
+    /// LRU-K with the given K
+    LruK(usize),
+    TwoQ,
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
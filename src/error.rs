@@ -1,4 +1,6 @@
 use bincode;
+use storage::BufKey;
+use tuple::TuplePtr;
 
 #[derive(Debug)]
 pub enum Error {
@@ -6,6 +8,10 @@ pub enum Error {
     SerdeError(bincode::Error),
     // TODO Update errors with more debug info
     CorruptedData,
+    /// The page's checksum does not match, e.g. after a torn write
+    CorruptedPage(BufKey),
+    /// The log entry's checksum does not match
+    CorruptedLogEntry(TuplePtr),
     Internal(String),
 }
 
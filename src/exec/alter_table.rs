@@ -0,0 +1,174 @@
+use data_type::DataType;
+use db_state::DbState;
+use error::{Error, Result};
+use exec::{catalog, DataStore, ExecNode};
+use index::IndexType;
+use nom_sql::{ColumnConstraint, ColumnSpecification};
+use rel::Rel;
+use std::sync::Arc;
+use tuple::TupleDesc;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AlterOp {
+    AddColumn(ColumnSpecification),
+    DropColumn(String),
+    RenameColumn(String, String),
+    RenameTable(String),
+}
+
+/// Adding and dropping columns rewrite the whole rel into a new file,
+/// renames only update the meta pages and the table index
+pub struct AlterTable {
+    name: String,
+    rel: Rel,
+    op: AlterOp,
+}
+
+impl AlterTable {
+    pub fn new(name: String, rel: Rel, op: AlterOp) -> AlterTable {
+        AlterTable { name, rel, op }
+    }
+
+    fn add_column(
+        &self,
+        spec: &ColumnSpecification,
+        db_state: &mut DbState,
+    ) -> Result<()> {
+        let desc = self.rel.tuple_desc();
+        let col = spec.column.name.clone();
+        if desc.attr_index(&col).is_some() {
+            return Err(column_exists(&col));
+        }
+        let attr_type = DataType::from_nom_type(spec.sql_type.clone())
+            .ok_or(Error::Internal("Unsupported column type".to_string()))?;
+        let default = spec
+            .constraints
+            .iter()
+            .filter_map(|constraint| match constraint {
+                ColumnConstraint::DefaultValue(literal) => Some(literal),
+                _ => None,
+            })
+            .next();
+        let default_data = match default {
+            Some(literal) => attr_type.literal_to_data(literal)?,
+            None => attr_type.default_data()?,
+        };
+
+        let mut attr_types = desc.attr_types();
+        attr_types.push(attr_type);
+        let mut attr_names = desc.attr_names();
+        attr_names.push(col);
+        self.rel.rewrite(
+            &self.name,
+            TupleDesc::new(attr_types, attr_names),
+            self.index_keys(|key| Some(key.to_vec())),
+            |tup| Ok([tup, &default_data].concat()),
+            db_state,
+        )?;
+        Ok(())
+    }
+
+    fn drop_column(&self, col: &str, db_state: &mut DbState) -> Result<()> {
+        let desc = self.rel.tuple_desc();
+        let idx = desc.attr_index(col).ok_or(no_such_column(col))?;
+        if desc.num_attrs() == 1 {
+            return Err(Error::Internal(
+                "Cannot drop the only column of a table".to_string()));
+        }
+        let keep: Vec<usize> =
+            (0..desc.num_attrs() as usize).filter(|&i| i != idx).collect();
+        // Indices on the dropped column are dropped with it
+        let indices = self.index_keys(|key| {
+            if key.contains(&idx) {
+                None
+            } else {
+                Some(key.iter().map(|&i| if i > idx { i - 1 } else { i })
+                     .collect())
+            }
+        });
+        self.rel.rewrite(
+            &self.name,
+            desc.subset(&keep)?,
+            indices,
+            |tup| desc.data_subset(&tup.to_vec(), &keep),
+            db_state,
+        )?;
+        Ok(())
+    }
+
+    fn rename_column(
+        &self,
+        col: &str,
+        new_col: &str,
+        db_state: &mut DbState,
+    ) -> Result<()> {
+        let desc = self.rel.tuple_desc();
+        let idx = desc.attr_index(col).ok_or(no_such_column(col))?;
+        if desc.attr_index(new_col).is_some() {
+            return Err(column_exists(new_col));
+        }
+        self.rel.rename_attr(idx, new_col, db_state)
+    }
+
+    /// Keys and included columns of the indices to recreate, map_key
+    /// returns None if the index should be dropped. Included columns
+    /// that map to None are dropped from the index.
+    fn index_keys<F>(
+        &self,
+        map_key: F,
+    ) -> Vec<(Vec<usize>, Vec<usize>, IndexType)>
+    where
+        F: Fn(&[usize]) -> Option<Vec<usize>>,
+    {
+        self.rel
+            .indices()
+            .into_iter()
+            .filter_map(|info| {
+                let include = info
+                    .include
+                    .iter()
+                    .filter_map(|col| map_key(&[*col]))
+                    .collect::<Vec<_>>()
+                    .concat();
+                map_key(&info.key).map(|key| (key, include, info.index_type))
+            })
+            .collect()
+    }
+}
+
+impl ExecNode for AlterTable {
+    fn exec(&self, db_state: &mut DbState) -> Result<()> {
+        match &self.op {
+            AlterOp::AddColumn(spec) => self.add_column(spec, db_state),
+            AlterOp::DropColumn(col) => self.drop_column(col, db_state),
+            AlterOp::RenameColumn(col, new_col) => {
+                self.rename_column(col, new_col, db_state)
+            }
+            AlterOp::RenameTable(new_name) => {
+                if catalog::is_catalog(new_name) {
+                    return Err(Error::Internal(format!(
+                        "{} is a reserved table name",
+                        new_name
+                    )));
+                }
+                self.rel.rename(&self.name, new_name, db_state)
+            }
+        }
+    }
+
+    fn inputs(&self) -> Vec<Arc<dyn ExecNode>> {
+        vec![Arc::new(DataStore::Rel(self.rel.clone()))]
+    }
+
+    fn output(&self) -> DataStore {
+        DataStore::Out
+    }
+}
+
+fn column_exists(col: &str) -> Error {
+    Error::Internal(format!("Column {} already exists", col))
+}
+
+fn no_such_column(col: &str) -> Error {
+    Error::Internal(format!("Column {} does not exist", col))
+}
@@ -0,0 +1,34 @@
+use backup;
+use db_state::DbState;
+use error::Result;
+use exec::{DataStore, ExecNode};
+use std::sync::Arc;
+
+pub struct Backup {
+    dir: String,
+}
+
+impl Backup {
+    pub fn new(dir: String) -> Backup {
+        Backup { dir }
+    }
+}
+
+impl ExecNode for Backup {
+    fn exec(&self, db_state: &mut DbState) -> Result<()> {
+        let label = backup::backup(&self.dir, db_state)?;
+        println!(
+            "BACKUP to {}: restores must redo the log to LSN {}",
+            self.dir, label.end_lsn
+        );
+        Ok(())
+    }
+
+    fn inputs(&self) -> Vec<Arc<dyn ExecNode>> {
+        vec![]
+    }
+
+    fn output(&self) -> DataStore {
+        DataStore::Out
+    }
+}
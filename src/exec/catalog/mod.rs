@@ -0,0 +1,144 @@
+use data_type::DataType;
+use db_state::DbState;
+use error::Result;
+use index::IndexType;
+use rel::Rel;
+use storage::BufType;
+use tuple::TupleDesc;
+use utils;
+
+#[cfg(test)]
+mod tests;
+
+pub static TABLES: &str = "sueql_tables";
+pub static COLUMNS: &str = "sueql_columns";
+pub static INDEXES: &str = "sueql_indexes";
+
+/// Catalog rels are not stored, they are built from the table index
+/// and the meta page of each rel into a temp rel when they are read
+pub fn load(name: &str, db_state: &mut DbState) -> Result<Option<Rel>> {
+    let desc = match tuple_desc(name) {
+        Some(desc) => desc,
+        None => return Ok(None),
+    };
+    let mut tuples = vec![];
+    for rel in tables(db_state)?.iter() {
+        tuples.append(&mut rows(name, rel, db_state)?);
+    }
+    let rel = Rel::new_temp_rel(desc.clone(), db_state)?;
+    rel.write_tuples(
+        &mut tuples.into_iter().map(|row| desc.create_tuple_data(row)),
+        db_state,
+    )?;
+    Ok(Some(rel))
+}
+
+pub fn is_catalog(name: &str) -> bool {
+    tuple_desc(name).is_some()
+}
+
+pub fn tuple_desc(name: &str) -> Option<TupleDesc> {
+    if name == TABLES {
+        Some(TupleDesc::new(
+            vec![
+                DataType::U32,
+                DataType::VarChar,
+                DataType::U32,
+                DataType::U32,
+                DataType::U64,
+            ],
+            vec!["table_id", "table_name", "columns", "indexes", "pages"],
+        ))
+    } else if name == COLUMNS {
+        Some(TupleDesc::new(
+            vec![
+                DataType::VarChar,
+                DataType::U32,
+                DataType::VarChar,
+                DataType::VarChar,
+            ],
+            vec!["table_name", "position", "column_name", "data_type"],
+        ))
+    } else if name == INDEXES {
+        Some(TupleDesc::new(
+            vec![
+                DataType::VarChar,
+                DataType::U32,
+                DataType::VarChar,
+                DataType::VarChar,
+                DataType::VarChar,
+            ],
+            vec!["table_name", "index_id", "index_type", "columns", "include"],
+        ))
+    } else {
+        None
+    }
+}
+
+fn tables(db_state: &mut DbState) -> Result<Vec<Rel>> {
+    let mut ids = utils::get_all_table_ids(db_state)?;
+    ids.sort();
+    ids.into_iter()
+        .map(|id| Rel::load(id, BufType::Data, db_state))
+        .collect()
+}
+
+/// Rows of a catalog describing rel, as strings for create_tuple_data
+fn rows(
+    name: &str,
+    rel: &Rel,
+    db_state: &mut DbState,
+) -> Result<Vec<Vec<String>>> {
+    let table_name = rel.name().unwrap_or_default();
+    let desc = rel.tuple_desc();
+    let attr_names = desc.attr_names();
+    if name == TABLES {
+        Ok(vec![vec![
+            rel.rel_id.to_string(),
+            table_name,
+            desc.num_attrs().to_string(),
+            rel.indices().len().to_string(),
+            rel.num_pages(&mut db_state.buf_mgr)?.to_string(),
+        ]])
+    } else if name == COLUMNS {
+        Ok(desc
+            .attr_types()
+            .iter()
+            .zip(attr_names.iter())
+            .enumerate()
+            .map(|(i, (attr_type, attr_name))| {
+                vec![
+                    table_name.clone(),
+                    i.to_string(),
+                    attr_name.clone(),
+                    format!("{:?}", attr_type),
+                ]
+            })
+            .collect())
+    } else if name == INDEXES {
+        Ok(rel
+            .indices()
+            .iter()
+            .map(|info| {
+                let index_type = match info.index_type {
+                    IndexType::Hash => "hash",
+                };
+                let names = |cols: &[usize]| {
+                    cols.iter()
+                        .map(|i| attr_names[*i].clone())
+                        .collect::<Vec<_>>()
+                        .join(",")
+                };
+                vec![
+                    table_name.clone(),
+                    info.file_id.to_string(),
+                    index_type.to_string(),
+                    names(&info.key),
+                    names(&info.include),
+                ]
+            })
+            .collect())
+    } else {
+        Ok(vec![])
+    }
+}
@@ -0,0 +1,71 @@
+use data_type::DataType;
+use exec::{exec_command, Command};
+use db_state::DbState;
+use index::IndexType;
+use rel::Rel;
+use test_utils::{setup, teardown};
+use tuple::TupleDesc;
+
+fn catalog_rows(rel: &Rel, db_state: &mut DbState) -> Vec<Vec<String>> {
+    let mut rows = vec![];
+    rel.scan(db_state, |_| Ok(true), |tup, _| {
+        rows.push(rel.data_to_strings(tup, None)?);
+        Ok(())
+    }).unwrap();
+    rows
+}
+
+#[test]
+fn test_catalogs() {
+    let mut db_state = setup("test_catalogs");
+    let desc = TupleDesc::new(
+        vec![DataType::U32, DataType::VarChar],
+        vec!["id", "name"],
+    );
+    let mut rel = Rel::new("test_catalogs", desc, &mut db_state).unwrap();
+    let info = rel
+        .new_covering_index(vec![1], vec![0], IndexType::Hash, &mut db_state)
+        .unwrap();
+
+    let tables = super::load(super::TABLES, &mut db_state).unwrap().unwrap();
+    let tables = catalog_rows(&tables, &mut db_state);
+    let columns = super::load(super::COLUMNS, &mut db_state).unwrap().unwrap();
+    let columns = catalog_rows(&columns, &mut db_state);
+    let indexes = super::load(super::INDEXES, &mut db_state).unwrap().unwrap();
+    let indexes = catalog_rows(&indexes, &mut db_state);
+    let not_catalog = super::load("test_catalogs", &mut db_state).unwrap();
+    teardown(db_state);
+
+    assert_eq!(tables, vec![vec![
+        rel.rel_id.to_string(),
+        "test_catalogs".to_string(),
+        "2".to_string(),
+        "1".to_string(),
+        "1".to_string(),
+    ]]);
+    assert_eq!(columns, vec![
+        vec!["test_catalogs", "0", "id", "U32"],
+        vec!["test_catalogs", "1", "name", "VarChar"],
+    ]);
+    assert_eq!(indexes, vec![vec![
+        "test_catalogs".to_string(),
+        info.file_id.to_string(),
+        "hash".to_string(),
+        "name".to_string(),
+        "id".to_string(),
+    ]]);
+    assert!(not_catalog.is_none());
+}
+
+#[test]
+fn test_describe_quoted_name() {
+    let mut db_state = setup("test_describe_quoted_name");
+    let desc = TupleDesc::new(vec![DataType::U32], vec!["id"]);
+    Rel::new("it's", desc, &mut db_state).unwrap();
+
+    let cmd = Command::DescribeTable("it's".to_string());
+    let described = exec_command(cmd, &mut db_state);
+    teardown(db_state);
+
+    assert!(described.is_ok());
+}
@@ -0,0 +1,418 @@
+use error::{Error, Result};
+use exec::AlterOp;
+use nom_sql::{self, SelectStatement, SqlQuery};
+
+#[cfg(test)]
+mod tests;
+
+/// Utility statements that are not understood by nom_sql
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Vacuum(Option<String>),
+    Truncate(String),
+    AlterTable(String, AlterOp),
+    /// CREATE INDEX ON table (key columns) [INCLUDE (columns)]
+    CreateIndex(String, Vec<String>, Vec<String>),
+    /// BACKUP TO 'dir'
+    Backup(String),
+    /// SHOW STATS, counters of the buffer pool
+    ShowStats,
+    /// \dt in the REPL
+    ListTables,
+    /// \d table in the REPL
+    DescribeTable(String),
+}
+
+impl Command {
+    /// Returns None if the query is not a utility command,
+    /// so it can be parsed by nom_sql instead
+    pub fn parse(query: &str) -> Option<Result<Command>> {
+        let tokens = tokenize(query);
+        let keyword = match tokens.first() {
+            Some(token) => token.to_lowercase(),
+            None => return None,
+        };
+
+        match keyword.as_str() {
+            "vacuum" => Some(Command::parse_vacuum(&tokens[1..])),
+            "truncate" => Some(Command::parse_truncate(&tokens[1..])),
+            "alter" => Some(Command::parse_alter(&tokens[1..])),
+            "create" => Command::parse_create(&tokens[1..]),
+            "backup" => Some(Command::parse_backup(&tokens[1..])),
+            "show" => Command::parse_show(&tokens[1..]),
+            "\\dt" => Some(match tokens.len() {
+                1 => Ok(Command::ListTables),
+                _ => Err(syntax_error("\\dt")),
+            }),
+            "\\d" => Some(match tokens.len() {
+                2 => Ok(Command::DescribeTable(tokens[1].clone())),
+                _ => Err(syntax_error("\\d table")),
+            }),
+            _ => None,
+        }
+    }
+
+    fn parse_vacuum(tokens: &[String]) -> Result<Command> {
+        match tokens.len() {
+            0 => Ok(Command::Vacuum(None)),
+            1 => Ok(Command::Vacuum(Some(tokens[0].clone()))),
+            _ => Err(syntax_error("VACUUM [table]")),
+        }
+    }
+
+    fn parse_truncate(tokens: &[String]) -> Result<Command> {
+        let tokens = match tokens.first() {
+            Some(token) if token.to_lowercase() == "table" => &tokens[1..],
+            _ => tokens,
+        };
+        match tokens.len() {
+            1 => Ok(Command::Truncate(tokens[0].clone())),
+            _ => Err(syntax_error("TRUNCATE [TABLE] table")),
+        }
+    }
+
+    fn parse_backup(tokens: &[String]) -> Result<Command> {
+        let usage = "BACKUP TO 'dir'";
+        if tokens.len() != 2 || tokens[0].to_lowercase() != "to" {
+            return Err(syntax_error(usage));
+        }
+        let dir = &tokens[1];
+        if dir.len() < 2 || !dir.starts_with('\'') || !dir.ends_with('\'') {
+            return Err(syntax_error(usage));
+        }
+        Ok(Command::Backup(dir[1..dir.len() - 1].to_string()))
+    }
+
+    /// CREATE TABLE is left to nom_sql
+    fn parse_create(tokens: &[String]) -> Option<Result<Command>> {
+        match tokens.first() {
+            Some(token) if token.to_lowercase() == "index" => {
+                Some(Command::parse_create_index(&tokens[1..]))
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_create_index(tokens: &[String]) -> Result<Command> {
+        let usage = "CREATE INDEX ON table (column, ...) \
+                     [INCLUDE (column, ...)]";
+        if tokens.len() < 2 || tokens[0].to_lowercase() != "on" {
+            return Err(syntax_error(usage));
+        }
+        let table = tokens[1].clone();
+        let (key, rest) =
+            parse_columns(&tokens[2..]).ok_or_else(|| syntax_error(usage))?;
+        let include = match rest.first() {
+            None => vec![],
+            Some(token) if token.to_lowercase() == "include" => {
+                match parse_columns(&rest[1..]) {
+                    Some((include, [])) => include,
+                    _ => return Err(syntax_error(usage)),
+                }
+            }
+            _ => return Err(syntax_error(usage)),
+        };
+        Ok(Command::CreateIndex(table, key, include))
+    }
+
+    /// Other SHOW statements are left to nom_sql
+    fn parse_show(tokens: &[String]) -> Option<Result<Command>> {
+        match tokens.first() {
+            Some(token) if token.to_lowercase() == "stats" => {
+                Some(match tokens.len() {
+                    1 => Ok(Command::ShowStats),
+                    _ => Err(syntax_error("SHOW STATS")),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_alter(tokens: &[String]) -> Result<Command> {
+        let usage = "ALTER TABLE table \
+                     ADD [COLUMN] column type [DEFAULT value] \
+                     | DROP [COLUMN] column \
+                     | RENAME [COLUMN] column TO name \
+                     | RENAME TO name";
+        if tokens.len() < 3 || tokens[0].to_lowercase() != "table" {
+            return Err(syntax_error(usage));
+        }
+        let table = tokens[1].clone();
+        let action = tokens[2].to_lowercase();
+        let args = match tokens.get(3) {
+            Some(token) if token.to_lowercase() == "column" => &tokens[4..],
+            _ => &tokens[3..],
+        };
+
+        let op = match (action.as_str(), args.len()) {
+            ("add", len) if len > 0 => {
+                // Let nom_sql parse the column definition
+                let query = format!("CREATE TABLE t ({})", join_tokens(args));
+                match nom_sql::parse_query(&query) {
+                    Ok(SqlQuery::CreateTable(ref stmt))
+                        if stmt.fields.len() == 1 =>
+                    {
+                        AlterOp::AddColumn(stmt.fields[0].clone())
+                    }
+                    _ => return Err(syntax_error(usage)),
+                }
+            }
+            ("drop", 1) => AlterOp::DropColumn(args[0].clone()),
+            ("rename", 2) if args[0].to_lowercase() == "to" => {
+                AlterOp::RenameTable(args[1].clone())
+            }
+            ("rename", 3) if args[1].to_lowercase() == "to" => {
+                AlterOp::RenameColumn(args[0].clone(), args[2].clone())
+            }
+            _ => return Err(syntax_error(usage)),
+        };
+        Ok(Command::AlterTable(table, op))
+    }
+}
+
+/// Split a query into words, parentheses, commas and quoted strings.
+/// Quoted strings keep their quotes so they can be told apart from names.
+fn tokenize(query: &str) -> Vec<String> {
+    token_spans(query)
+        .into_iter()
+        .map(|(start, end)| query[start..end].to_string())
+        .collect()
+}
+
+/// Byte ranges of the tokens of query, semicolons separate tokens
+/// like whitespace
+fn token_spans(query: &str) -> Vec<(usize, usize)> {
+    let mut spans = vec![];
+    let mut start = None;
+    let mut quote = None;
+
+    for (i, c) in query.char_indices() {
+        match quote {
+            Some(q) => {
+                if c == q {
+                    spans.push((start.take().unwrap(), i + 1));
+                    quote = None;
+                }
+            }
+            None => match c {
+                '\'' | '"' => {
+                    if let Some(s) = start.take() {
+                        spans.push((s, i));
+                    }
+                    start = Some(i);
+                    quote = Some(c);
+                }
+                '(' | ')' | ',' => {
+                    if let Some(s) = start.take() {
+                        spans.push((s, i));
+                    }
+                    spans.push((i, i + 1));
+                }
+                ';' => {
+                    if let Some(s) = start.take() {
+                        spans.push((s, i));
+                    }
+                }
+                _ if c.is_whitespace() => {
+                    if let Some(s) = start.take() {
+                        spans.push((s, i));
+                    }
+                }
+                _ => {
+                    start.get_or_insert(i);
+                }
+            },
+        }
+    }
+    if let Some(s) = start {
+        spans.push((s, query.len()));
+    }
+    spans
+}
+
+/// Rewrite the conditions of WHERE clauses nom_sql does not parse into
+/// ones it does: x [NOT] BETWEEN low AND high and x NOT LIKE pattern.
+/// The operands must be single columns or literals, anything else is
+/// an error rather than a rewrite that changes what the query means
+pub fn rewrite_conditions(query: &str) -> Result<String> {
+    let spans = token_spans(query);
+    let text = |i: usize| &query[spans[i].0..spans[i].1];
+    let is_word = |i: usize, word: &str| {
+        i < spans.len() && text(i).eq_ignore_ascii_case(word)
+    };
+    let is_operand = |i: usize| i < spans.len() && is_operand(text(i));
+    // An operand is complete if it is not part of an arithmetic
+    // expression, a function call or parentheses
+    let starts_operand = |i: usize| {
+        ["where", "and", "or", "not", "("]
+            .iter()
+            .any(|word| is_word(i - 1, word))
+    };
+    let ends_operand = |i: usize| {
+        i + 1 == spans.len()
+            || ["and", "or", ")", "order", "group", "limit"]
+                .iter()
+                .any(|word| is_word(i + 1, word))
+    };
+    let mut rewritten = String::new();
+    // End of the part of query copied to rewritten
+    let mut copied = 0;
+    // Open SELECT statements and WHERE clauses with the paren depth they
+    // start at, and whether they are a WHERE clause
+    let mut clauses: Vec<(usize, bool)> = vec![];
+    let mut depth = 0;
+    let mut i = 0;
+    while i < spans.len() {
+        if is_word(i, "(") {
+            depth += 1;
+        } else if is_word(i, ")") {
+            depth = depth.max(1) - 1;
+            while clauses.last().is_some_and(|clause| clause.0 > depth) {
+                clauses.pop();
+            }
+        } else if is_word(i, "select") || is_word(i, "where") {
+            clauses.push((depth, is_word(i, "where")));
+            i += 1;
+            continue;
+        } else if ["order", "group", "limit"].iter().any(|w| is_word(i, w)) {
+            if let Some(clause) = clauses.last_mut() {
+                if clause.0 == depth {
+                    clause.1 = false;
+                }
+            }
+        }
+        if !clauses.last().is_some_and(|clause| clause.1) {
+            i += 1;
+            continue;
+        }
+        let negated = is_word(i + 1, "not");
+        let op = if negated { i + 2 } else { i + 1 };
+        let cond = if is_word(op, "between") {
+            let valid = is_operand(i)
+                && starts_operand(i)
+                && is_operand(op + 1)
+                && is_word(op + 2, "and")
+                && is_operand(op + 3)
+                && ends_operand(op + 3);
+            if !valid {
+                return Err(Error::internal(
+                    "BETWEEN operands must be columns or literals",
+                ));
+            }
+            let (x, low, high) = (text(i), text(op + 1), text(op + 3));
+            let cond = if negated {
+                format!("({} < {} OR {} > {})", x, low, x, high)
+            } else {
+                format!("({} >= {} AND {} <= {})", x, low, x, high)
+            };
+            Some((cond, op + 3))
+        } else if negated && is_word(op, "like") {
+            let valid = is_operand(i)
+                && starts_operand(i)
+                && is_operand(op + 1)
+                && ends_operand(op + 1);
+            if !valid {
+                return Err(Error::internal(
+                    "NOT LIKE operands must be columns or literals",
+                ));
+            }
+            let cond = format!("NOT ({} LIKE {})", text(i), text(op + 1));
+            Some((cond, op + 1))
+        } else {
+            None
+        };
+        match cond {
+            Some((cond, end)) => {
+                rewritten.push_str(&query[copied..spans[i].0]);
+                rewritten.push_str(&cond);
+                copied = spans[end].1;
+                i = end + 1;
+            }
+            None => i += 1,
+        }
+    }
+    rewritten.push_str(&query[copied..]);
+    Ok(rewritten)
+}
+
+/// A column name or a literal, not a keyword or punctuation
+fn is_operand(token: &str) -> bool {
+    let keywords = ["and", "or", "not", "between", "like", "in", "select"];
+    if keywords.iter().any(|word| token.eq_ignore_ascii_case(word)) {
+        return false;
+    }
+    token.starts_with('\'')
+        || token.starts_with('"')
+        || token.chars().all(|c| {
+            c.is_alphanumeric() || c == '_' || c == '.' || c == '-'
+        })
+}
+
+/// Parse a SELECT without FROM as a select from no tables, nom_sql
+/// requires a FROM so the statement is parsed with a placeholder one
+pub fn parse_select_without_from(query: &str) -> Option<SelectStatement> {
+    let tokens = tokenize(query);
+    if !tokens.first()?.eq_ignore_ascii_case("select") {
+        return None;
+    }
+    let query = format!("{} FROM dual;", query.trim().trim_end_matches(';'));
+    match nom_sql::parse_query(query) {
+        Ok(SqlQuery::Select(mut stmt)) => {
+            let only_fields = stmt.tables.len() == 1
+                && stmt.join.is_empty()
+                && stmt.where_clause.is_none()
+                && stmt.group_by.is_none()
+                && stmt.order.is_none()
+                && stmt.limit.is_none();
+            if !only_fields {
+                return None;
+            }
+            stmt.tables.clear();
+            Some(stmt)
+        }
+        _ => None,
+    }
+}
+
+/// A parenthesized, comma separated list of names and the tokens after it
+fn parse_columns(tokens: &[String]) -> Option<(Vec<String>, &[String])> {
+    if tokens.first()? != "(" {
+        return None;
+    }
+    let end = tokens.iter().position(|token| token == ")")?;
+    let names = &tokens[1..end];
+    let mut cols = vec![];
+    for (i, token) in names.iter().enumerate() {
+        match (i % 2, token.as_str()) {
+            (0, "(") | (0, ",") => return None,
+            (0, _) => cols.push(token.clone()),
+            (_, ",") => {}
+            _ => return None,
+        }
+    }
+    if cols.is_empty() || names.last()? == "," {
+        return None;
+    }
+    Some((cols, &tokens[end + 1..]))
+}
+
+/// Reverse of tokenize, without spaces around parentheses and commas
+fn join_tokens(tokens: &[String]) -> String {
+    let mut query = String::new();
+    for token in tokens.iter() {
+        let glued = query.is_empty()
+            || query.ends_with('(')
+            || token == "("
+            || token == ")"
+            || token == ",";
+        if !glued {
+            query.push(' ');
+        }
+        query.push_str(token);
+    }
+    query
+}
+
+fn syntax_error(usage: &str) -> Error {
+    Error::Internal(format!("Invalid syntax, expected: {}", usage))
+}
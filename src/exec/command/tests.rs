@@ -0,0 +1,197 @@
+use super::{
+    parse_select_without_from, rewrite_conditions, tokenize, Command,
+};
+
+#[test]
+fn test_tokenize() {
+    assert_eq!(
+        tokenize("select 'a b' from t;"),
+        vec!["select", "'a b'", "from", "t"]
+    );
+    assert_eq!(
+        tokenize("index (a,b)"),
+        vec!["index", "(", "a", ",", "b", ")"]
+    );
+}
+
+#[test]
+fn test_parse_vacuum() {
+    assert_eq!(
+        Command::parse("VACUUM;").unwrap().unwrap(),
+        Command::Vacuum(None)
+    );
+    assert_eq!(
+        Command::parse("vacuum test").unwrap().unwrap(),
+        Command::Vacuum(Some("test".to_string()))
+    );
+    assert!(Command::parse("vacuum a b").unwrap().is_err());
+    assert!(Command::parse("select * from test;").is_none());
+}
+
+#[test]
+fn test_parse_truncate() {
+    assert_eq!(
+        Command::parse("TRUNCATE TABLE test;").unwrap().unwrap(),
+        Command::Truncate("test".to_string())
+    );
+    assert_eq!(
+        Command::parse("truncate test").unwrap().unwrap(),
+        Command::Truncate("test".to_string())
+    );
+    assert!(Command::parse("truncate").unwrap().is_err());
+}
+
+#[test]
+fn test_parse_alter() {
+    use exec::AlterOp;
+
+    let query = "ALTER TABLE test ADD COLUMN c varchar(10) DEFAULT 'a';";
+    match Command::parse(query).unwrap().unwrap() {
+        Command::AlterTable(table, AlterOp::AddColumn(spec)) => {
+            assert_eq!(table, "test");
+            assert_eq!(spec.column.name, "c");
+            assert_eq!(spec.constraints.len(), 1);
+        }
+        cmd => panic!("Unexpected command {:?}", cmd),
+    }
+    assert_eq!(
+        Command::parse("alter table test drop c").unwrap().unwrap(),
+        Command::AlterTable(
+            "test".to_string(), AlterOp::DropColumn("c".to_string()))
+    );
+    assert_eq!(
+        Command::parse("alter table test rename column a to b")
+            .unwrap()
+            .unwrap(),
+        Command::AlterTable(
+            "test".to_string(),
+            AlterOp::RenameColumn("a".to_string(), "b".to_string()))
+    );
+    assert_eq!(
+        Command::parse("alter table test rename to other").unwrap().unwrap(),
+        Command::AlterTable(
+            "test".to_string(), AlterOp::RenameTable("other".to_string()))
+    );
+    assert!(Command::parse("alter table test add").unwrap().is_err());
+    assert!(Command::parse("alter test drop c").unwrap().is_err());
+}
+
+#[test]
+fn test_parse_shortcuts() {
+    assert_eq!(Command::parse("\\dt").unwrap().unwrap(), Command::ListTables);
+    assert_eq!(
+        Command::parse("\\d test").unwrap().unwrap(),
+        Command::DescribeTable("test".to_string())
+    );
+    assert!(Command::parse("\\d").unwrap().is_err());
+}
+
+#[test]
+fn test_parse_backup() {
+    assert_eq!(
+        Command::parse("BACKUP TO 'backups/today';").unwrap().unwrap(),
+        Command::Backup("backups/today".to_string())
+    );
+    assert!(Command::parse("backup 'dir'").unwrap().is_err());
+    assert!(Command::parse("backup to dir").unwrap().is_err());
+}
+
+#[test]
+fn test_parse_show_stats() {
+    assert_eq!(
+        Command::parse("SHOW STATS;").unwrap().unwrap(),
+        Command::ShowStats
+    );
+    assert!(Command::parse("show stats now").unwrap().is_err());
+    assert!(Command::parse("SHOW TABLES;").is_none());
+}
+
+#[test]
+fn test_parse_create_index() {
+    let cols = |names: &[&str]| {
+        names.iter().map(|name| name.to_string()).collect::<Vec<_>>()
+    };
+    assert_eq!(
+        Command::parse("CREATE INDEX ON test (a);").unwrap().unwrap(),
+        Command::CreateIndex("test".to_string(), cols(&["a"]), vec![])
+    );
+    assert_eq!(
+        Command::parse("create index on test (a) include (b, c)")
+            .unwrap()
+            .unwrap(),
+        Command::CreateIndex(
+            "test".to_string(), cols(&["a"]), cols(&["b", "c"]))
+    );
+    assert!(Command::parse("create index on test ()").unwrap().is_err());
+    assert!(Command::parse("create index on test (a,)").unwrap().is_err());
+    assert!(Command::parse("create index on test (a) b").unwrap().is_err());
+    assert!(Command::parse("create table test (a int)").is_none());
+}
+
+#[test]
+fn test_rewrite_conditions() {
+    let rewrite = |query: &str| rewrite_conditions(query).unwrap();
+    assert_eq!(
+        rewrite("select * from t where a BETWEEN 1 AND 5;"),
+        "select * from t where (a >= 1 AND a <= 5);"
+    );
+    assert_eq!(
+        rewrite("select * from t where a not between 'x' and b"),
+        "select * from t where (a < 'x' OR a > b)"
+    );
+    assert_eq!(
+        rewrite("select * from t where (a between -1 and 1) and b = 2"),
+        "select * from t where ((a >= -1 AND a <= 1)) and b = 2"
+    );
+    assert_eq!(
+        rewrite("select * from t where s NOT LIKE 'not like'"),
+        "select * from t where NOT (s LIKE 'not like')"
+    );
+    // NOT IN is parsed by nom_sql
+    let query = "select * from t where a not in (1, 2)";
+    assert_eq!(rewrite(query), query);
+    // Only WHERE clauses are rewritten
+    let query = "insert into t values ('a between 1 and 2')";
+    assert_eq!(rewrite(query), query);
+    let query = "select * from t where a = 1 order by b not like c";
+    assert_eq!(rewrite(query), query);
+    // Subqueries have their own WHERE clauses
+    assert_eq!(
+        rewrite("select * from t where a in (select b from u \
+                 where b between 1 and 2) and c between 3 and 4"),
+        "select * from t where a in (select b from u \
+                 where (b >= 1 AND b <= 2)) and (c >= 3 AND c <= 4)"
+    );
+    let query = "select x from (select a from t where a = 1) \
+                 as s order by a not like b";
+    assert_eq!(rewrite(query), query);
+
+    // Expression and parenthesized operands are not split
+    for clause in [
+        "a + 1 between 2 and 3",
+        "a between 1 and 2 + 1",
+        "(a) between 1 and 2",
+        "a between f(x) and 3",
+        "a between 1 and (3)",
+        "a between 1 or 2",
+        "a + 1 not like 'x'",
+    ].iter() {
+        let query = format!("select * from t where {}", clause);
+        assert!(rewrite_conditions(&query).is_err(), "{}", clause);
+    }
+}
+
+#[test]
+fn test_parse_select_without_from() {
+    let stmt = parse_select_without_from("SELECT 1 + 1;").unwrap();
+    assert!(stmt.tables.is_empty());
+    assert_eq!(stmt.fields.len(), 1);
+    let stmt = parse_select_without_from("select 'from' as x, 2").unwrap();
+    assert!(stmt.tables.is_empty());
+    assert_eq!(stmt.fields.len(), 2);
+
+    assert!(parse_select_without_from("select a where a = 1").is_none());
+    assert!(parse_select_without_from("select a from").is_none());
+    assert!(parse_select_without_from("select a from t x").is_none());
+    assert!(parse_select_without_from("vacuum").is_none());
+}
@@ -0,0 +1,59 @@
+use db_state::DbState;
+use error::{Error, Result};
+use exec::{DataStore, ExecNode};
+use index::IndexType;
+use rel::Rel;
+use std::sync::Arc;
+
+/// Add an index to a rel, the include columns are stored in the index
+/// so queries only using them and the key do not read the rel
+pub struct CreateIndex {
+    rel: Rel,
+    key: Vec<String>,
+    include: Vec<String>,
+}
+
+impl CreateIndex {
+    pub fn new(rel: Rel, key: Vec<String>, include: Vec<String>) -> Self {
+        CreateIndex { rel, key, include }
+    }
+
+    fn col_indices(&self, rel: &Rel, cols: &[String]) -> Result<Vec<usize>> {
+        let desc = rel.tuple_desc();
+        cols.iter()
+            .map(|col| {
+                desc.attr_index(col).ok_or_else(|| {
+                    Error::Internal(format!("Column {} does not exist", col))
+                })
+            })
+            .collect()
+    }
+}
+
+impl ExecNode for CreateIndex {
+    fn exec(&self, db_state: &mut DbState) -> Result<()> {
+        // The index list is stored in the meta page of the rel
+        let mut rel = self.rel.clone();
+        let key = self.col_indices(&rel, &self.key)?;
+        let include = self.col_indices(&rel, &self.include)?;
+        let mut cols = [key.clone(), include.clone()].concat();
+        cols.sort();
+        cols.dedup();
+        if key.is_empty() || cols.len() != key.len() + include.len() {
+            return Err(Error::Internal(
+                "Index columns must be distinct and the key not empty"
+                    .to_string(),
+            ));
+        }
+        rel.new_covering_index(key, include, IndexType::Hash, db_state)?;
+        Ok(())
+    }
+
+    fn inputs(&self) -> Vec<Arc<dyn ExecNode>> {
+        vec![Arc::new(DataStore::Rel(self.rel.clone()))]
+    }
+
+    fn output(&self) -> DataStore {
+        DataStore::Out
+    }
+}
@@ -1,7 +1,7 @@
 use data_type::DataType;
 use db_state::DbState;
 use error::{Error, Result};
-use exec::{DataStore, ExecNode};
+use exec::{catalog, DataStore, ExecNode};
 use index::IndexType;
 use nom_sql::{Column, CreateTableStatement, TableKey};
 use rel::Rel;
@@ -36,6 +36,12 @@ impl CreateTable {
 
 impl ExecNode for CreateTable {
     fn exec(&self, db_state: &mut DbState) -> Result<()> {
+        if catalog::is_catalog(&self.stmt.table.name) {
+            return Err(Error::Internal(format!(
+                "{} is a reserved table name",
+                self.stmt.table.name
+            )));
+        }
         let attr_types: Vec<DataType> = self
             .stmt
             .fields
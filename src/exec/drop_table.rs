@@ -0,0 +1,37 @@
+use db_state::DbState;
+use error::Result;
+use exec::{DataStore, ExecNode};
+use rel::Rel;
+use std::sync::Arc;
+
+pub struct DropTable {
+    rels: Vec<(String, Rel)>,
+}
+
+impl DropTable {
+    pub fn new(rels: Vec<(String, Rel)>) -> DropTable {
+        DropTable { rels }
+    }
+}
+
+impl ExecNode for DropTable {
+    fn exec(&self, db_state: &mut DbState) -> Result<()> {
+        for (name, rel) in self.rels.iter() {
+            rel.drop_table(name.clone(), db_state)?;
+        }
+        Ok(())
+    }
+
+    fn inputs(&self) -> Vec<Arc<dyn ExecNode>> {
+        self.rels
+            .iter()
+            .map(|(_, rel)| {
+                Arc::new(DataStore::Rel(rel.clone())) as Arc<dyn ExecNode>
+            })
+            .collect()
+    }
+
+    fn output(&self) -> DataStore {
+        DataStore::Out
+    }
+}
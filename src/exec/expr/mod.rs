@@ -98,7 +98,8 @@ pub struct Expr {
 }
 
 impl Expr {
-    pub fn from_nom<E>(nom: E, rel: &Rel) -> Result<Expr>
+    /// Expression over tuples of desc
+    pub fn from_nom<E>(nom: E, desc: &TupleDesc) -> Result<Expr>
     where
         E: Into<NomExpr>,
     {
@@ -107,8 +108,16 @@ impl Expr {
 
         match nom {
             NomExpr::ConditionTree(expr) => {
-                let left = Expr::from_nom((*expr.left).clone(), rel)?;
-                let right = Expr::from_nom((*expr.right).clone(), rel)?;
+                let left = Expr::from_nom((*expr.left).clone(), desc)?;
+                if let Operator::In = expr.operator {
+                    return Expr::in_list(left, &expr.right);
+                }
+                let right = Expr::from_nom((*expr.right).clone(), desc)?;
+                match expr.operator {
+                    Operator::And => return Expr::logical(left, right, true),
+                    Operator::Or => return Expr::logical(left, right, false),
+                    _ => {}
+                }
                 let (left, right) = Expr::try_match_type(left, right)?;
                 match expr.operator {
                     Operator::Equal => {
@@ -118,38 +127,55 @@ impl Expr {
                         let eq_expr = cmp_expr!(left, right, Ordering::Equal);
                         eq_expr.negate()
                     }
+                    Operator::Less => {
+                        Ok(cmp_expr!(left, right, Ordering::Less))
+                    }
+                    Operator::Greater => {
+                        Ok(cmp_expr!(left, right, Ordering::Greater))
+                    }
+                    Operator::LessOrEqual => {
+                        cmp_expr!(left, right, Ordering::Greater).negate()
+                    }
+                    Operator::GreaterOrEqual => {
+                        cmp_expr!(left, right, Ordering::Less).negate()
+                    }
+                    Operator::Like => Expr::like(left, right),
+                    Operator::NotLike => Expr::like(left, right)?.negate(),
                     _ => Err(not_impl),
                 }
             }
 
             NomExpr::ConditionExpression(expr) => match expr {
                 ConditionExpression::ComparisonOp(expr) => {
-                    Expr::from_nom(expr, rel)
+                    Expr::from_nom(expr, desc)
                 }
                 ConditionExpression::LogicalOp(expr) => {
-                    Expr::from_nom(expr, rel)
+                    Expr::from_nom(expr, desc)
                 }
                 ConditionExpression::NegationOp(expr) => {
-                    Expr::from_nom((*expr).clone(), rel)
+                    Expr::from_nom((*expr).clone(), desc)?.negate()
                 }
-                ConditionExpression::Base(expr) => Expr::from_nom(expr, rel),
+                ConditionExpression::Base(expr) => Expr::from_nom(expr, desc),
                 ConditionExpression::Arithmetic(expr) => {
-                    Expr::from_nom((*expr).clone(), rel)
+                    Expr::from_nom((*expr).clone(), desc)
                 }
                 ConditionExpression::Bracketed(expr) => {
-                    Expr::from_nom((*expr).clone(), rel)
+                    Expr::from_nom((*expr).clone(), desc)
                 }
             }
 
             NomExpr::ConditionBase(expr) => match expr {
-                ConditionBase::Field(col) => Expr::from_col(col, rel.tuple_desc()),
+                ConditionBase::Field(col) => Expr::from_col(col, desc.clone()),
                 ConditionBase::Literal(literal) => Expr::from_literal(literal),
+                ConditionBase::NestedSelect(_) => Err(Error::internal(
+                    "Subqueries are replaced by their values when planning",
+                )),
                 _ => Err(not_impl),
             }
 
             NomExpr::ArithmeticExpression(expr) => {
-                let left = Expr::from_nom(expr.left.clone(), rel)?;
-                let right = Expr::from_nom(expr.right.clone(), rel)?;
+                let left = Expr::from_nom(expr.left.clone(), desc)?;
+                let right = Expr::from_nom(expr.right.clone(), desc)?;
                 if !left.output_type.is_numerical()
                     || !right.output_type.is_numerical()
                 {
@@ -173,7 +199,9 @@ impl Expr {
             }
 
             NomExpr::ArithmeticBase(expr) => match expr {
-                ArithmeticBase::Column(col) => Expr::from_col(col, rel.tuple_desc()),
+                ArithmeticBase::Column(col) => {
+                    Expr::from_col(col, desc.clone())
+                }
                 ArithmeticBase::Scalar(literal) => Expr::from_literal(literal),
             }
 
@@ -310,6 +338,78 @@ impl Expr {
         }
     }
 
+    /// Whether left is one of the values of an IN list, the right side
+    /// of NOT IN is the negated list
+    fn in_list(left: Expr, right: &ConditionExpression) -> Result<Expr> {
+        let (list, negated) = match *right {
+            ConditionExpression::Base(ConditionBase::LiteralList(ref list)) => {
+                (list, false)
+            }
+            ConditionExpression::NegationOp(ref expr) => match **expr {
+                ConditionExpression::Base(ConditionBase::LiteralList(
+                    ref list,
+                )) => (list, true),
+                _ => return Err(Error::internal("Invalid IN list")),
+            },
+            _ => return Err(Error::internal("Invalid IN list")),
+        };
+        let values = list
+            .iter()
+            .map(|literal| left.output_type.literal_to_data(literal))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Expr {
+            output_type: DataType::Bool,
+            function: Box::new(move |bytes| {
+                let value = (left.function)(bytes)?;
+                let found = values.contains(&value);
+                Ok(bincode::serialize(&(found != negated))?)
+            }),
+        })
+    }
+
+    /// LIKE, % matches any string and _ any single character
+    fn like(left: Expr, right: Expr) -> Result<Expr> {
+        let left = left.cast(DataType::VarChar)?;
+        let right = right.cast(DataType::VarChar)?;
+        Ok(Expr {
+            output_type: DataType::Bool,
+            function: Box::new(move |bytes| {
+                let string: String =
+                    bincode::deserialize(&(left.function)(bytes)?)?;
+                let pattern: String =
+                    bincode::deserialize(&(right.function)(bytes)?)?;
+                let chars = string.chars().collect::<Vec<_>>();
+                let pattern = pattern.chars().collect::<Vec<_>>();
+                Ok(bincode::serialize(&like_match(&chars, &pattern))?)
+            }),
+        })
+    }
+
+    /// AND if is_and, else OR. The right side is only evaluated if the
+    /// left side does not decide the result.
+    fn logical(left: Expr, right: Expr, is_and: bool) -> Result<Expr> {
+        if left.output_type != DataType::Bool
+            || right.output_type != DataType::Bool
+        {
+            return Err(Error::internal(format!(
+                "Cannot do logical operation with types {:?} and {:?}",
+                left.output_type, right.output_type
+            )));
+        }
+        Ok(Expr {
+            output_type: DataType::Bool,
+            function: Box::new(move |bytes| {
+                let value: bool =
+                    bincode::deserialize(&(left.function)(bytes)?)?;
+                if value != is_and {
+                    Ok(bincode::serialize(&value)?)
+                } else {
+                    (right.function)(bytes)
+                }
+            }),
+        })
+    }
+
     fn try_match_type(left: Expr, right: Expr) -> Result<(Expr, Expr)> {
         if left.output_type == right.output_type {
             Ok((left, right))
@@ -396,6 +496,23 @@ impl Expr {
     }
 }
 
+fn like_match(string: &[char], pattern: &[char]) -> bool {
+    match pattern.split_first() {
+        None => string.is_empty(),
+        Some((&'%', rest)) => {
+            // Runs of % match the same strings as a single one
+            let rest = &rest[rest.iter().take_while(|&&c| c == '%').count()..];
+            (0..=string.len()).any(|i| like_match(&string[i..], rest))
+        }
+        Some((&c, rest)) => match string.split_first() {
+            Some((&s, string)) if c == '_' || c == s => {
+                like_match(string, rest)
+            }
+            _ => false,
+        },
+    }
+}
+
 #[derive(Debug)]
 pub enum NomExpr {
     ConditionTree(ConditionTree),
@@ -1,6 +1,7 @@
 use bincode;
 use data_type::DataType;
-use super::Expr;
+use exec::command::rewrite_conditions;
+use super::{like_match, Expr};
 use nom_sql;
 use tuple::TupleDesc;
 use test_utils::{setup, teardown};
@@ -95,6 +96,93 @@ fn test_select_col() {
     );
 }
 
+#[test]
+fn test_logical_ops() {
+    let desc = TupleDesc::new(
+        vec![DataType::I32, DataType::I32],
+        vec!["a", "b"],
+    );
+    let data = [
+        bincode::serialize(&1i32).unwrap(),
+        bincode::serialize(&2i32).unwrap(),
+    ].concat();
+
+    let cases = vec![
+        ("a = 1 and b = 2", true_bytes()),
+        ("a = 1 and b = 3", false_bytes()),
+        ("a = 2 or b = 2", true_bytes()),
+        ("a = 2 or b = 3", false_bytes()),
+        ("(a = 2 or b = 2) and a = 1", true_bytes()),
+    ];
+    for (clause, expect_out) in cases.into_iter() {
+        test_query_output(
+            &format!("select * from test where {}", clause),
+            Some(desc.clone()),
+            &data,
+            DataType::Bool,
+            expect_out,
+        );
+    }
+}
+
+#[test]
+fn test_condition_ops() {
+    let desc = TupleDesc::new(
+        vec![DataType::I32, DataType::VarChar],
+        vec!["a", "s"],
+    );
+    let data = [
+        bincode::serialize(&1i32).unwrap(),
+        bincode::serialize(&String::from("Hello")).unwrap(),
+    ].concat();
+
+    let cases = vec![
+        ("a < 2", true),
+        ("a <= 1", true),
+        ("a > 1", false),
+        ("a >= 2", false),
+        ("s > 'Abc'", true),
+        ("a in (3, 1)", true),
+        ("s in ('World')", false),
+        ("a not in (3, 1)", false),
+        ("s like 'He%'", true),
+        ("s like 'H_llo'", true),
+        ("s like '%x%'", false),
+        ("s not like 'W%'", true),
+        ("not (a = 1)", false),
+        ("a between 0 and 1", true),
+        ("a not between 0 and 1", false),
+    ];
+    for (clause, expect_out) in cases.into_iter() {
+        let query = format!("select * from test where {}", clause);
+        let expect_out = if expect_out { true_bytes() } else { false_bytes() };
+        test_query_output(
+            &rewrite_conditions(&query).unwrap(),
+            Some(desc.clone()),
+            &data,
+            DataType::Bool,
+            expect_out,
+        );
+    }
+}
+
+#[test]
+fn test_like_match() {
+    let like = |string: &str, pattern: &str| {
+        let string = string.chars().collect::<Vec<_>>();
+        let pattern = pattern.chars().collect::<Vec<_>>();
+        like_match(&string, &pattern)
+    };
+    assert!(like("", ""));
+    assert!(like("", "%%"));
+    assert!(like("abc", "a%c"));
+    assert!(like("abc", "%b%"));
+    assert!(like("abc", "___"));
+    assert!(!like("abc", "__"));
+    assert!(!like("abc", "a%b"));
+    assert!(like("a%c", "a%%c"));
+}
+
 fn test_query_output(
     query: &str,
     desc: Option<TupleDesc>,
@@ -111,7 +199,7 @@ fn test_query_output(
     match query {
         nom_sql::SqlQuery::Select(stmt) => {
             let cond = stmt.where_clause.unwrap();
-            let expr = Expr::from_nom(cond, &rel).unwrap();
+            let expr = Expr::from_nom(cond, &rel.tuple_desc()).unwrap();
             assert_eq!(expr.output_type, expect_ty);
             let expr_out = (expr.function)(input).unwrap();
             assert_eq!(expr_out, expect_out);
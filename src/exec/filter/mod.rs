@@ -0,0 +1,302 @@
+use db_state::DbState;
+use error::{Error, Result};
+use exec::{DataStore, ExecNode, Expr};
+use index::{HashIndex, Index, IndexType};
+use internal_types::TupleData;
+use nom_sql::{ConditionExpression, Operator};
+use rel::rel::{Rel, IndexInfo};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use storage::BufKey;
+use tuple::TuplePtr;
+
+#[cfg(test)]
+mod tests;
+
+pub struct Filter {
+    data: Rel,
+    dest: DataStore,
+    clause: ConditionExpression,
+    /// Answer from this index alone, the output tuples have the columns
+    /// of its item_desc
+    index_only: Option<IndexInfo>,
+}
+
+/// Index probes whose tuple sets are combined to find the tuples
+/// that may match a clause
+enum Bitmap {
+    /// Equality clause answered by the index
+    Probe(IndexInfo, ConditionExpression),
+    And(Box<Bitmap>, Box<Bitmap>),
+    Or(Box<Bitmap>, Box<Bitmap>),
+}
+
+impl Filter {
+    pub fn new(
+        data: Rel,
+        dest: DataStore,
+        clause: ConditionExpression,
+    ) -> Filter {
+        Filter { data, dest, clause, index_only: None }
+    }
+
+    /// Filter that does not read the tuples of data, index must be the
+    /// one find_index returns for clause
+    pub fn index_only(
+        data: Rel,
+        dest: DataStore,
+        clause: ConditionExpression,
+        index: IndexInfo,
+    ) -> Filter {
+        Filter { data, dest, clause, index_only: Some(index) }
+    }
+
+    /// The index used to filter rel by clause, if any
+    pub fn find_index(
+        rel: &Rel,
+        clause: &ConditionExpression,
+    ) -> Option<IndexInfo> {
+        // Only single column keys can be matched for now, the first
+        // matching index is used
+        rel.indices().into_iter().find(|index| {
+            index.key.len() == 1
+                && match index.index_type {
+                    IndexType::Hash => {
+                        Filter::maybe_hash_index(rel, clause, index.key[0])
+                    }
+                }
+        })
+    }
+
+    fn maybe_hash_index(
+        rel: &Rel,
+        clause: &ConditionExpression,
+        key: usize,
+    ) -> bool {
+        if let ConditionExpression::ComparisonOp(ref tree) = *clause {
+            if let Operator::Equal = tree.operator {
+                match (Expr::is_only_col((*tree.left).clone(), rel),
+                    Expr::is_no_col((*tree.right).clone())) {
+                    (Some(col_index), true) => return key == col_index,
+                    // Non-constant right exprs cannot be probed
+                    _ => return false,
+                }
+            }
+        }
+        return false;
+    }
+
+    /// Bitmap of the indices that answer clause, None if the rel has to
+    /// be scanned. Only one side of an AND needs an index, the fetched
+    /// tuples are checked against the whole clause.
+    fn bitmap(rel: &Rel, clause: &ConditionExpression) -> Option<Bitmap> {
+        match *clause {
+            ConditionExpression::ComparisonOp(_) => {
+                Filter::find_index(rel, clause)
+                    .map(|index| Bitmap::Probe(index, clause.clone()))
+            }
+            ConditionExpression::LogicalOp(ref tree) => {
+                let left = Filter::bitmap(rel, &tree.left);
+                let right = Filter::bitmap(rel, &tree.right);
+                match (&tree.operator, left, right) {
+                    (&Operator::And, Some(l), Some(r)) => {
+                        Some(Bitmap::And(Box::new(l), Box::new(r)))
+                    }
+                    (&Operator::And, Some(bitmap), None)
+                    | (&Operator::And, None, Some(bitmap)) => Some(bitmap),
+                    (&Operator::Or, Some(l), Some(r)) => {
+                        Some(Bitmap::Or(Box::new(l), Box::new(r)))
+                    }
+                    _ => None,
+                }
+            }
+            ConditionExpression::Bracketed(ref clause) => {
+                Filter::bitmap(rel, clause)
+            }
+            _ => None,
+        }
+    }
+
+    /// Pointers of the tuples in bitmap, sorted
+    fn bitmap_ptrs(
+        &self,
+        bitmap: &Bitmap,
+        db_state: &mut DbState,
+    ) -> Result<BTreeSet<TuplePtr>> {
+        match *bitmap {
+            Bitmap::Probe(ref info, ref clause) => match info.index_type {
+                IndexType::Hash => {
+                    let index = HashIndex::load(info.file_id, db_state)?;
+                    let data = self.hash_index_data(clause)?;
+                    Ok(index.get(&data, db_state)?.into_iter().collect())
+                }
+            },
+            Bitmap::And(ref left, ref right) => {
+                let left = self.bitmap_ptrs(left, db_state)?;
+                let right = self.bitmap_ptrs(right, db_state)?;
+                Ok(left.intersection(&right).cloned().collect())
+            }
+            Bitmap::Or(ref left, ref right) => {
+                let mut left = self.bitmap_ptrs(left, db_state)?;
+                left.append(&mut self.bitmap_ptrs(right, db_state)?);
+                Ok(left)
+            }
+        }
+    }
+
+    fn hash_index_data(
+        &self,
+        clause: &ConditionExpression,
+    ) -> Result<TupleData> {
+        let err = Err(Error::Internal(
+                "Invalid expression for hash indexing".to_string()));
+        if let ConditionExpression::ComparisonOp(ref tree) = *clause {
+            if let Operator::Equal = tree.operator {
+                if Expr::is_no_col((*tree.right).clone()) {
+                    let desc = self.data.tuple_desc();
+                    let left = Expr::from_nom((*tree.left).clone(), &desc)?;
+                    let right = Expr::from_nom((*tree.right).clone(), &desc)?
+                        .cast(left.output_type)?;
+                    return (right.function)(&vec![]);
+                }
+                return err;
+            }
+        }
+        return err;
+    }
+
+    /// Fetch the tuples of bitmap in page order, keeping the ones
+    /// that match the clause
+    fn bitmap_scan(
+        &self,
+        bitmap: &Bitmap,
+        db_state: &mut DbState,
+    ) -> Result<()> {
+        let ptrs = self.bitmap_ptrs(bitmap, db_state)?;
+        let ptrs = self.group_ptrs(ptrs.into_iter().collect());
+        // Probes may match more tuples than the clause, e.g. hash
+        // collisions or the side of an AND without an index
+        let desc = self.data.tuple_desc();
+        let expr = Expr::from_nom(self.clause.clone(), &desc)?;
+        let keys = ptrs.keys().cloned().collect();
+        let pages = db_state.buf_mgr.read_ahead(keys);
+        let output = self.output();
+        for (buf, buf_ptrs) in pages.zip(ptrs.values()) {
+            let buf = buf?;
+            let mut tuples = vec![];
+            {
+                let guard = buf.read().unwrap();
+                for ptr in buf_ptrs.iter() {
+                    let data = guard.get_tuple_data(ptr)?;
+                    if bincode::deserialize(&(expr.function)(data)?)? {
+                        tuples.push(data.to_vec());
+                    }
+                }
+            }
+            match output {
+                DataStore::Rel(ref rel) => {
+                    rel.write_tuples(&mut tuples.into_iter(), db_state)?;
+                }
+                DataStore::Out => {
+                    for data in tuples.iter() {
+                        println!(
+                            "{:?}", self.data.data_to_strings(data, None));
+                    }
+                }
+                _ => panic!("Invalid output destination for filter"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Output the key and included columns of the matching items
+    fn index_only_scan(
+        &self,
+        info: &IndexInfo,
+        db_state: &mut DbState,
+    ) -> Result<()> {
+        let index = HashIndex::load(info.file_id, db_state)?;
+        let key_data = self.hash_index_data(&self.clause)?;
+        let mut tuples = index
+            .get_included(&key_data, db_state)?
+            .into_iter()
+            .map(|(_, include)| [key_data.clone(), include].concat());
+        match self.output() {
+            DataStore::Rel(rel) => {
+                rel.write_tuples(&mut tuples, db_state)?;
+                Ok(())
+            }
+            DataStore::Out => {
+                let desc = index.item_desc();
+                for data in tuples {
+                    println!("{:?}", desc.data_to_strings(&data, None)?);
+                }
+                Ok(())
+            }
+            _ => panic!("Invalid output destination for filter"),
+        }
+    }
+
+    fn group_ptrs(
+        &self,
+        mut ptrs: Vec<TuplePtr>,
+    ) -> BTreeMap<BufKey, Vec<TuplePtr>> {
+        ptrs.sort();
+        let mut map = BTreeMap::new();
+        for ptr in ptrs.into_iter() {
+            map.entry(ptr.buf_key).or_insert_with(Vec::new).push(ptr);
+        }
+        map
+    }
+}
+
+impl ExecNode for Filter {
+    fn exec(&self, db_state: &mut DbState) -> Result<()> {
+        if let Some(ref index) = self.index_only {
+            return self.index_only_scan(index, db_state);
+        }
+        if let Some(bitmap) = Filter::bitmap(&self.data, &self.clause) {
+            self.bitmap_scan(&bitmap, db_state)
+        } else {
+            use data_type::DataType;
+            let desc = self.data.tuple_desc();
+            let expr = Expr::from_nom(self.clause.clone(), &desc)?;
+            assert_eq!(expr.output_type, DataType::Bool);
+            let then_fn: Box<dyn FnMut(&[u8], &mut DbState) -> Result<()>> =
+                    match self.output() {
+                DataStore::Rel(output) => {
+                    Box::new(move |data, db_state| {
+                        output.write_tuples(
+                            &mut vec![data.to_vec()].into_iter(),
+                            db_state)?;
+                        Ok(())
+                    })
+                }
+                DataStore::Out => {
+                    Box::new(|data, _| {
+                        println!(
+                            "{:?}",
+                            self.data.data_to_strings(data, None));
+                        Ok(())
+                    })
+                }
+                _ => panic!("Invalid output destination for Filter")
+            };
+            self.data.scan(
+                db_state,
+                |data| {
+                    Ok(bincode::deserialize(&(expr.function)(data)?)?)
+                },
+                then_fn
+            )
+        }
+    }
+
+    fn inputs(&self) -> Vec<Arc<dyn ExecNode>> {
+        vec![Arc::new(DataStore::Rel(self.data.clone()))]
+    }
+
+    fn output(&self) -> DataStore {
+        self.dest.clone()
+    }
+}
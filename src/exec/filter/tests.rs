@@ -0,0 +1,129 @@
+use bincode;
+use data_type::DataType;
+use db_state::DbState;
+use exec::planner::resolve_subqueries;
+use exec::{self, DataStore, ExecNode};
+use index::IndexType;
+use nom_sql::SqlQuery;
+use rel::Rel;
+use super::Filter;
+use test_utils::{setup, teardown};
+use tuple::TupleDesc;
+
+fn filter_rows(
+    rel: &Rel,
+    clause: &str,
+    db_state: &mut DbState,
+) -> Vec<Vec<String>> {
+    let query = format!("select * from test where {}", clause);
+    let clause = match exec::parse_query(&query).unwrap() {
+        SqlQuery::Select(stmt) => stmt.where_clause.unwrap(),
+        _ => panic!("Test query is not a Select statement"),
+    };
+    let clause = resolve_subqueries(clause, db_state).unwrap();
+    let output = Rel::new_temp_rel(rel.tuple_desc(), db_state).unwrap();
+    Filter::new(rel.clone(), DataStore::Rel(output.clone()), clause)
+        .exec(db_state)
+        .unwrap();
+    let mut rows = vec![];
+    output.scan(db_state, |_| Ok(true), |tup, _| {
+        rows.push(output.data_to_strings(tup, None)?);
+        Ok(())
+    }).unwrap();
+    rows
+}
+
+#[test]
+fn test_bitmap_scan() {
+    let mut db_state = setup("test_bitmap_scan");
+    let desc = TupleDesc::new(
+        vec![DataType::I32, DataType::I32],
+        vec!["a", "b"],
+    );
+    let mut rel = Rel::new("test_bitmap_scan", desc, &mut db_state).unwrap();
+    let tuples = [(1, 1), (1, 2), (2, 2), (3, 3)]
+        .iter()
+        .map(|(a, b): &(i32, i32)| {
+            [bincode::serialize(a).unwrap(), bincode::serialize(b).unwrap()]
+                .concat()
+        })
+        .collect::<Vec<_>>();
+    rel.write_tuples(&mut tuples.into_iter(), &mut db_state).unwrap();
+    rel.new_index(vec![0], IndexType::Hash, &mut db_state).unwrap();
+    rel.new_index(vec![1], IndexType::Hash, &mut db_state).unwrap();
+
+    let and = filter_rows(&rel, "a = 1 and b = 2", &mut db_state);
+    let or = filter_rows(&rel, "a = 1 or b = 2", &mut db_state);
+    // Only a can be probed, b is checked on the fetched tuples
+    let recheck = filter_rows(&rel, "a = 1 and b <> 2", &mut db_state);
+    let nested = filter_rows(&rel, "(a = 3 or b = 1) and a = 1", &mut db_state);
+    // Scanned, the right side of the OR has no index
+    let scan = filter_rows(&rel, "a = 3 or b <> 2", &mut db_state);
+    teardown(db_state);
+
+    let rows = |rows: &[(&str, &str)]| {
+        rows.iter()
+            .map(|(a, b)| vec![a.to_string(), b.to_string()])
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(and, rows(&[("1", "2")]));
+    assert_eq!(or, rows(&[("1", "1"), ("1", "2"), ("2", "2")]));
+    assert_eq!(recheck, rows(&[("1", "1")]));
+    assert_eq!(nested, rows(&[("1", "1")]));
+    assert_eq!(scan, rows(&[("1", "1"), ("3", "3")]));
+}
+
+#[test]
+fn test_in_subquery() {
+    let mut db_state = setup("test_in_subquery");
+    let desc = TupleDesc::new(vec![DataType::I32], vec!["a"]);
+    let rel = Rel::new("test_in_subquery", desc, &mut db_state).unwrap();
+    let tuples = (1..5i32)
+        .map(|a| bincode::serialize(&a).unwrap())
+        .collect::<Vec<_>>();
+    rel.write_tuples(&mut tuples.into_iter(), &mut db_state).unwrap();
+
+    let subquery = "select a from test_in_subquery where a > 2";
+    let rows = filter_rows(
+        &rel, &format!("a in ({})", subquery), &mut db_state);
+    let not_in = filter_rows(
+        &rel, &format!("not (a in ({}))", subquery), &mut db_state);
+    teardown(db_state);
+
+    assert_eq!(rows, vec![vec!["3"], vec!["4"]]);
+    assert_eq!(not_in, vec![vec!["1"], vec!["2"]]);
+}
+
+#[test]
+fn test_u64_subquery() {
+    let mut db_state = setup("test_u64_subquery");
+    let desc = TupleDesc::new(vec![DataType::U64], vec!["a"]);
+    let write_rel = |name: &str, values: &[u64], db_state: &mut DbState| {
+        let rel = Rel::new(name, desc.clone(), db_state).unwrap();
+        let tuples = values
+            .iter()
+            .map(|a| bincode::serialize(a).unwrap())
+            .collect::<Vec<_>>();
+        rel.write_tuples(&mut tuples.into_iter(), db_state).unwrap();
+    };
+    write_rel("fits", &[1, i64::MAX as u64], &mut db_state);
+    write_rel("wraps", &[1, u64::MAX], &mut db_state);
+
+    let resolve = |table: &str, db_state: &mut DbState| {
+        let query = format!(
+            "select * from fits where a in (select a from {})", table);
+        match exec::parse_query(&query).unwrap() {
+            SqlQuery::Select(stmt) => {
+                resolve_subqueries(stmt.where_clause.unwrap(), db_state)
+            }
+            _ => panic!("Test query is not a Select statement"),
+        }
+    };
+    let fits = resolve("fits", &mut db_state);
+    // u64::MAX would wrap to -1
+    let wraps = resolve("wraps", &mut db_state);
+    teardown(db_state);
+
+    assert!(fits.is_ok());
+    assert!(wraps.is_err());
+}
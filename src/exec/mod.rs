@@ -1,25 +1,77 @@
+pub mod alter_table;
+pub mod backup;
+pub mod catalog;
+pub mod command;
+pub mod create_index;
 pub mod create_table;
 pub mod data_store;
+pub mod drop_table;
 pub mod exec_node;
 pub mod expr;
 pub mod filter;
 pub mod insert;
 mod planner;
 pub mod projection;
+pub mod show_stats;
+pub mod truncate;
+pub mod vacuum;
 
+pub use self::alter_table::{AlterOp, AlterTable};
+pub use self::backup::Backup;
+pub use self::command::Command;
+pub use self::create_index::CreateIndex;
 pub use self::create_table::CreateTable;
 pub use self::data_store::DataStore;
+pub use self::drop_table::DropTable;
 pub use self::exec_node::ExecNode;
 pub use self::expr::Expr;
 pub use self::filter::Filter;
 pub use self::insert::Insert;
 pub use self::projection::Projection;
+pub use self::show_stats::ShowStats;
+pub use self::truncate::Truncate;
+pub use self::vacuum::Vacuum;
 
 use db_state::DbState;
-use error::Result;
-use nom_sql::SqlQuery;
+use error::{Error, Result};
+use nom_sql::{
+    self, Column, ConditionBase, ConditionExpression, ConditionTree, Literal,
+    Operator, SqlQuery,
+};
+use utils;
 
+/// Parse a statement with nom_sql, see command::rewrite_conditions
+/// and command::parse_select_without_from
+pub fn parse_query(query: &str) -> Result<SqlQuery> {
+    let query = command::rewrite_conditions(query)?;
+    nom_sql::parse_query(&query).or_else(|e| {
+        command::parse_select_without_from(&query)
+            .map(SqlQuery::Select)
+            .ok_or_else(|| Error::Internal(e.to_string()))
+    })
+}
+
+/// Execute a statement, its log entries are durable once it returns
 pub fn exec(query: SqlQuery, db_state: &mut DbState) -> Result<()> {
+    let mut query_state = db_state.for_query();
+    let result = exec_query(query, &mut query_state);
+    // Temp rels only live as long as the query, even if it failed
+    let dropped = query_state.buf_mgr.drop_temp_files();
+    result?;
+    dropped?;
+    db_state.commit()
+}
+
+pub fn exec_command(cmd: Command, db_state: &mut DbState) -> Result<()> {
+    let mut query_state = db_state.for_query();
+    let result = run_command(cmd, &mut query_state);
+    let dropped = query_state.buf_mgr.drop_temp_files();
+    result?;
+    dropped?;
+    db_state.commit()
+}
+
+fn exec_query(query: SqlQuery, db_state: &mut DbState) -> Result<()> {
     match query {
         SqlQuery::CreateTable(stmt) => match planner::plan_create(stmt)? {
             Some(node) => node.exec(db_state),
@@ -33,6 +85,82 @@ pub fn exec(query: SqlQuery, db_state: &mut DbState) -> Result<()> {
             Some(node) => node.exec(db_state),
             None => Ok(()),
         },
+        SqlQuery::DropTable(stmt) => {
+            let tables = stmt
+                .tables
+                .iter()
+                .map(|table| table.name.clone())
+                .collect();
+            match planner::plan_drop(tables, stmt.if_exists, db_state)? {
+                Some(node) => node.exec(db_state),
+                None => Ok(()),
+            }
+        }
         _ => Ok(()),
     }
 }
+
+fn run_command(cmd: Command, db_state: &mut DbState) -> Result<()> {
+    match cmd {
+        Command::Vacuum(table) => {
+            match planner::plan_vacuum(table, db_state)? {
+                Some(node) => node.exec(db_state),
+                None => Ok(()),
+            }
+        }
+        Command::AlterTable(table, op) => {
+            match planner::plan_alter(table, op, db_state)? {
+                Some(node) => node.exec(db_state),
+                None => Ok(()),
+            }
+        }
+        Command::CreateIndex(table, key, include) => {
+            match planner::plan_create_index(table, key, include, db_state)? {
+                Some(node) => node.exec(db_state),
+                None => Ok(()),
+            }
+        }
+        Command::Backup(dir) => Backup::new(dir).exec(db_state),
+        Command::ShowStats => ShowStats::new().exec(db_state),
+        Command::ListTables => {
+            exec_catalog_query(catalog::TABLES, None, db_state)
+        }
+        Command::DescribeTable(table) => {
+            utils::get_table_id(table.clone(), db_state)?;
+            let filter = table_name_filter(table);
+            exec_catalog_query(
+                catalog::COLUMNS, Some(filter.clone()), db_state)?;
+            exec_catalog_query(catalog::INDEXES, Some(filter), db_state)
+        }
+        Command::Truncate(table) => {
+            match planner::plan_truncate(table, db_state)? {
+                Some(node) => node.exec(db_state),
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+/// Print the attribute names of a catalog and the rows matching filter
+fn exec_catalog_query(
+    name: &str,
+    filter: Option<ConditionExpression>,
+    db_state: &mut DbState,
+) -> Result<()> {
+    let mut query = parse_query(&format!("SELECT * FROM {}", name))?;
+    if let SqlQuery::Select(ref mut stmt) = query {
+        stmt.where_clause = filter;
+    }
+    exec(query, db_state)
+}
+
+/// table_name = table, built as a tree so table is never parsed as SQL
+fn table_name_filter(table: String) -> ConditionExpression {
+    let field = ConditionBase::Field(Column::from("table_name"));
+    let value = ConditionBase::Literal(Literal::String(table));
+    ConditionExpression::ComparisonOp(ConditionTree {
+        operator: Operator::Equal,
+        left: Box::new(ConditionExpression::Base(field)),
+        right: Box::new(ConditionExpression::Base(value)),
+    })
+}
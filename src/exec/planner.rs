@@ -1,9 +1,10 @@
 use db_state::DbState;
-use error::Result;
-use exec::{DataStore, ExecNode};
+use error::{Error, Result};
+use exec::{catalog, AlterOp, DataStore, ExecNode};
 use nom_sql::{
-    CreateTableStatement, FieldDefinitionExpression, InsertStatement,
-    SelectStatement,
+    ArithmeticBase, Column, ConditionBase, ConditionExpression, ConditionTree,
+    CreateTableStatement, FieldDefinitionExpression, FieldValueExpression,
+    InsertStatement, Literal, SelectStatement,
 };
 use rel::Rel;
 use std::sync::Arc;
@@ -42,15 +43,45 @@ pub fn plan_select(
 ) -> Result<Option<Box<dyn ExecNode>>> {
     use super::{Filter, Projection};
 
-    let rel_id = match stmt.tables.len() {
-        1 => utils::get_table_id(stmt.tables[0].name.clone(), db_state)?,
-        _ => todo!("Join is not supported yet"),
-    };
+    if stmt.tables.is_empty() {
+        if stmt.where_clause.is_some() {
+            return Err(Error::internal("WHERE needs a FROM clause"));
+        }
+        let src = DataStore::Data {
+            tuples: vec![vec![]],
+            desc: TupleDesc::empty(),
+        };
+        return Ok(Some(Box::new(Projection::new(
+            Arc::new(src),
+            DataStore::Out,
+            stmt.fields,
+        ))));
+    }
 
-    let rel = Rel::load(rel_id, BufType::Data, db_state)?;
-    let fields = build_select_fields(&stmt.fields, rel.tuple_desc());
-    let projection_src = match stmt.where_clause {
-        Some(clause) => {
+    let rel = load_table(&stmt, db_state)?;
+    let fields = expand_all(stmt.fields, &rel.tuple_desc());
+    let where_clause = match stmt.where_clause {
+        Some(clause) => Some(resolve_subqueries(clause, db_state)?),
+        None => None,
+    };
+    let covering = where_clause.as_ref().and_then(|clause| {
+        let cols = select_cols(&fields, &rel.tuple_desc())?;
+        Filter::find_index(&rel, clause).filter(|index| index.covers(&cols))
+    });
+    let projection_src = match (where_clause, covering) {
+        (Some(clause), Some(index)) => {
+            // Fields are taken from the index items instead of the tuples
+            let cols = [index.key.clone(), index.include.clone()].concat();
+            let desc = rel.tuple_desc().subset(&cols)?;
+            let temp_rel = Rel::new_temp_rel(desc, db_state)?;
+            Arc::new(Filter::index_only(
+                rel,
+                DataStore::Rel(temp_rel),
+                clause,
+                index,
+            )) as Arc<dyn ExecNode>
+        }
+        (Some(clause), None) => {
             let temp_rel = Rel::new_temp_rel(rel.tuple_desc(), db_state)?;
             Arc::new(
                 Filter::new(
@@ -58,7 +89,7 @@ pub fn plan_select(
                     DataStore::Rel(temp_rel),
                     clause)) as Arc<dyn ExecNode>
         }
-        None => Arc::new(DataStore::Rel(rel)),
+        (None, _) => Arc::new(DataStore::Rel(rel)),
     };
     Ok(Some(Box::new(Projection::new(
         projection_src,
@@ -67,21 +98,217 @@ pub fn plan_select(
     ))))
 }
 
-fn build_select_fields(
-    fields: &Vec<FieldDefinitionExpression>,
-    tuple_desc: TupleDesc,
-) -> Vec<usize> {
+fn load_table(stmt: &SelectStatement, db_state: &mut DbState) -> Result<Rel> {
+    let table = match stmt.tables.len() {
+        0 => return Err(Error::internal("SELECT needs a FROM clause")),
+        1 => stmt.tables[0].name.clone(),
+        _ => todo!("Join is not supported yet"),
+    };
+
+    match catalog::load(&table, db_state)? {
+        Some(rel) => Ok(rel),
+        None => {
+            let rel_id = utils::get_table_id(table, db_state)?;
+            Rel::load(rel_id, BufType::Data, db_state)
+        }
+    }
+}
+
+/// Replace the subqueries of clause by the lists of values they return.
+/// Subqueries cannot refer to the outer query, so they are run once.
+pub fn resolve_subqueries(
+    clause: ConditionExpression,
+    db_state: &mut DbState,
+) -> Result<ConditionExpression> {
+    let mut resolve = |expr: Box<ConditionExpression>| -> Result<_> {
+        Ok(Box::new(resolve_subqueries(*expr, db_state)?))
+    };
+    Ok(match clause {
+        ConditionExpression::ComparisonOp(tree) => {
+            ConditionExpression::ComparisonOp(ConditionTree {
+                operator: tree.operator,
+                left: resolve(tree.left)?,
+                right: resolve(tree.right)?,
+            })
+        }
+        ConditionExpression::LogicalOp(tree) => {
+            ConditionExpression::LogicalOp(ConditionTree {
+                operator: tree.operator,
+                left: resolve(tree.left)?,
+                right: resolve(tree.right)?,
+            })
+        }
+        ConditionExpression::NegationOp(expr) => {
+            ConditionExpression::NegationOp(resolve(expr)?)
+        }
+        ConditionExpression::Bracketed(expr) => {
+            ConditionExpression::Bracketed(resolve(expr)?)
+        }
+        ConditionExpression::Base(ConditionBase::NestedSelect(stmt)) => {
+            let values = select_values(*stmt, db_state)?;
+            ConditionExpression::Base(ConditionBase::LiteralList(values))
+        }
+        clause => clause,
+    })
+}
+
+/// Values of the one column selected by stmt
+fn select_values(
+    stmt: SelectStatement,
+    db_state: &mut DbState,
+) -> Result<Vec<Literal>> {
+    use super::{Filter, Projection};
+
+    let rel = load_table(&stmt, db_state)?;
+    let desc = rel.tuple_desc();
+    let fields = expand_all(stmt.fields, &desc);
+    let (exprs, output_desc) = Projection::output_exprs(&fields, &desc)?;
+    if exprs.len() != 1 {
+        return Err(Error::internal("Subquery must select one column"));
+    }
+    let src = match stmt.where_clause {
+        Some(clause) => {
+            let clause = resolve_subqueries(clause, db_state)?;
+            let temp_rel = Rel::new_temp_rel(desc.clone(), db_state)?;
+            let dest = DataStore::Rel(temp_rel.clone());
+            Filter::new(rel, dest, clause).exec(db_state)?;
+            temp_rel
+        }
+        None => rel,
+    };
+    let attr_type = output_desc.attr_types()[0];
+    let mut values = vec![];
+    src.scan(db_state, |_| Ok(true), |data, _| {
+        let value = (exprs[0].function)(data)?;
+        values.push(attr_type.data_to_literal(&value)?);
+        Ok(())
+    })?;
+    Ok(values)
+}
+
+pub fn plan_vacuum(
+    table: Option<String>,
+    db_state: &mut DbState,
+) -> Result<Option<Box<dyn ExecNode>>> {
+    use exec::Vacuum;
+
+    let rel_ids = match table {
+        Some(name) => vec![utils::get_table_id(name, db_state)?],
+        None => utils::get_all_table_ids(db_state)?,
+    };
+    let rels = rel_ids
+        .into_iter()
+        .map(|rel_id| Rel::load(rel_id, BufType::Data, db_state))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Some(Box::new(Vacuum::new(rels))))
+}
+
+pub fn plan_drop(
+    tables: Vec<String>,
+    if_exists: bool,
+    db_state: &mut DbState,
+) -> Result<Option<Box<dyn ExecNode>>> {
+    use exec::DropTable;
+
+    let mut rels = vec![];
+    for name in tables.into_iter() {
+        let rel_id = match utils::find_table_id(name.clone(), db_state)? {
+            Some(rel_id) => rel_id,
+            None if if_exists => continue,
+            None => {
+                return Err(Error::Internal(format!(
+                    "Table {} does not exist",
+                    name
+                )))
+            }
+        };
+        rels.push((name, Rel::load(rel_id, BufType::Data, db_state)?));
+    }
+    Ok(Some(Box::new(DropTable::new(rels))))
+}
+
+pub fn plan_truncate(
+    table: String,
+    db_state: &mut DbState,
+) -> Result<Option<Box<dyn ExecNode>>> {
+    use exec::Truncate;
+
+    let rel_id = utils::get_table_id(table, db_state)?;
+    let rel = Rel::load(rel_id, BufType::Data, db_state)?;
+    Ok(Some(Box::new(Truncate::new(rel))))
+}
+
+pub fn plan_create_index(
+    table: String,
+    key: Vec<String>,
+    include: Vec<String>,
+    db_state: &mut DbState,
+) -> Result<Option<Box<dyn ExecNode>>> {
+    use exec::CreateIndex;
+
+    let rel_id = utils::get_table_id(table, db_state)?;
+    let rel = Rel::load(rel_id, BufType::Data, db_state)?;
+    Ok(Some(Box::new(CreateIndex::new(rel, key, include))))
+}
+
+pub fn plan_alter(
+    table: String,
+    op: AlterOp,
+    db_state: &mut DbState,
+) -> Result<Option<Box<dyn ExecNode>>> {
+    use exec::AlterTable;
+
+    let rel_id = utils::get_table_id(table.clone(), db_state)?;
+    let rel = Rel::load(rel_id, BufType::Data, db_state)?;
+    Ok(Some(Box::new(AlterTable::new(table, rel, op))))
+}
+
+/// Replace * by the columns of desc, in order
+fn expand_all(
+    fields: Vec<FieldDefinitionExpression>,
+    desc: &TupleDesc,
+) -> Vec<FieldDefinitionExpression> {
     fields
+        .into_iter()
+        .flat_map(|field| match field {
+            FieldDefinitionExpression::All
+            | FieldDefinitionExpression::AllInTable(_) => desc
+                .attr_names()
+                .iter()
+                .map(|name| {
+                    FieldDefinitionExpression::Col(Column::from(name.as_str()))
+                })
+                .collect(),
+            field => vec![field],
+        })
+        .collect()
+}
+
+/// Columns of desc read by the fields, None if a field reads something
+/// else
+fn select_cols(
+    fields: &[FieldDefinitionExpression],
+    desc: &TupleDesc,
+) -> Option<Vec<usize>> {
+    let col = |col: &Column| desc.attr_index(&col.name).map(|i| vec![i]);
+    let base_cols = |base: &ArithmeticBase| match base {
+        ArithmeticBase::Column(c) => col(c),
+        ArithmeticBase::Scalar(_) => Some(vec![]),
+    };
+    let cols = fields
         .iter()
         .map(|field| match field {
-            FieldDefinitionExpression::All => {
-                (0..tuple_desc.num_attrs() as usize).collect()
-            }
-            FieldDefinitionExpression::Col(column) => {
-                vec![tuple_desc.attr_index(&column.name).unwrap()]
+            FieldDefinitionExpression::Col(c) if c.function.is_none() => col(c),
+            FieldDefinitionExpression::Value(
+                FieldValueExpression::Arithmetic(expr),
+            ) => {
+                Some([base_cols(&expr.left)?, base_cols(&expr.right)?].concat())
             }
-            _ => vec![],
+            FieldDefinitionExpression::Value(
+                FieldValueExpression::Literal(_),
+            ) => Some(vec![]),
+            _ => None,
         })
-        .collect::<Vec<_>>()
-        .concat()
+        .collect::<Option<Vec<_>>>()?;
+    Some(cols.concat())
 }
@@ -0,0 +1,145 @@
+use db_state::DbState;
+use error::{Error, Result};
+use exec::{DataStore, ExecNode, Expr};
+use internal_types::TupleData;
+use nom_sql::{
+    Column, ConditionBase, FieldDefinitionExpression, FieldValueExpression,
+};
+use std::sync::Arc;
+use tuple::TupleDesc;
+
+#[cfg(test)]
+mod tests;
+
+/// Evaluates the fields of a select list for every tuple of src
+pub struct Projection {
+    src: Arc<dyn ExecNode>,
+    dest: DataStore,
+    fields: Vec<FieldDefinitionExpression>,
+}
+
+impl Projection {
+    pub fn new(
+        src: Arc<dyn ExecNode>,
+        dest: DataStore,
+        fields: Vec<FieldDefinitionExpression>,
+    ) -> Projection {
+        Projection { src, dest, fields }
+    }
+
+    /// Expressions of the output columns over tuples of src_desc and
+    /// the desc of the output, columns are named by their alias or
+    /// else by the expression
+    pub fn output_exprs(
+        fields: &[FieldDefinitionExpression],
+        src_desc: &TupleDesc,
+    ) -> Result<(Vec<Expr>, TupleDesc)> {
+        let mut exprs = vec![];
+        let mut names = vec![];
+        for field in fields.iter() {
+            match field {
+                FieldDefinitionExpression::All
+                | FieldDefinitionExpression::AllInTable(_) => {
+                    for name in src_desc.attr_names().into_iter() {
+                        let col = ConditionBase::Field(Column::from(&*name));
+                        exprs.push(Expr::from_nom(col, src_desc)?);
+                        names.push(name);
+                    }
+                }
+                FieldDefinitionExpression::Col(col) => {
+                    if col.function.is_some() {
+                        return Err(Error::internal(
+                            "Functions are not supported yet",
+                        ));
+                    }
+                    let name = col.alias.clone().unwrap_or(col.name.clone());
+                    let col = ConditionBase::Field(col.clone());
+                    exprs.push(Expr::from_nom(col, src_desc)?);
+                    names.push(name);
+                }
+                FieldDefinitionExpression::Value(
+                    FieldValueExpression::Arithmetic(expr),
+                ) => {
+                    let name = match expr.alias {
+                        Some(ref alias) => alias.clone(),
+                        None => expr.to_string(),
+                    };
+                    exprs.push(Expr::from_nom(expr.clone(), src_desc)?);
+                    names.push(name);
+                }
+                FieldDefinitionExpression::Value(
+                    FieldValueExpression::Literal(expr),
+                ) => {
+                    let name = match expr.alias {
+                        Some(ref alias) => alias.clone(),
+                        None => expr.value.to_string(),
+                    };
+                    let literal = ConditionBase::Literal(expr.value.clone());
+                    exprs.push(Expr::from_nom(literal, src_desc)?);
+                    names.push(name);
+                }
+            }
+        }
+        let types = exprs.iter().map(|expr| expr.output_type).collect();
+        Ok((exprs, TupleDesc::new(types, names)))
+    }
+}
+
+impl ExecNode for Projection {
+    fn exec(&self, db_state: &mut DbState) -> Result<()> {
+        self.src.exec(db_state)?;
+
+        let src_desc = match self.src.output() {
+            DataStore::Rel(ref input) => input.tuple_desc(),
+            DataStore::Data { ref desc, .. } => desc.clone(),
+            DataStore::Out => panic!("Invalid input for Projection"),
+        };
+        let (exprs, desc) = Projection::output_exprs(&self.fields, &src_desc)?;
+        let project = |data: &[u8]| -> Result<TupleData> {
+            let cols = exprs
+                .iter()
+                .map(|expr| (expr.function)(data))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(cols.concat())
+        };
+        let output = self.output();
+        if let DataStore::Out = output {
+            println!("{:?}", desc.attr_names());
+        }
+        let then = |data: &[u8], db_state: &mut DbState| {
+            let projected = project(data)?;
+            match output {
+                DataStore::Rel(ref output) => {
+                    output.write_tuples(
+                        &mut vec![projected].into_iter(),
+                        db_state,
+                    )?;
+                }
+                DataStore::Out => {
+                    println!("{:?}", desc.data_to_strings(&projected, None)?);
+                }
+                _ => panic!("Invalid output destination for Projection"),
+            }
+            Ok(())
+        };
+
+        match self.src.output() {
+            DataStore::Rel(input) => input.scan(db_state, |_| Ok(true), then),
+            DataStore::Data { tuples, .. } => {
+                for data in tuples.iter() {
+                    then(data, db_state)?;
+                }
+                Ok(())
+            }
+            DataStore::Out => Ok(()),
+        }
+    }
+
+    fn inputs(&self) -> Vec<Arc<dyn ExecNode>> {
+        vec![self.src.clone()]
+    }
+
+    fn output(&self) -> DataStore {
+        self.dest.clone()
+    }
+}
@@ -0,0 +1,72 @@
+use bincode;
+use data_type::DataType;
+use exec::{self, DataStore, ExecNode};
+use nom_sql::{FieldDefinitionExpression, SqlQuery};
+use rel::Rel;
+use std::sync::Arc;
+use super::Projection;
+use test_utils::{setup, teardown};
+use tuple::TupleDesc;
+
+fn select_fields(query: &str) -> Vec<FieldDefinitionExpression> {
+    match exec::parse_query(query).unwrap() {
+        SqlQuery::Select(stmt) => stmt.fields,
+        _ => panic!("Test query is not a Select statement"),
+    }
+}
+
+#[test]
+fn test_output_exprs() {
+    let desc = TupleDesc::new(
+        vec![DataType::I32, DataType::I32],
+        vec!["a", "b"],
+    );
+    let fields =
+        select_fields("select a + b as total, b as x, 'c', a * 2 from t");
+    let (exprs, output_desc) =
+        Projection::output_exprs(&fields, &desc).unwrap();
+    let data = [
+        bincode::serialize(&1i32).unwrap(),
+        bincode::serialize(&2i32).unwrap(),
+    ].concat();
+    let projected = exprs
+        .iter()
+        .map(|expr| (expr.function)(&data).unwrap())
+        .collect::<Vec<_>>()
+        .concat();
+
+    assert_eq!(output_desc.attr_names(), vec!["total", "x", "'c'", "a * 2"]);
+    assert_eq!(output_desc.attr_types(), vec![
+        DataType::I32, DataType::I32, DataType::VarChar, DataType::I64,
+    ]);
+    assert_eq!(
+        output_desc.data_to_strings(&projected, None).unwrap(),
+        vec!["3", "2", "c", "2"]
+    );
+    let invalid = select_fields("select c from t");
+    assert!(Projection::output_exprs(&invalid, &desc).is_err());
+}
+
+#[test]
+fn test_project_without_table() {
+    let mut db_state = setup("test_project_without_table");
+    let fields = select_fields("select 1 + 2 as three, 'x'");
+    let (_, output_desc) =
+        Projection::output_exprs(&fields, &TupleDesc::empty()).unwrap();
+    let output = Rel::new_temp_rel(output_desc, &mut db_state).unwrap();
+    let src = DataStore::Data {
+        tuples: vec![vec![]],
+        desc: TupleDesc::empty(),
+    };
+    Projection::new(Arc::new(src), DataStore::Rel(output.clone()), fields)
+        .exec(&mut db_state)
+        .unwrap();
+    let mut rows = vec![];
+    output.scan(&mut db_state, |_| Ok(true), |tup, _| {
+        rows.push(output.data_to_strings(tup, None)?);
+        Ok(())
+    }).unwrap();
+    teardown(db_state);
+
+    assert_eq!(rows, vec![vec!["3", "x"]]);
+}
@@ -0,0 +1,70 @@
+use db_state::DbState;
+use error::Result;
+use exec::{DataStore, ExecNode};
+use std::sync::Arc;
+use storage::buf_mgr::Counters;
+
+/// Print the BufMgr counters, per BufType then per rel
+#[derive(Default)]
+pub struct ShowStats {}
+
+impl ShowStats {
+    pub fn new() -> ShowStats {
+        ShowStats {}
+    }
+
+    fn row(buf_type: String, rel: String, counters: &Counters) -> Vec<String> {
+        vec![
+            buf_type,
+            rel,
+            counters.hits.to_string(),
+            counters.misses.to_string(),
+            counters.reads.to_string(),
+            counters.writes.to_string(),
+            counters.evictions.to_string(),
+            format!("{:.3}", counters.hit_ratio()),
+        ]
+    }
+}
+
+impl ExecNode for ShowStats {
+    fn exec(&self, db_state: &mut DbState) -> Result<()> {
+        let stats = db_state.buf_mgr.stats();
+        println!(
+            "cached: {}, dirty: {}, pinned: {}, capacity: {}",
+            stats.cached, stats.dirty, stats.pinned, stats.capacity
+        );
+        println!(
+            "{:?}",
+            [
+                "buf_type", "rel", "hits", "misses", "reads", "writes",
+                "evictions", "hit_ratio"
+            ]
+        );
+        let total = stats.total();
+        println!("{:?}", ShowStats::row("All".into(), "".into(), &total));
+        for (buf_type, counters) in stats.by_buf_type() {
+            let buf_type = format!("{:?}", buf_type);
+            println!("{:?}", ShowStats::row(buf_type, "".into(), &counters));
+        }
+        for ((buf_type, file_id), counters) in stats.counters.iter() {
+            if let Some(file_id) = file_id {
+                let row = ShowStats::row(
+                    format!("{:?}", buf_type),
+                    file_id.to_string(),
+                    counters,
+                );
+                println!("{:?}", row);
+            }
+        }
+        Ok(())
+    }
+
+    fn inputs(&self) -> Vec<Arc<dyn ExecNode>> {
+        vec![]
+    }
+
+    fn output(&self) -> DataStore {
+        DataStore::Out
+    }
+}
@@ -0,0 +1,29 @@
+use db_state::DbState;
+use error::Result;
+use exec::{DataStore, ExecNode};
+use rel::Rel;
+use std::sync::Arc;
+
+pub struct Truncate {
+    rel: Rel,
+}
+
+impl Truncate {
+    pub fn new(rel: Rel) -> Truncate {
+        Truncate { rel }
+    }
+}
+
+impl ExecNode for Truncate {
+    fn exec(&self, db_state: &mut DbState) -> Result<()> {
+        self.rel.truncate(db_state)
+    }
+
+    fn inputs(&self) -> Vec<Arc<dyn ExecNode>> {
+        vec![Arc::new(DataStore::Rel(self.rel.clone()))]
+    }
+
+    fn output(&self) -> DataStore {
+        DataStore::Out
+    }
+}
@@ -0,0 +1,46 @@
+use db_state::DbState;
+use error::Result;
+use exec::{DataStore, ExecNode};
+use rel::Rel;
+use std::sync::Arc;
+
+pub struct Vacuum {
+    rels: Vec<Rel>,
+}
+
+impl Vacuum {
+    pub fn new(rels: Vec<Rel>) -> Vacuum {
+        Vacuum { rels }
+    }
+}
+
+impl ExecNode for Vacuum {
+    fn exec(&self, db_state: &mut DbState) -> Result<()> {
+        for rel in self.rels.iter() {
+            let stats = rel.vacuum(db_state)?;
+            println!(
+                "VACUUM rel {}: compacted {} pages, reclaimed {} slots, \
+                 truncated {} pages ({} bytes reclaimed)",
+                rel.rel_id,
+                stats.pages_compacted,
+                stats.slots_reclaimed,
+                stats.pages_truncated,
+                stats.bytes_reclaimed(db_state.buf_mgr.page_size())
+            );
+        }
+        Ok(())
+    }
+
+    fn inputs(&self) -> Vec<Arc<dyn ExecNode>> {
+        self.rels
+            .iter()
+            .map(|rel| {
+                Arc::new(DataStore::Rel(rel.clone())) as Arc<dyn ExecNode>
+            })
+            .collect()
+    }
+
+    fn output(&self) -> DataStore {
+        DataStore::Out
+    }
+}
@@ -2,7 +2,10 @@ use bincode;
 use db_state::DbState;
 use error::{Error, Result};
 use internal_types::{TupleData, ID};
+use meta::TABLE_REL_ID;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use storage::buf_page::{HEADER_SIZE, SLOT_SIZE};
 use storage::{BufMgr, BufKey, BufPage, BufType};
 use super::Index;
 use tuple::{TupleDesc, TuplePtr};
@@ -12,9 +15,33 @@ use utils;
 mod tests;
 
 const INIT_N: u64 = 2;
-// (PAGE_SIZE - HEADER_SIZE) / (sizeof(HashItem) + 4) - overflow_ptr = 91
-const ITEMS_PER_BUCKET: usize = 90;
-const METADATA_ITEMS: usize = 5;
+/// Serialized sizes of a HashItem without included columns and of the
+/// overflow key of a page
+const ITEM_LEN: usize = 48;
+const OVERFLOW_KEY_LEN: usize = 16;
+const METADATA_ITEMS: usize = 9;
+/// Version of the layout of the meta page and items, stored as the last
+/// item of the meta page. Indexes of another version are not loaded.
+const FORMAT_VERSION: u32 = 2;
+/// Buckets are merged when they are less than 1/MERGE_LOAD full on average
+const MERGE_LOAD: usize = 4;
+/// Overflow and free list keys use file 0 for the end of the list
+const NO_PAGE: BufKey = BufKey {
+    file_id: 0,
+    offset: 0,
+    buf_type: BufType::Data,
+};
+
+/// Items that fit in a bucket or overflow page next to its overflow key,
+/// pages of covering indexes fill up before reaching it
+fn items_per_bucket(page_size: usize) -> usize {
+    items_per_page(page_size, ITEM_LEN)
+}
+
+fn items_per_page(page_size: usize, item_len: usize) -> usize {
+    (page_size - HEADER_SIZE - OVERFLOW_KEY_LEN - SLOT_SIZE)
+        / (item_len + SLOT_SIZE)
+}
 
 #[derive(Clone, Debug)]
 pub struct HashIndex {
@@ -22,6 +49,8 @@ pub struct HashIndex {
     pub rel_id: ID,
     pub key_desc: TupleDesc,
     pub overflow_file_id: ID,
+    /// Columns stored in the items next to the key, see get_included
+    pub include_desc: TupleDesc,
 }
 
 impl Index for HashIndex {
@@ -62,10 +91,12 @@ impl Index for HashIndex {
             let level: u32 = bincode::deserialize(
                 &meta_guard.get_tuple_data(&self.level_ptr())?,
             )?;
-            let hash = self.hash(&data);
-            let bucket = self.get_bucket(hash, &next, level);
-            let need_split = bucket.write_items(
-                vec![HashItem { hash, ptr }], db_state)?;
+            let item = self.new_item(&data, ptr)?;
+            let bucket = self.get_bucket(item.hash, &next, level);
+            let need_split =
+                bucket.write_items(vec![item], &mut meta_guard, db_state)?;
+            let count = self.count(&meta_guard)?;
+            self.set_count(count + 1, &mut meta_guard)?;
             if need_split {
                 self.split(&mut *meta_guard, db_state)?;
             }
@@ -73,9 +104,54 @@ impl Index for HashIndex {
         Ok(())
     }
 
+    fn remove(
+        &self,
+        items: &mut dyn Iterator<Item=(TupleData, TuplePtr)>,
+        db_state: &mut DbState,
+    ) -> Result<()> {
+        let meta = db_state.buf_mgr.get_buf(&self.meta_key())?;
+        let mut meta_guard = meta.write().unwrap();
+        let next: BufKey =
+            bincode::deserialize(meta_guard.get_tuple_data(&self.next_ptr())?)?;
+        let level: u32 = bincode::deserialize(
+            meta_guard.get_tuple_data(&self.level_ptr())?,
+        )?;
+        let mut count = self.count(&meta_guard)?;
+        for (data, ptr) in items {
+            let item = self.new_item(&data, ptr)?;
+            let bucket = self.get_bucket(item.hash, &next, level);
+            let removed =
+                bucket.remove_items(&[item], &mut meta_guard, db_state)?;
+            count = count.saturating_sub(removed as u64);
+        }
+        self.set_count(count, &mut meta_guard)?;
+
+        // Recovery cannot rebuild the table index, so it is not merged
+        let per_bucket = items_per_bucket(db_state.buf_mgr.page_size());
+        while self.file_id != TABLE_REL_ID {
+            let num_buckets = self.num_buckets(&meta_guard)?;
+            if num_buckets <= INIT_N
+                || count * MERGE_LOAD as u64 >= num_buckets * per_bucket as u64
+            {
+                break;
+            }
+            self.merge(&mut meta_guard, db_state)?;
+        }
+        Ok(())
+    }
+
     fn key_desc(&self) -> TupleDesc {
         self.key_desc.clone()
     }
+
+    fn item_desc(&self) -> TupleDesc {
+        TupleDesc::new(
+            [self.key_desc.attr_types(), self.include_desc.attr_types()]
+                .concat(),
+            [self.key_desc.attr_names(), self.include_desc.attr_names()]
+                .concat(),
+        )
+    }
 }
 
 impl HashIndex {
@@ -83,6 +159,16 @@ impl HashIndex {
         rel_id: ID,
         key_desc: TupleDesc,
         db_state: &mut DbState,
+    ) -> Result<HashIndex> {
+        HashIndex::new_covering(rel_id, key_desc, TupleDesc::empty(), db_state)
+    }
+
+    /// Index that also stores the include_desc columns of each tuple
+    pub fn new_covering(
+        rel_id: ID,
+        key_desc: TupleDesc,
+        include_desc: TupleDesc,
+        db_state: &mut DbState,
     ) -> Result<HashIndex> {
         let file_id = db_state.meta.get_new_id();
         let overflow_file_id = db_state.meta.get_new_id();
@@ -91,11 +177,79 @@ impl HashIndex {
             rel_id,
             key_desc,
             overflow_file_id,
+            include_desc,
         };
         index.write_new(&mut db_state.buf_mgr)?;
         Ok(index)
     }
 
+    /// Create an index of items with enough buckets for all of them up
+    /// front, so building it does not split buckets one at a time
+    pub fn build(
+        rel_id: ID,
+        key_desc: TupleDesc,
+        include_desc: TupleDesc,
+        items: Vec<(TupleData, TuplePtr)>,
+        db_state: &mut DbState,
+    ) -> Result<HashIndex> {
+        let index =
+            HashIndex::new_covering(rel_id, key_desc, include_desc, db_state)?;
+        let items = items
+            .into_iter()
+            .map(|(data, ptr)| index.new_item(&data, ptr))
+            .collect::<Result<Vec<_>>>()?;
+        let meta = db_state.buf_mgr.get_buf(&index.meta_key())?;
+        let mut meta_guard = meta.write().unwrap();
+
+        // Leave a quarter of each bucket for later inserts
+        let page_size = db_state.buf_mgr.page_size();
+        let include_len = match items.len() {
+            0 => 0,
+            len => items.iter().map(|item| item.include.len()).sum::<usize>()
+                / len,
+        };
+        let per_bucket = items_per_page(page_size, ITEM_LEN + include_len)
+            .min(items_per_bucket(page_size))
+            * 3
+            / 4;
+        let num_buckets =
+            (items.len().div_ceil(per_bucket.max(1)) as u64).max(INIT_N);
+        let level = 63 - num_buckets.leading_zeros();
+        let next = BufKey::new(
+            index.file_id,
+            num_buckets - INIT_N.pow(level) + 1,
+            BufType::Data,
+        );
+        for offset in INIT_N + 1..num_buckets + 1 {
+            HashBucket::new(
+                BufKey::new(index.file_id, offset, BufType::Data),
+                index.overflow_file_id,
+                &mut db_state.buf_mgr,
+            )?;
+        }
+        meta_guard.write_tuple_data(
+            &bincode::serialize(&next)?, Some(&index.next_ptr()), None)?;
+        meta_guard.write_tuple_data(
+            &bincode::serialize(&level)?, Some(&index.level_ptr()), None)?;
+        index.set_count(items.len() as u64, &mut meta_guard)?;
+
+        // Each bucket is written once, in file order
+        let mut buckets: BTreeMap<u64, Vec<HashItem>> = BTreeMap::new();
+        for item in items.into_iter() {
+            let bucket = index.get_bucket(item.hash, &next, level);
+            buckets.entry(bucket.buf_key.offset).or_default().push(item);
+        }
+        for (offset, items) in buckets.into_iter() {
+            let bucket = HashBucket {
+                buf_key: BufKey::new(index.file_id, offset, BufType::Data),
+                overflow_file_id: index.overflow_file_id,
+            };
+            bucket.write_items(items, &mut meta_guard, db_state)?;
+        }
+        drop(meta_guard);
+        Ok(index)
+    }
+
     pub fn new_meta(
         file_id: ID,
         overflow_file_id: ID,
@@ -107,11 +261,19 @@ impl HashIndex {
             overflow_file_id,
             key_desc,
             rel_id: 0,
+            include_desc: TupleDesc::empty(),
         };
         index.write_new(buf_mgr)?;
         Ok(index)
     }
 
+    /// Delete the index files and recreate them empty
+    pub fn reset(&self, buf_mgr: &mut BufMgr) -> Result<()> {
+        buf_mgr.drop_file(self.file_id, BufType::Data)?;
+        buf_mgr.drop_file(self.overflow_file_id, BufType::Data)?;
+        self.write_new(buf_mgr)
+    }
+
     fn write_new(&self, buf_mgr: &mut BufMgr) -> Result<()> {
         let meta_page = buf_mgr.new_buf(&self.meta_key())?;
         let _first_bucket = HashBucket::new(
@@ -145,6 +307,33 @@ impl HashIndex {
             &bincode::serialize(&1u32)?, None, None)?;
         meta_guard.write_tuple_data(
             &bincode::serialize(&self.overflow_file_id)?, None, None)?;
+        meta_guard.write_tuple_data(
+            &bincode::serialize(&0u64)?, None, None)?;
+        meta_guard.write_tuple_data(
+            &bincode::serialize(&NO_PAGE)?, None, None)?;
+        meta_guard.write_tuple_data(
+            &bincode::serialize(&self.include_desc)?, None, None)?;
+        meta_guard.write_tuple_data(
+            &bincode::serialize(&FORMAT_VERSION)?, None, None)?;
+        Ok(())
+    }
+
+    /// Fail if meta is not the meta page of an index of FORMAT_VERSION,
+    /// e.g. one created by an older build
+    pub fn check_format(meta: &BufPage) -> Result<()> {
+        let version = match meta.tuple_count() {
+            METADATA_ITEMS => {
+                let ptr = TuplePtr::new(meta.buf_key, METADATA_ITEMS - 1);
+                bincode::deserialize(meta.get_tuple_data(&ptr)?)?
+            }
+            _ => 0u32,
+        };
+        if version != FORMAT_VERSION {
+            return Err(Error::Internal(format!(
+                "Hash index {} has format version {}, expected {}",
+                meta.buf_key.file_id, version, FORMAT_VERSION
+            )));
+        }
         Ok(())
     }
 
@@ -156,31 +345,116 @@ impl HashIndex {
         ))?;
         let guard = meta_page.read().unwrap();
 
-        assert!(guard.tuple_count() == METADATA_ITEMS);
+        HashIndex::check_format(&guard)?;
         let mut iter = guard.iter();
         let rel_id: ID = bincode::deserialize(iter.next().unwrap())?;
         let key_desc: TupleDesc = bincode::deserialize(iter.next().unwrap())?;
         let _next: BufKey = bincode::deserialize(iter.next().unwrap())?;
         let _level: u32 = bincode::deserialize(iter.next().unwrap())?;
         let overflow_file_id: ID = bincode::deserialize(iter.next().unwrap())?;
+        let _count: u64 = bincode::deserialize(iter.next().unwrap())?;
+        let _free: BufKey = bincode::deserialize(iter.next().unwrap())?;
+        let include_desc: TupleDesc =
+            bincode::deserialize(iter.next().unwrap())?;
 
         Ok(HashIndex {
             file_id,
             rel_id,
             key_desc,
             overflow_file_id,
+            include_desc,
         })
     }
 
-    fn write_item(
+    /// Pointers of every item in the index, in bucket order
+    pub fn all_ptrs(&self, db_state: &mut DbState) -> Result<Vec<TuplePtr>> {
+        let meta = db_state.buf_mgr.get_buf(&self.meta_key())?;
+        let _meta_guard = meta.read().unwrap();
+        let num_buckets = utils::num_pages(
+            &db_state.buf_mgr.key_to_filename(self.meta_key()),
+            db_state.buf_mgr.page_size(),
+        )?;
+
+        let mut result = vec![];
+        for offset in 1..num_buckets + 1 {
+            let bucket = HashBucket {
+                buf_key: BufKey::new(self.file_id, offset, BufType::Data),
+                overflow_file_id: self.overflow_file_id,
+            };
+            result.extend(
+                bucket.get_all_items(db_state)?.into_iter().map(|i| i.ptr));
+        }
+        Ok(result)
+    }
+
+    /// Pointers of the items with key data and their included columns,
+    /// the columns of include_desc
+    pub fn get_included(
         &self,
-        hash: u128,
-        ptr: TuplePtr,
-        bucket: &mut BufPage,
-    ) -> Result<()> {
-        let item = HashItem { hash, ptr };
-        bucket.write_tuple_data(&bincode::serialize(&item)?, None, None)?;
-        Ok(())
+        data: &TupleData,
+        db_state: &mut DbState,
+    ) -> Result<Vec<(TuplePtr, TupleData)>> {
+        self.key_desc.assert_data_len(data)?;
+        let meta = db_state.buf_mgr.get_buf(&self.meta_key())?;
+        let meta_guard = meta.read().unwrap();
+        let next: BufKey =
+            bincode::deserialize(meta_guard.get_tuple_data(&self.next_ptr())?)?;
+        let level: u32 = bincode::deserialize(
+            meta_guard.get_tuple_data(&self.level_ptr())?,
+        )?;
+        let hash = self.hash(data);
+        let bucket = self.get_bucket(hash, &next, level);
+        Ok(bucket
+            .get_items(hash, db_state)?
+            .into_iter()
+            .map(|item| (item.ptr, item.include))
+            .collect())
+    }
+
+    /// Hash and pointer of an item tuple, tuple 0 of bucket and
+    /// overflow pages is the overflow key instead
+    pub fn decode_item(data: &[u8]) -> Result<(u128, TuplePtr)> {
+        let item: HashItem = bincode::deserialize(data)?;
+        Ok((item.hash, item.ptr))
+    }
+
+    /// Compact the slot arrays of bucket and overflow pages, returns
+    /// the number of slots reclaimed. Nothing points into these pages
+    /// except the overflow key, which is always the first tuple.
+    pub fn vacuum(&self, db_state: &mut DbState) -> Result<usize> {
+        let meta = db_state.buf_mgr.get_buf(&self.meta_key())?;
+        let _meta_guard = meta.write().unwrap();
+
+        let mut reclaimed = 0;
+        for file_id in [self.file_id, self.overflow_file_id].iter() {
+            let key = BufKey::new(*file_id, 0, BufType::Data);
+            let num_pages = utils::num_pages(
+                &db_state.buf_mgr.key_to_filename(key),
+                db_state.buf_mgr.page_size(),
+            )?;
+            for offset in 1..num_pages + 1 {
+                let page = db_state
+                    .buf_mgr
+                    .get_buf(&BufKey::new(*file_id, offset, BufType::Data))?;
+                let mut guard = page.write().unwrap();
+                if guard.gap_count() > 0 {
+                    reclaimed += guard.gap_count() as usize;
+                    guard.compact(None)?;
+                }
+            }
+        }
+        Ok(reclaimed)
+    }
+
+    /// Item of data, the columns of item_desc
+    fn new_item(&self, data: &TupleData, ptr: TuplePtr) -> Result<HashItem> {
+        let cols = self.item_desc().cols(data)?;
+        let num_keys = self.key_desc.num_attrs() as usize;
+        Ok(HashItem {
+            hash: self.hash(&cols[..num_keys].concat()),
+            ptr,
+            include: cols[num_keys..].concat(),
+        })
     }
 
     fn get_items(&self, hash: u128, bucket: &BufPage) -> Vec<HashItem> {
@@ -214,7 +488,8 @@ impl HashIndex {
             self.overflow_file_id,
             &mut db_state.buf_mgr,
         )?;
-        next_bucket.split(&new_bucket, (num_buckets * 2) as u128, db_state)?;
+        next_bucket.split(
+            &new_bucket, (num_buckets * 2) as u128, meta, db_state)?;
 
         // Update next and level if necessary
         if new_next.offset > num_buckets {
@@ -234,6 +509,90 @@ impl HashIndex {
         Ok(())
     }
 
+    /// Undo the last split, its new bucket is merged back into the
+    /// bucket it was split from and removed from the end of the file.
+    /// Merges are not logged, recovery rebuilds the index after a crash.
+    fn merge(&self, meta: &mut BufPage, db_state: &mut DbState) -> Result<()> {
+        let mut next: BufKey =
+            bincode::deserialize(meta.get_tuple_data(&self.next_ptr())?)?;
+        let mut level: u32 =
+            bincode::deserialize(meta.get_tuple_data(&self.level_ptr())?)?;
+        if next.offset == 1 {
+            level -= 1;
+            next.offset = INIT_N.pow(level) + 1;
+            meta.write_tuple_data(
+                &bincode::serialize(&level)?,
+                Some(&self.level_ptr()),
+                None,
+            )?;
+        }
+        next.offset -= 1;
+        let last = next.offset + INIT_N.pow(level);
+
+        let bucket = HashBucket {
+            buf_key: next,
+            overflow_file_id: self.overflow_file_id,
+        };
+        let last_bucket = HashBucket {
+            buf_key: BufKey::new(self.file_id, last, BufType::Data),
+            overflow_file_id: self.overflow_file_id,
+        };
+        let items = last_bucket.take_all_items(meta, db_state)?;
+        bucket.write_items(items, meta, db_state)?;
+        // next never points past the end of the file
+        meta.write_tuple_data(
+            &bincode::serialize(&next)?,
+            Some(&self.next_ptr()),
+            None,
+        )?;
+        db_state.buf_mgr.truncate_file(self.file_id, BufType::Data, last)?;
+        Ok(())
+    }
+
+    fn num_buckets(&self, meta: &BufPage) -> Result<u64> {
+        let next: BufKey =
+            bincode::deserialize(meta.get_tuple_data(&self.next_ptr())?)?;
+        let level: u32 =
+            bincode::deserialize(meta.get_tuple_data(&self.level_ptr())?)?;
+        Ok(INIT_N.pow(level) + next.offset - 1)
+    }
+
+    /// Number of items in the index
+    fn count(&self, meta: &BufPage) -> Result<u64> {
+        Ok(bincode::deserialize(meta.get_tuple_data(&self.count_ptr())?)?)
+    }
+
+    /// Store the descs of the index after rel columns are renamed
+    pub fn write_descs(
+        &self,
+        key_desc: &TupleDesc,
+        include_desc: &TupleDesc,
+        buf_mgr: &mut BufMgr,
+    ) -> Result<()> {
+        let meta = buf_mgr.get_buf(&self.meta_key())?;
+        let mut guard = meta.write().unwrap();
+        guard.write_tuple_data(
+            &bincode::serialize(key_desc)?,
+            Some(&TuplePtr::new(self.meta_key(), 1)),
+            None,
+        )?;
+        guard.write_tuple_data(
+            &bincode::serialize(include_desc)?,
+            Some(&TuplePtr::new(self.meta_key(), 7)),
+            None,
+        )?;
+        Ok(())
+    }
+
+    fn set_count(&self, count: u64, meta: &mut BufPage) -> Result<()> {
+        meta.write_tuple_data(
+            &bincode::serialize(&count)?,
+            Some(&self.count_ptr()),
+            None,
+        )?;
+        Ok(())
+    }
+
     fn get_bucket(
         &self,
         hash: u128,
@@ -268,12 +627,30 @@ impl HashIndex {
     fn level_ptr(&self) -> TuplePtr {
         TuplePtr::new(self.meta_key(), 3)
     }
+
+    fn count_ptr(&self) -> TuplePtr {
+        TuplePtr::new(self.meta_key(), 5)
+    }
+}
+
+/// Head of the list of overflow pages that can be reused, the pages are
+/// linked through their overflow key
+fn free_list_ptr(meta: &BufPage) -> TuplePtr {
+    TuplePtr::new(meta.buf_key, 6)
+}
+
+/// Empty a bucket or overflow page, keeping only its overflow key
+fn reset_page(page: &mut BufPage, overflow_key: &BufKey) -> Result<()> {
+    page.clear();
+    page.write_tuple_data(&bincode::serialize(overflow_key)?, None, None)?;
+    Ok(())
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 struct HashItem {
     hash: u128,
     ptr: TuplePtr,
+    include: TupleData,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -291,10 +668,7 @@ impl HashBucket {
         let page = buf_mgr.new_buf(&buf_key)?;
         let mut guard = page.write().unwrap();
         assert_eq!(guard.tuple_count(), 0);
-        guard.write_tuple_data(
-            &bincode::serialize(&BufKey::new(0, 0, BufType::Data))?,
-            None,
-            None)?;
+        guard.write_tuple_data(&bincode::serialize(&NO_PAGE)?, None, None)?;
         Ok(Self {
             buf_key,
             overflow_file_id,
@@ -305,6 +679,7 @@ impl HashBucket {
     fn write_items(
         &self,
         items: Vec<HashItem>,
+        meta: &mut BufPage,
         db_state: &mut DbState
     ) -> Result<bool> {
         let bucket = db_state.buf_mgr.get_buf(&self.buf_key)?;
@@ -314,14 +689,14 @@ impl HashBucket {
             Ok(false)
         } else {
             let mut overflow_key = self.get_overflow_key(
-                &mut bucket_guard, db_state)?;
+                &mut bucket_guard, meta, db_state)?;
             loop {
                 let overflow = db_state.buf_mgr.get_buf(&overflow_key)?;
                 let mut guard = overflow.write().unwrap();
                 items = self.write_items_to_page(items, &mut guard)?;
                 if items.len() > 0 {
                     overflow_key = self.get_overflow_key(
-                        &mut guard, db_state)?;
+                        &mut guard, meta, db_state)?;
                 } else {
                     break;
                 }
@@ -337,29 +712,30 @@ impl HashBucket {
         items: Vec<HashItem>,
         page: &mut BufPage,
     ) -> Result<Vec<HashItem>> {
-        items
-            .into_iter()
-            .filter_map(|item| {
-                if self.get_items_count(&page) >= ITEMS_PER_BUCKET {
-                    Some(Ok(item))
-                } else {
-                    match bincode::serialize(&item) {
-                        Ok(data) => {
-                            match page.write_tuple_data(&data, None, None) {
-                                Ok(_) => None,
-                                Err(err) => Some(Err(Error::from(err))),
-                            }
-                        }
-                        Err(err) => Some(Err(Error::from(err))),
-                    }
-                }
-            })
-            .collect::<Result<Vec<HashItem>>>()
+        let capacity = items_per_bucket(page.page_size());
+        let mut left = vec![];
+        for item in items.into_iter() {
+            let data = bincode::serialize(&item)?;
+            let count = self.get_items_count(page);
+            if count < capacity && page.available_data_space() >= data.len() {
+                page.write_tuple_data(&data, None, None)?;
+            } else if count == 0 {
+                // The item would not fit in any overflow page either
+                return Err(Error::Internal(format!(
+                    "Index item of {} bytes does not fit in a page",
+                    data.len()
+                )));
+            } else {
+                left.push(item);
+            }
+        }
+        Ok(left)
     }
 
     fn get_overflow_key(
         &self,
         page: &mut BufPage,
+        meta: &mut BufPage,
         db_state: &mut DbState,
     ) -> Result<BufKey> {
         let overflow_key: BufKey = bincode::deserialize(
@@ -367,19 +743,7 @@ impl HashBucket {
         if self.is_valid_overflow(&overflow_key) {
             Ok(overflow_key)
         } else {
-            let overflow_filename = db_state.buf_mgr.key_to_filename(
-                BufKey::new(self.overflow_file_id, 0, BufType::Data));
-            let overflow_num_pages = utils::num_pages(&overflow_filename)?;
-            let overflow_key = BufKey::new(
-                self.overflow_file_id,
-                overflow_num_pages,
-                BufType::Data,
-            ).inc_offset();
-            HashBucket::new(
-                overflow_key.clone(),
-                self.overflow_file_id,
-                &mut db_state.buf_mgr,
-            )?;
+            let overflow_key = self.new_overflow(meta, db_state)?;
             page.write_tuple_data(
                 &bincode::serialize(&overflow_key)?,
                 Some(&TuplePtr::new(page.buf_key.clone(), 0)),
@@ -389,6 +753,61 @@ impl HashBucket {
         }
     }
 
+    /// Take a page from the free list, or add one to the overflow file
+    fn new_overflow(
+        &self,
+        meta: &mut BufPage,
+        db_state: &mut DbState,
+    ) -> Result<BufKey> {
+        let free_ptr = free_list_ptr(meta);
+        let free: BufKey =
+            bincode::deserialize(meta.get_tuple_data(&free_ptr)?)?;
+        if self.is_valid_overflow(&free) {
+            let page = db_state.buf_mgr.get_buf(&free)?;
+            let mut guard = page.write().unwrap();
+            let next_free = guard.iter().next().unwrap().to_vec();
+            meta.write_tuple_data(&next_free, Some(&free_ptr), None)?;
+            reset_page(&mut guard, &NO_PAGE)?;
+            return Ok(free);
+        }
+
+        let overflow_filename = db_state.buf_mgr.key_to_filename(
+            BufKey::new(self.overflow_file_id, 0, BufType::Data));
+        let overflow_num_pages = utils::num_pages(
+            &overflow_filename,
+            db_state.buf_mgr.page_size(),
+        )?;
+        let overflow_key = BufKey::new(
+            self.overflow_file_id,
+            overflow_num_pages,
+            BufType::Data,
+        ).inc_offset();
+        HashBucket::new(
+            overflow_key,
+            self.overflow_file_id,
+            &mut db_state.buf_mgr,
+        )?;
+        Ok(overflow_key)
+    }
+
+    /// Put an overflow page that is no longer linked on the free list
+    fn free_overflow(
+        &self,
+        page: &mut BufPage,
+        meta: &mut BufPage,
+    ) -> Result<()> {
+        let free_ptr = free_list_ptr(meta);
+        let head: BufKey =
+            bincode::deserialize(meta.get_tuple_data(&free_ptr)?)?;
+        reset_page(page, &head)?;
+        meta.write_tuple_data(
+            &bincode::serialize(&page.buf_key)?,
+            Some(&free_ptr),
+            None,
+        )?;
+        Ok(())
+    }
+
     fn get_items(
         &self,
         hash: u128,
@@ -435,55 +854,117 @@ impl HashBucket {
         Ok((overflow_key, items))
     }
 
-    fn split(
+    fn get_all_items(&self, db_state: &mut DbState) -> Result<Vec<HashItem>> {
+        let mut result = vec![];
+        let mut key = self.buf_key;
+        while self.is_valid_overflow(&key) {
+            let page = db_state.buf_mgr.get_buf(&key)?;
+            let guard = page.read().unwrap();
+            let mut iter = guard.iter();
+            key = bincode::deserialize(iter.next().unwrap())?;
+            for tuple in iter {
+                result.push(bincode::deserialize::<HashItem>(tuple)?);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Remove the given items from the bucket and its overflow pages,
+    /// returns the number of items removed. The chain is compacted when
+    /// the items left fit in fewer pages.
+    fn remove_items(
         &self,
-        other: &HashBucket,
-        modulo: u128,
+        items: &[HashItem],
+        meta: &mut BufPage,
         db_state: &mut DbState,
-    ) -> Result<()> {
-        let bucket = db_state.buf_mgr.get_buf(&self.buf_key)?;
-        let mut bucket_guard = bucket.write().unwrap();
-        let (mut overflow_key, mut split_items) =
-            self.get_split_items_from_page(&mut bucket_guard, modulo)?;
-
-        while self.is_valid_overflow(&overflow_key) {
-            let page = db_state.buf_mgr.get_buf(&overflow_key)?;
+    ) -> Result<usize> {
+        let mut removed = 0;
+        let (mut pages, mut left) = (0, 0);
+        let mut key = self.buf_key;
+        while self.is_valid_overflow(&key) {
+            let page = db_state.buf_mgr.get_buf(&key)?;
             let mut guard = page.write().unwrap();
-            let (new_overflow_key, mut new_split_items) =
-                self.get_split_items_from_page(&mut guard, modulo)?;
-            overflow_key = new_overflow_key;
-            split_items.append(&mut new_split_items);
+            let mut ptrs = vec![];
+            {
+                let all_ptrs = guard.get_all_ptrs();
+                let mut iter = guard.iter().zip(all_ptrs);
+                key = bincode::deserialize(iter.next().unwrap().0)?;
+                // Included columns are not compared
+                for (tuple, ptr) in iter {
+                    let item = bincode::deserialize::<HashItem>(tuple)?;
+                    let found = items
+                        .iter()
+                        .any(|i| (i.hash, i.ptr) == (item.hash, item.ptr));
+                    if found {
+                        ptrs.push(ptr);
+                    }
+                }
+            }
+            // Remove from the back so the last pointer is freed instead of
+            // leaving a gap whenever possible
+            for ptr in ptrs.iter().rev() {
+                guard.remove_tuple(ptr, None)?;
+            }
+            removed += ptrs.len();
+            pages += 1;
+            left += self.get_items_count(&guard);
         }
-        other.write_items(split_items, db_state)?;
-        Ok(())
+
+        let per_page = items_per_bucket(db_state.buf_mgr.page_size());
+        if removed > 0 && left.div_ceil(per_page).max(1) < pages {
+            let items = self.take_all_items(meta, db_state)?;
+            self.write_items(items, meta, db_state)?;
+        }
+        Ok(removed)
     }
 
-    fn get_split_items_from_page(
+    /// Remove every item of the bucket and its overflow pages, the
+    /// overflow pages are put on the free list
+    fn take_all_items(
         &self,
-        page: &mut BufPage,
-        modulo: u128,
-    ) -> Result<(BufKey, Vec<HashItem>)> {
-        assert!(page.tuple_count() > 1);
-        let (overflow_key, items, ptrs) = {
-            let all_ptrs = page.get_all_ptrs();
-            let mut iter = page.iter().zip(all_ptrs.into_iter());
-            let overflow_key: BufKey = bincode::deserialize(
-                iter.next().unwrap().0)?;
-            let mut items = vec![];
-            let mut ptrs = vec![];
-            for (tuple, ptr) in iter {
-                let item = bincode::deserialize::<HashItem>(tuple)?;
-                if item.hash % modulo + 1 != self.buf_key.offset as u128 {
-                    items.push(item);
-                    ptrs.push(ptr);
+        meta: &mut BufPage,
+        db_state: &mut DbState,
+    ) -> Result<Vec<HashItem>> {
+        let mut result = vec![];
+        let mut key = self.buf_key;
+        while self.is_valid_overflow(&key) {
+            let page = db_state.buf_mgr.get_buf(&key)?;
+            let mut guard = page.write().unwrap();
+            let next = {
+                let mut iter = guard.iter();
+                let next: BufKey = bincode::deserialize(iter.next().unwrap())?;
+                for tuple in iter {
+                    result.push(bincode::deserialize::<HashItem>(tuple)?);
                 }
+                next
+            };
+            if key == self.buf_key {
+                reset_page(&mut guard, &NO_PAGE)?;
+            } else {
+                self.free_overflow(&mut guard, meta)?;
             }
-            (overflow_key, items, ptrs)
-        };
-        for ptr in ptrs.iter() {
-            page.remove_tuple(ptr, None)?;
+            key = next;
         }
-        Ok((overflow_key, items))
+        Ok(result)
+    }
+
+    /// Move the items whose bucket is other under modulo, both chains
+    /// are rewritten without gaps
+    fn split(
+        &self,
+        other: &HashBucket,
+        modulo: u128,
+        meta: &mut BufPage,
+        db_state: &mut DbState,
+    ) -> Result<()> {
+        let offset = self.buf_key.offset as u128;
+        let (items, split_items): (Vec<_>, Vec<_>) = self
+            .take_all_items(meta, db_state)?
+            .into_iter()
+            .partition(|item| item.hash % modulo + 1 == offset);
+        self.write_items(items, meta, db_state)?;
+        other.write_items(split_items, meta, db_state)?;
+        Ok(())
     }
 
     fn get_items_count(&self, page: &BufPage) -> usize {
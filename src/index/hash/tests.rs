@@ -1,9 +1,13 @@
+use bincode;
 use data_type::DataType;
+use db_state::DbState;
 use index::Index;
-use super::{HashIndex, HashBucket, HashItem, ITEMS_PER_BUCKET};
-use storage::{BufKey, BufType};
+use super::{free_list_ptr, items_per_bucket, HashIndex, HashBucket, HashItem};
+use super::{INIT_N, ITEM_LEN, OVERFLOW_KEY_LEN};
+use storage::{BufKey, BufPage, BufType, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
 use tuple::{TupleDesc, TuplePtr};
 use test_utils::{setup, teardown};
+use utils;
 
 #[test]
 fn test_insert_and_get_hash() {
@@ -29,15 +33,13 @@ fn test_insert_and_get_hash() {
 
 #[test]
 fn test_split_hash() {
-    use bincode;
-
     let mut db_state = setup("test_split_hash");
 
     let key_desc = TupleDesc::new(vec![DataType::U32], vec![""]);
     let index = HashIndex::new(0, key_desc, &mut db_state).unwrap();
 
     let test_buf_key = BufKey::new(0, 0, BufType::Data);
-    let bucket_one: Vec<u32> = (0..ITEMS_PER_BUCKET * 2)
+    let bucket_one: Vec<u32> = (0..items_per_bucket(DEFAULT_PAGE_SIZE) * 2)
         .filter_map(|i| {
             if index.hash(&bincode::serialize(&(i as u32)).unwrap()) % 2 == 0 {
                 Some(i as u32)
@@ -61,7 +63,7 @@ fn test_split_hash() {
         })
         .collect();
     // Make sure the index will split
-    assert!(bucket_one.len() > ITEMS_PER_BUCKET);
+    assert!(bucket_one.len() > items_per_bucket(DEFAULT_PAGE_SIZE));
     let items = bucket_one_data
         .iter()
         .map(|data| (data.clone(), TuplePtr::new(test_buf_key.clone(), 0)))
@@ -97,6 +99,8 @@ fn test_split_hash() {
         })
         .collect::<Vec<_>>();
 
+    drop(guard);
+    drop(meta_page);
     teardown(db_state);
 
     assert_eq!(next, BufKey::new(index.file_id, 2, BufType::Data));
@@ -117,23 +121,24 @@ fn test_split_hash() {
 fn test_insert_and_get_bucket() {
     let mut db_state = setup("test_insert_to_bucket");
 
-    let bucket_file_id = db_state.meta.get_new_id();
-    let overflow_file_id = db_state.meta.get_new_id();
-    let _ = db_state.buf_mgr.new_buf(
-        &BufKey::new(overflow_file_id, 0, BufType::Data),
-    ).unwrap();
-    let bucket = HashBucket::new(
-        BufKey::new(bucket_file_id, 0, BufType::Data),
-        overflow_file_id,
-        &mut db_state.buf_mgr,
-    ).unwrap();
-    let items = (0..ITEMS_PER_BUCKET * 3)
+    let key_desc = TupleDesc::new(vec![DataType::U32], vec![""]);
+    let index = HashIndex::new(0, key_desc, &mut db_state).unwrap();
+    let meta = db_state.buf_mgr.get_buf(&index.meta_key()).unwrap();
+    let mut meta_guard = meta.write().unwrap();
+    let bucket = HashBucket {
+        buf_key: BufKey::new(index.file_id, 1, BufType::Data),
+        overflow_file_id: index.overflow_file_id,
+    };
+    let items = (0..items_per_bucket(DEFAULT_PAGE_SIZE) * 3)
         .map(|_| HashItem {
             hash: 0,
             ptr: TuplePtr::new(bucket.buf_key.clone(), 0),
+            include: vec![],
         })
         .collect::<Vec<_>>();
-    bucket.write_items(items.clone(), &mut db_state).unwrap();
+    bucket
+        .write_items(items.clone(), &mut meta_guard, &mut db_state)
+        .unwrap();
     let returned_items = bucket.get_items(0, &mut db_state).unwrap();
 
     let mut buf_key = bucket.buf_key.clone();
@@ -145,6 +150,8 @@ fn test_insert_and_get_bucket() {
         overflow_count += 1;
     };
 
+    drop(meta_guard);
+    drop(meta);
     teardown(db_state);
 
     items
@@ -153,3 +160,235 @@ fn test_insert_and_get_bucket() {
         .for_each(|(item1, item2)| assert_eq!(item1, item2));
     assert_eq!(overflow_count, 3);
 }
+
+#[test]
+fn test_merge_hash() {
+    let mut db_state = setup("test_merge_hash");
+
+    let key_desc = TupleDesc::new(vec![DataType::U32], vec![""]);
+    let index = HashIndex::new(0, key_desc, &mut db_state).unwrap();
+    let ptr = TuplePtr::new(BufKey::new(0, 1, BufType::Data), 0);
+    let items = (0..items_per_bucket(DEFAULT_PAGE_SIZE) as u32 * 8)
+        .map(|i| (bincode::serialize(&i).unwrap(), ptr))
+        .collect::<Vec<_>>();
+    index.insert(&mut items.clone().into_iter(), &mut db_state).unwrap();
+    let grown = num_buckets(&index, &mut db_state);
+
+    // Keep a few items, the rest is removed
+    index.remove(&mut items[10..].iter().cloned(), &mut db_state).unwrap();
+    let shrunk = num_buckets(&index, &mut db_state);
+    let file_pages = utils::num_pages(
+        &db_state.buf_mgr.key_to_filename(index.meta_key()),
+        DEFAULT_PAGE_SIZE,
+    )
+    .unwrap();
+    let found = items[..10]
+        .iter()
+        .map(|(data, _)| index.get(data, &mut db_state).unwrap().len())
+        .collect::<Vec<_>>();
+    let all = index.all_ptrs(&mut db_state).unwrap().len();
+
+    teardown(db_state);
+
+    assert!(grown > 8);
+    assert_eq!(shrunk, INIT_N);
+    assert_eq!(file_pages, INIT_N);
+    assert_eq!(found, vec![1; 10]);
+    assert_eq!(all, 10);
+}
+
+#[test]
+fn test_overflow_free_list() {
+    let mut db_state = setup("test_overflow_free_list");
+
+    let key_desc = TupleDesc::new(vec![DataType::U32], vec![""]);
+    let index = HashIndex::new(0, key_desc, &mut db_state).unwrap();
+    let meta = db_state.buf_mgr.get_buf(&index.meta_key()).unwrap();
+    let mut meta_guard = meta.write().unwrap();
+    let bucket = HashBucket {
+        buf_key: BufKey::new(index.file_id, 1, BufType::Data),
+        overflow_file_id: index.overflow_file_id,
+    };
+    let item = |i| HashItem {
+        hash: 0,
+        ptr: TuplePtr::new(BufKey::new(0, 1, BufType::Data), i),
+        include: vec![],
+    };
+    let items = (0..items_per_bucket(DEFAULT_PAGE_SIZE) * 3)
+        .map(item)
+        .collect::<Vec<_>>();
+    bucket
+        .write_items(items.clone(), &mut meta_guard, &mut db_state)
+        .unwrap();
+    let overflow_filename = db_state.buf_mgr.key_to_filename(
+        BufKey::new(index.overflow_file_id, 0, BufType::Data));
+    let overflow_pages = || {
+        utils::num_pages(&overflow_filename, DEFAULT_PAGE_SIZE).unwrap()
+    };
+    let before = overflow_pages();
+
+    // The items left fit in the bucket page, both overflow pages are freed
+    bucket
+        .remove_items(&items[10..], &mut meta_guard, &mut db_state)
+        .unwrap();
+    let chain = bucket_chain(&bucket, &mut db_state);
+    let free: BufKey = bincode::deserialize(
+        meta_guard.get_tuple_data(&free_list_ptr(&meta_guard)).unwrap(),
+    )
+    .unwrap();
+
+    // Overflow pages are reused instead of added to the file
+    bucket
+        .write_items(items[10..].to_vec(), &mut meta_guard, &mut db_state)
+        .unwrap();
+    let after = overflow_pages();
+    let returned = bucket.get_items(0, &mut db_state).unwrap();
+
+    drop(meta_guard);
+    drop(meta);
+    teardown(db_state);
+
+    assert_eq!(before, 2);
+    assert_eq!(chain, 1);
+    assert!(bucket.is_valid_overflow(&free));
+    assert_eq!(after, 2);
+    assert_eq!(returned.len(), items.len());
+}
+
+#[test]
+fn test_build_hash() {
+    let mut db_state = setup("test_build_hash");
+
+    let key_desc = TupleDesc::new(vec![DataType::U32], vec![""]);
+    let ptr = TuplePtr::new(BufKey::new(0, 1, BufType::Data), 0);
+    let items = (0..items_per_bucket(DEFAULT_PAGE_SIZE) as u32 * 10)
+        .map(|i| (bincode::serialize(&i).unwrap(), ptr))
+        .collect::<Vec<_>>();
+    let index = HashIndex::build(
+        0, key_desc, TupleDesc::empty(), items.clone(), &mut db_state)
+        .unwrap();
+    let buckets = num_buckets(&index, &mut db_state);
+    let found = items
+        .iter()
+        .all(|(data, _)| index.get(data, &mut db_state).unwrap().len() == 1);
+    let loaded = HashIndex::load(index.file_id, &mut db_state)
+        .unwrap()
+        .all_ptrs(&mut db_state)
+        .unwrap()
+        .len();
+
+    teardown(db_state);
+
+    // 10 full buckets at 3/4 of their capacity
+    assert_eq!(buckets, 14);
+    assert!(found);
+    assert_eq!(loaded, items.len());
+}
+
+fn num_buckets(index: &HashIndex, db_state: &mut DbState) -> u64 {
+    let meta = db_state.buf_mgr.get_buf(&index.meta_key()).unwrap();
+    let guard = meta.read().unwrap();
+    index.num_buckets(&guard).unwrap()
+}
+
+fn bucket_chain(bucket: &HashBucket, db_state: &mut DbState) -> usize {
+    let mut key = bucket.buf_key;
+    let mut pages = 0;
+    while bucket.is_valid_overflow(&key) {
+        let page = db_state.buf_mgr.get_buf(&key).unwrap();
+        let guard = page.read().unwrap();
+        key = bucket.get_items_from_page(0, &guard).unwrap().0;
+        pages += 1;
+    }
+    pages
+}
+
+#[test]
+fn test_items_per_bucket() {
+    let key = BufKey::new(0, 1, BufType::Data);
+    let item = HashItem {
+        hash: 0,
+        ptr: TuplePtr::new(key, 0),
+        include: vec![],
+    };
+    let item_data = bincode::serialize(&item).unwrap();
+    let key_data = bincode::serialize(&key).unwrap();
+    assert_eq!(item_data.len(), ITEM_LEN);
+    assert_eq!(key_data.len(), OVERFLOW_KEY_LEN);
+
+    for page_size in [DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE].iter() {
+        let buf = BufPage::default_buf(*page_size);
+        let mut page = BufPage::load_from(&buf, &key).unwrap();
+        page.write_tuple_data(&key_data, None, None).unwrap();
+        for _ in 0..items_per_bucket(*page_size) {
+            page.write_tuple_data(&item_data, None, None).unwrap();
+        }
+        assert!(page.write_tuple_data(&item_data, None, None).is_err());
+    }
+}
+
+#[test]
+fn test_covering_hash() {
+    let mut db_state = setup("test_covering_hash");
+
+    let key_desc = TupleDesc::new(vec![DataType::U32], vec!["id"]);
+    let include_desc = TupleDesc::new(vec![DataType::VarChar], vec!["name"]);
+    let index =
+        HashIndex::new_covering(0, key_desc, include_desc, &mut db_state)
+            .unwrap();
+    let item_desc = index.item_desc();
+    let ptr = |i| TuplePtr::new(BufKey::new(0, 1, BufType::Data), i);
+    let item = |id: &str, name: &str| {
+        item_desc.create_tuple_data(vec![id.to_string(), name.to_string()])
+    };
+    let items = vec![
+        (item("1", "one"), ptr(0)),
+        (item("1", "uno"), ptr(1)),
+        (item("2", "two"), ptr(2)),
+    ];
+    index.insert(&mut items.clone().into_iter(), &mut db_state).unwrap();
+    let key = bincode::serialize(&1u32).unwrap();
+    let mut included = index.get_included(&key, &mut db_state).unwrap();
+    let loaded = HashIndex::load(index.file_id, &mut db_state).unwrap();
+    // Included columns do not have to match to remove an item
+    index
+        .remove(&mut vec![(item("1", ""), ptr(0))].into_iter(), &mut db_state)
+        .unwrap();
+    let left = index.get(&key, &mut db_state).unwrap();
+
+    teardown(db_state);
+
+    included.sort();
+    let names = included
+        .into_iter()
+        .map(|(ptr, data)| (ptr, loaded.include_desc.decode(&data).unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec![
+        (ptr(0), vec!["one".to_string()]),
+        (ptr(1), vec!["uno".to_string()]),
+    ]);
+    assert_eq!(loaded.item_desc().attr_names(), vec!["id", "name"]);
+    assert_eq!(left, vec![ptr(1)]);
+}
+
+#[test]
+fn test_load_old_format() {
+    let mut db_state = setup("test_load_old_format");
+
+    let key_desc = TupleDesc::new(vec![DataType::U32], vec![""]);
+    let index = HashIndex::new(0, key_desc, &mut db_state).unwrap();
+    let current = HashIndex::load(index.file_id, &mut db_state).is_ok();
+    {
+        // Drop the version item, as in meta pages of older builds
+        let meta = db_state.buf_mgr.get_buf(&index.meta_key()).unwrap();
+        let mut guard = meta.write().unwrap();
+        let version_ptr = TuplePtr::new(guard.buf_key, 8);
+        guard.remove_tuple(&version_ptr, None).unwrap();
+    }
+    let old = HashIndex::load(index.file_id, &mut db_state).is_err();
+
+    teardown(db_state);
+
+    assert!(current);
+    assert!(old);
+}
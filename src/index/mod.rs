@@ -21,7 +21,17 @@ pub trait Index {
         db_state: &mut DbState,
     ) -> Result<()>;
 
+    fn remove(
+        &self,
+        items: &mut dyn Iterator<Item=(TupleData, TuplePtr)>,
+        db_state: &mut DbState,
+    ) -> Result<()>;
+
     fn key_desc(&self) -> TupleDesc;
+
+    /// Columns of the data given to insert and remove, the key columns
+    /// followed by the included columns stored in the index
+    fn item_desc(&self) -> TupleDesc;
 }
 
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
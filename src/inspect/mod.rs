@@ -0,0 +1,164 @@
+use bincode;
+use db_state::DbState;
+use error::Result;
+use index::HashIndex;
+use internal_types::ID;
+use log::{LogEntry, LogMgr, LOG_REL_ID};
+use meta::{TABLE_OVERFLOW_ID, TABLE_REL_ID};
+use rel::Rel;
+use std::io::Write;
+use storage::{BufKey, BufPage, BufType, LOG_SEGMENT_PAGES};
+use tuple::{TupleDesc, TuplePtr};
+use utils;
+
+#[cfg(test)]
+mod tests;
+
+/// How the tuples of a file are decoded
+#[derive(Debug)]
+enum FileKind {
+    Table(TupleDesc),
+    Index,
+    Log,
+    Raw,
+}
+
+/// Print the header and slots of every page of a file, with its tuples
+/// decoded as table tuples, hash index items or log entries
+pub fn dump(
+    file_id: ID,
+    db_state: &mut DbState,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let kind = file_kind(file_id, db_state)?;
+    writeln!(out, "File {}: {:?}", file_id, kind)?;
+
+    let page_size = db_state.buf_mgr.page_size();
+    let filename = db_state
+        .buf_mgr
+        .key_to_filename(BufKey::new(file_id, 0, BufType::Data));
+    let mut keys: Vec<BufKey> = (0..utils::num_pages(&filename, page_size)? + 1)
+        .map(|offset| BufKey::new(file_id, offset, BufType::Data))
+        .collect();
+    if let FileKind::Log = kind {
+        let data_dir = db_state.buf_mgr.data_dir();
+        for segment in LogMgr::segments_in(&format!("{}/wal", data_dir))? {
+            let filename = BufKey::segment_filename(data_dir.clone(), segment);
+            let num_pages = utils::file_len(&filename)? / page_size as u64;
+            keys.extend((0..num_pages).map(|page_idx| {
+                BufKey::new(
+                    LOG_REL_ID,
+                    segment * LOG_SEGMENT_PAGES + page_idx,
+                    BufType::Log,
+                )
+            }));
+        }
+    }
+
+    for key in keys {
+        match db_state.buf_mgr.get_buf(&key) {
+            Ok(page) => dump_page(&page.read().unwrap(), &kind, out)?,
+            Err(e) => writeln!(out, "{:?}: {:?}", key, e)?,
+        }
+    }
+    Ok(())
+}
+
+fn file_kind(file_id: ID, db_state: &mut DbState) -> Result<FileKind> {
+    if file_id == LOG_REL_ID {
+        return Ok(FileKind::Log);
+    }
+    if file_id == TABLE_REL_ID || file_id == TABLE_OVERFLOW_ID {
+        return Ok(FileKind::Index);
+    }
+    for rel_id in utils::get_all_table_ids(db_state)? {
+        let rel = Rel::load(rel_id, BufType::Data, db_state)?;
+        if rel_id == file_id {
+            return Ok(FileKind::Table(rel.tuple_desc()));
+        }
+        for info in rel.indices() {
+            let index = HashIndex::load(info.file_id, db_state)?;
+            if file_id == index.file_id || file_id == index.overflow_file_id {
+                return Ok(FileKind::Index);
+            }
+        }
+    }
+    Ok(FileKind::Raw)
+}
+
+fn dump_page(
+    page: &BufPage,
+    kind: &FileKind,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let key = page.buf_key;
+    writeln!(
+        out,
+        "{:?} lsn: {}, upper_ptr: {}, lower_ptr: {}, gap_count: {}",
+        key,
+        page.lsn,
+        page.upper_ptr,
+        page.lower_ptr,
+        page.gap_count()
+    )?;
+    let slots = match page.slots() {
+        Ok(slots) => slots,
+        Err(e) => return Ok(writeln!(out, "  Invalid slots: {:?}", e)?),
+    };
+    // Page 0 of tables and indexes has their metadata
+    let kind = match (kind, key.buf_type, key.offset) {
+        (&FileKind::Table(_), BufType::Data, 0)
+        | (&FileKind::Index, BufType::Data, 0) => &FileKind::Raw,
+        _ => kind,
+    };
+
+    for (offset, (start, end)) in slots.into_iter().enumerate() {
+        if (start, end) == (0, 0) {
+            writeln!(out, "  [{}] empty", offset)?;
+            continue;
+        }
+        let ptr = TuplePtr::new(key, offset);
+        let tuple = match page.get_tuple_data(&ptr) {
+            Ok(data) => describe(data, &ptr, kind)
+                .unwrap_or_else(|e| format!("{} ({:?})", hex(data), e)),
+            Err(e) => format!("{:?}", e),
+        };
+        writeln!(out, "  [{}] {}..{} {}", offset, start, end, tuple)?;
+    }
+    Ok(())
+}
+
+/// Decoded tuple, raw tuples are shown in hex
+fn describe(data: &[u8], ptr: &TuplePtr, kind: &FileKind) -> Result<String> {
+    match kind {
+        FileKind::Table(desc) => Ok(desc.decode(data)?.join(", ")),
+        FileKind::Index if ptr.buf_offset == 0 => {
+            let key: BufKey = bincode::deserialize(data)?;
+            Ok(format!("overflow {:?}", key))
+        }
+        FileKind::Index => {
+            let (hash, ptr) = HashIndex::decode_item(data)?;
+            Ok(format!("hash {:032x} -> {:?}", hash, ptr))
+        }
+        FileKind::Log if ptr.buf_key.buf_type == BufType::Log => {
+            let entry = LogEntry::load(data, ptr)?;
+            Ok(format!(
+                "lsn {} {:?} {:?} at {}, {} bytes",
+                entry.header.lsn,
+                entry.header.op,
+                entry.header.buf_key,
+                entry.header.timestamp,
+                entry.data.len()
+            ))
+        }
+        FileKind::Log => {
+            let cp: TuplePtr = bincode::deserialize(data)?;
+            Ok(format!("last checkpoint {:?}", cp))
+        }
+        FileKind::Raw => Ok(hex(data)),
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
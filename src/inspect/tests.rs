@@ -0,0 +1,44 @@
+use data_type::DataType;
+use index::IndexType;
+use inspect::dump;
+use log::LOG_REL_ID;
+use nom_sql::Literal;
+use rel::Rel;
+use test_utils::{setup_no_persist, teardown};
+use tuple::TupleDesc;
+
+#[test]
+fn test_dump() {
+    let data_dir = "test_dump";
+    let mut db_state = setup_no_persist(data_dir);
+    let desc = TupleDesc::new(
+        vec![DataType::U32, DataType::VarChar],
+        vec!["id", "name"],
+    );
+    let mut rel = Rel::new("rel", desc, &mut db_state).unwrap();
+    let info = rel
+        .new_index(vec![0], IndexType::Hash, &mut db_state)
+        .unwrap();
+    let tuples = rel
+        .literal_to_data(vec![vec![
+            Literal::Integer(7),
+            Literal::String("seven".to_string()),
+        ]])
+        .unwrap();
+    rel.write_tuples(&mut tuples.into_iter(), &mut db_state).unwrap();
+
+    let mut outputs = vec![];
+    for file_id in [rel.rel_id, info.file_id, LOG_REL_ID].iter() {
+        let mut out = vec![];
+        dump(*file_id, &mut db_state, &mut out).unwrap();
+        outputs.push(String::from_utf8(out).unwrap());
+    }
+    teardown(db_state);
+
+    assert!(outputs[0].contains("Table"));
+    assert!(outputs[0].contains("7, seven"));
+    assert!(outputs[1].contains("overflow"));
+    assert!(outputs[1].contains("hash"));
+    assert!(outputs[2].contains("last checkpoint"));
+    assert!(outputs[2].contains("InsertTuple"));
+}
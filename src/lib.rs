@@ -0,0 +1,30 @@
+#![allow(dead_code)]
+
+extern crate bincode;
+extern crate crc32fast;
+extern crate evmap;
+extern crate fasthash;
+extern crate libc;
+extern crate nom_sql;
+extern crate serde;
+
+#[macro_use]
+pub mod utils;
+#[macro_use]
+pub mod rel;
+
+pub mod backup;
+pub mod check;
+pub mod data_type;
+pub mod db_state;
+pub mod error;
+pub mod exec;
+pub mod index;
+pub mod inspect;
+pub mod internal_types;
+pub mod log;
+pub mod meta;
+pub mod metrics;
+pub mod storage;
+mod test_utils;
+pub mod tuple;
@@ -1,14 +1,18 @@
+use bincode;
+use crc32fast;
 use db_state::DbState;
-use error::Result;
-use internal_types::TupleData;
+use error::{Error, Result};
+use internal_types::{TupleData, LSN};
 use log::{LogHeader, OpType};
 use serde::{Deserialize, Serialize};
 use storage::{BufKey, BufType};
+use tuple::TuplePtr;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LogEntry {
     pub header: LogHeader,
     pub data: TupleData,
+    checksum: u32,
 }
 
 impl LogEntry {
@@ -20,7 +24,7 @@ impl LogEntry {
     ) -> Result<LogEntry> {
         let lsn = db_state.meta.get_new_lsn();
         let header = LogHeader::new(lsn, buf_key, op);
-        Ok(LogEntry { header, data })
+        Ok(LogEntry::with_checksum(header, data))
     }
 
     pub fn new_pending_cp() -> LogEntry {
@@ -29,10 +33,7 @@ impl LogEntry {
             BufKey::new(0, 0, BufType::Data),
             OpType::PendingCheckpoint,
         );
-        LogEntry {
-            header,
-            data: vec![],
-        }
+        LogEntry::with_checksum(header, vec![])
     }
 
     pub fn new_cp() -> LogEntry {
@@ -41,9 +42,44 @@ impl LogEntry {
             BufKey::new(0, 0, BufType::Data),
             OpType::Checkpoint,
         );
+        LogEntry::with_checksum(header, vec![])
+    }
+
+    /// Part of a page's image, lsn is the page's lsn
+    pub fn new_page_image(
+        buf_key: BufKey,
+        lsn: LSN,
+        data: TupleData,
+    ) -> LogEntry {
+        let header = LogHeader::new(lsn, buf_key, OpType::PageImage);
+        LogEntry::with_checksum(header, data)
+    }
+
+    /// Deserialize the entry at ptr and verify its checksum
+    pub fn load(data: &[u8], ptr: &TuplePtr) -> Result<LogEntry> {
+        let entry: LogEntry = match bincode::deserialize(data) {
+            Ok(entry) => entry,
+            Err(_) => return Err(Error::CorruptedLogEntry(*ptr)),
+        };
+        if entry.checksum != LogEntry::checksum(&entry.header, &entry.data) {
+            return Err(Error::CorruptedLogEntry(*ptr));
+        }
+        Ok(entry)
+    }
+
+    fn with_checksum(header: LogHeader, data: TupleData) -> LogEntry {
+        let checksum = LogEntry::checksum(&header, &data);
         LogEntry {
             header,
-            data: vec![],
+            data,
+            checksum,
         }
     }
+
+    fn checksum(header: &LogHeader, data: &[u8]) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&bincode::serialize(header).unwrap());
+        hasher.update(data);
+        hasher.finalize()
+    }
 }
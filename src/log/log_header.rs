@@ -8,10 +8,26 @@ pub struct LogHeader {
     pub lsn: LSN,
     pub buf_key: BufKey,
     pub op: OpType,
+    /// Milliseconds since the Unix epoch when the entry was created
+    pub timestamp: u64,
 }
 
 impl LogHeader {
     pub fn new(lsn: LSN, buf_key: BufKey, op: OpType) -> LogHeader {
-        LogHeader { lsn, buf_key, op }
+        LogHeader {
+            lsn,
+            buf_key,
+            op,
+            timestamp: LogHeader::now(),
+        }
+    }
+
+    pub fn now() -> u64 {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or(0)
     }
 }
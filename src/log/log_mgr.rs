@@ -1,21 +1,49 @@
 use bincode;
-use error::Result;
-use internal_types::ID;
-use log::{LogEntry, OpType};
-use std::sync::{Arc, RwLock};
-use storage::buf_mgr::PageLock;
-use storage::{BufKey, BufMgr, BufType};
+use db_state::{DbState, SyncMode};
+use error::{Error, Result};
+use internal_types::{ID, LSN};
+use log::{LogEntry, OpType, RecoveryTarget};
+use rel::Rel;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, VecDeque};
+use std::fs;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use storage::buf_mgr::PinnedPage;
+use storage::{BufKey, BufMgr, BufPage, BufType, LOG_SEGMENT_PAGES};
 use tuple::TuplePtr;
+use utils;
 
 pub static LOG_REL_ID: ID = 3;
-static LOG_META_KEY: BufKey = BufKey::new(LOG_REL_ID, 0, BufType::Data);
+pub static LOG_META_KEY: BufKey = BufKey::new(LOG_REL_ID, 0, BufType::Data);
 static LAST_CP_PTR: TuplePtr = TuplePtr::new(LOG_META_KEY, 0);
+static FIRST_LOG_KEY: BufKey = BufKey::new(LOG_REL_ID, 0, BufType::Log);
 
+/// The log's metadata is on page 0 of the log rel, log entries are
+/// written to pages of segment files, see BufType::Log
 #[derive(Clone, Debug)]
 pub struct LogMgr {
-    meta_page: PageLock,
+    meta_page: PinnedPage,
     cur_page_key: Arc<RwLock<BufKey>>,
     last_cp: Arc<RwLock<TuplePtr>>,
+    pending_sync: Arc<Mutex<PendingSync>>,
+    /// Held while the log is synced, the number of writes that are durable
+    synced_writes: Arc<Mutex<u64>>,
+    /// Number of checkpoints started, see write_page_entries
+    cp_count: Arc<AtomicU64>,
+    /// First segment kept while a backup runs, see start_backup
+    backup_segment: Arc<Mutex<Option<u64>>>,
+}
+
+/// Writes to the log that may not be durable yet
+#[derive(Debug, Default)]
+struct PendingSync {
+    writes: u64,
+    segments: BTreeSet<u64>,
+    /// Smallest LSN of the writes since the last sync started
+    min_lsn: Option<LSN>,
+    /// Smallest LSN of the writes being synced
+    syncing_min_lsn: Option<LSN>,
 }
 
 impl LogMgr {
@@ -44,20 +72,23 @@ impl LogMgr {
             )?;
         }
 
-        let _first_page =
-            buf_mgr.new_buf(&BufKey::new(LOG_REL_ID, 1, BufType::Data))?;
-        let cur_page_key =
-            Arc::new(RwLock::new(BufKey::new(LOG_REL_ID, 1, BufType::Data)));
+        fs::create_dir_all(format!("{}/wal", buf_mgr.data_dir()))?;
+        let _first_page = buf_mgr.new_buf(&FIRST_LOG_KEY)?;
+        let cur_page_key = Arc::new(RwLock::new(FIRST_LOG_KEY));
 
         Ok(LogMgr {
             meta_page,
             cur_page_key,
             last_cp: Arc::new(RwLock::new(LogMgr::default_checkpoint())),
+            pending_sync: Arc::new(Mutex::new(PendingSync::default())),
+            synced_writes: Arc::new(Mutex::new(0)),
+            // Pages start with image_cp 0, so they are always imaged once
+            cp_count: Arc::new(AtomicU64::new(1)),
+            backup_segment: Arc::new(Mutex::new(None)),
         })
     }
 
     pub fn load(buf_mgr: &mut BufMgr) -> Result<LogMgr> {
-        use storage::PAGE_SIZE;
         use utils::file_len;
 
         let meta_page = buf_mgr.get_buf(&LOG_META_KEY)?;
@@ -67,22 +98,58 @@ impl LogMgr {
             bincode::deserialize(data)?
         };
 
-        let log_file_len =
-            file_len(&LOG_META_KEY.to_filename(buf_mgr.data_dir()))?;
-        let cur_page_key = Arc::new(RwLock::new(BufKey::new(
+        let last_segment = match LogMgr::segments(buf_mgr)?.pop() {
+            Some(segment) => segment,
+            None => {
+                return Err(Error::from(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "No log segment found",
+                )))
+            }
+        };
+        let num_pages = file_len(&BufKey::segment_filename(
+            buf_mgr.data_dir(), last_segment))?
+            / buf_mgr.page_size() as u64;
+        let last_page_key = BufKey::new(
             LOG_REL_ID,
-            log_file_len / PAGE_SIZE as u64 - 1,
-            BufType::Data,
-        )));
+            last_segment * LOG_SEGMENT_PAGES + num_pages - 1,
+            BufType::Log,
+        );
+        LogMgr::repair_last_page(last_page_key, buf_mgr)?;
+        let cur_page_key = Arc::new(RwLock::new(last_page_key));
 
-        let mut log_mgr = LogMgr {
+        Ok(LogMgr {
             meta_page,
             cur_page_key,
             last_cp: Arc::new(RwLock::new(last_cp)),
-        };
-        log_mgr.recover(buf_mgr)?;
+            pending_sync: Arc::new(Mutex::new(PendingSync::default())),
+            synced_writes: Arc::new(Mutex::new(0)),
+            // Pages start with image_cp 0, so they are always imaged once
+            cp_count: Arc::new(AtomicU64::new(1)),
+            backup_segment: Arc::new(Mutex::new(None)),
+        })
+    }
 
-        Ok(log_mgr)
+    /// Entries are appended by rewriting the whole page, so a torn write
+    /// can break entries that were already durable. The log ends before
+    /// the first entry of the page that fails its checksum.
+    fn repair_last_page(key: BufKey, buf_mgr: &mut BufMgr) -> Result<()> {
+        match buf_mgr.get_buf(&key) {
+            Err(Error::CorruptedPage(_)) => {}
+            result => return result.map(|_| ()),
+        }
+        let tuples = BufPage::salvage_tuples(&buf_mgr.read_raw(&key)?);
+        let page = buf_mgr.reset_buf(&key)?;
+        {
+            let mut guard = page.write().unwrap();
+            for (i, data) in tuples.iter().enumerate() {
+                if LogEntry::load(data, &TuplePtr::new(key, i)).is_err() {
+                    break;
+                }
+                guard.write_tuple_data(data, None, None)?;
+            }
+        }
+        buf_mgr.store_buf(&key, None)
     }
 
     pub fn write_entries<E>(
@@ -91,20 +158,75 @@ impl LogMgr {
         buf_mgr: &mut BufMgr,
     ) -> Result<Vec<TuplePtr>>
     where
-        E: Into<std::collections::VecDeque<LogEntry>>,
+        E: Into<VecDeque<LogEntry>>,
     {
-        use std::collections::VecDeque;
+        self.write(entries.into(), None, buf_mgr)
+    }
 
-        let mut entries: VecDeque<LogEntry> = entries.into();
-        let _log_guard = self.meta_page.write().unwrap();
+    /// Write entries of a page that the caller has locked. The first
+    /// time the page is logged after a checkpoint, its image is logged
+    /// before the entries so recovery can repair a torn write.
+    ///
+    /// Only heap pages are logged. Index pages are rebuilt by recovery,
+    /// the meta and log meta pages are written whole by BufMgr. A torn
+    /// rel meta page or table index page cannot be recovered, loading
+    /// it fails with CorruptedPage and a backup has to be restored.
+    pub fn write_page_entries<E>(
+        &mut self,
+        entries: E,
+        page: &mut BufPage,
+        buf_mgr: &mut BufMgr,
+    ) -> Result<Vec<TuplePtr>>
+    where
+        E: Into<VecDeque<LogEntry>>,
+    {
+        self.write(entries.into(), Some(page), buf_mgr)
+    }
+
+    fn write(
+        &mut self,
+        mut entries: VecDeque<LogEntry>,
+        page: Option<&mut BufPage>,
+        buf_mgr: &mut BufMgr,
+    ) -> Result<Vec<TuplePtr>> {
+        if entries.is_empty() {
+            return Ok(vec![]);
+        }
+        let log_guard = self.meta_page.write().unwrap();
         let mut key_guard = self.cur_page_key.write().unwrap();
+
+        // The checkpoint count is only read and changed under the log lock,
+        // so no entry can be written after a checkpoint without an image
+        let cp_count = self.cp_count.load(Ordering::SeqCst);
+        let mut num_images = 0;
+        if let Some(page) = page {
+            if page.image_cp != cp_count {
+                let images = LogMgr::page_image_entries(page)?;
+                num_images = images.len();
+                for image in images.into_iter().rev() {
+                    entries.push_front(image);
+                }
+                page.image_cp = cp_count;
+            }
+        }
+        let has_cp = entries
+            .iter()
+            .any(|entry| entry.header.op == OpType::PendingCheckpoint);
         let mut pages_to_store = vec![];
+        let mut written = vec![];
         let mut ret = vec![];
 
+        // The current page already exists, new_buf would fail on the
+        // first page of a segment
+        let mut is_new_page = false;
         while entries.len() > 0 {
             pages_to_store.push(key_guard.clone());
 
-            let cur_page = buf_mgr.new_buf(&*key_guard)?;
+            let cur_page = if is_new_page {
+                buf_mgr.new_buf(&key_guard)?
+            } else {
+                buf_mgr.get_buf(&key_guard)?
+            };
             let mut page_guard = cur_page.write().unwrap();
 
             loop {
@@ -114,6 +236,7 @@ impl LogMgr {
                             < bincode::serialized_size(&entry)?
                         {
                             key_guard.offset += 1;
+                            is_new_page = true;
                             entries.push_front(entry);
                             break;
                         } else {
@@ -122,6 +245,7 @@ impl LogMgr {
                                 None,
                                 None,
                             )?);
+                            written.push(entry);
                         }
                     }
                     None => break,
@@ -132,7 +256,112 @@ impl LogMgr {
         for key in pages_to_store.iter() {
             buf_mgr.store_buf(&key, None)?;
         }
-        Ok(ret)
+        {
+            let mut pending = self.pending_sync.lock().unwrap();
+            pending.writes += 1;
+            pending
+                .segments
+                .extend(pages_to_store.iter().map(|key| key.segment()));
+            // Checkpoint entries do not change pages
+            let min_lsn = written
+                .iter()
+                .filter(|entry| {
+                    !matches!(
+                        entry.header.op,
+                        OpType::Checkpoint | OpType::PendingCheckpoint
+                    )
+                })
+                .map(|entry| entry.header.lsn)
+                .chain(pending.min_lsn)
+                .min();
+            pending.min_lsn = min_lsn;
+        }
+        if has_cp {
+            self.cp_count.fetch_add(1, Ordering::SeqCst);
+        }
+        drop(key_guard);
+        drop(log_guard);
+
+        if buf_mgr.sync_mode() == SyncMode::Full {
+            self.commit(buf_mgr)?;
+        }
+        Ok(ret.split_off(num_images))
+    }
+
+    /// Entries holding the page's image, split in parts that fit a log page
+    fn page_image_entries(page: &BufPage) -> Result<Vec<LogEntry>> {
+        // Log pages have the same size, a whole page does not fit one
+        let part_len = page.page_size() / 2;
+        let mut entries = vec![];
+        for (i, part) in page.buf().chunks(part_len).enumerate() {
+            let data = PageImageData {
+                offset: i * part_len,
+                data: part.to_vec(),
+            };
+            entries.push(LogEntry::new_page_image(
+                page.buf_key,
+                page.lsn,
+                bincode::serialize(&data)?,
+            ));
+        }
+        Ok(entries)
+    }
+
+    /// Fsync the log up to the last write, unless SyncMode is Off.
+    /// Concurrent committers wait on one sync instead of syncing in turn.
+    pub fn commit(&self, buf_mgr: &BufMgr) -> Result<()> {
+        if buf_mgr.sync_mode() == SyncMode::Off {
+            return Ok(());
+        }
+
+        let target = self.pending_sync.lock().unwrap().writes;
+        let mut synced_guard = self.synced_writes.lock().unwrap();
+        // Another committer synced our writes while we were waiting
+        if *synced_guard >= target {
+            return Ok(());
+        }
+
+        let (writes, segments) = {
+            let mut pending = self.pending_sync.lock().unwrap();
+            pending.syncing_min_lsn = pending.min_lsn.take();
+            (pending.writes, std::mem::take(&mut pending.segments))
+        };
+        for segment in segments {
+            buf_mgr.sync_file(&BufKey::new(
+                LOG_REL_ID,
+                segment * LOG_SEGMENT_PAGES,
+                BufType::Log,
+            ))?;
+        }
+        self.pending_sync.lock().unwrap().syncing_min_lsn = None;
+        *synced_guard = writes;
+        Ok(())
+    }
+
+    /// Every entry written with a smaller LSN is durable
+    pub fn flushed_lsn(&self) -> LSN {
+        let pending = self.pending_sync.lock().unwrap();
+        pending
+            .min_lsn
+            .into_iter()
+            .chain(pending.syncing_min_lsn)
+            .min()
+            .unwrap_or(LSN::MAX)
+    }
+
+    /// Make the entries up to lsn durable, unless SyncMode is Off.
+    /// The entries that changed a page must be durable before the page.
+    pub fn flush_to(&self, lsn: LSN, buf_mgr: &BufMgr) -> Result<()> {
+        if lsn < self.flushed_lsn() {
+            return Ok(());
+        }
+        self.commit(buf_mgr)
+    }
+
+    /// Whether every write to the log has been synced
+    pub fn is_synced(&self) -> bool {
+        let synced_guard = self.synced_writes.lock().unwrap();
+        *synced_guard == self.pending_sync.lock().unwrap().writes
     }
 
     pub fn create_checkpoint(
@@ -144,8 +373,9 @@ impl LogMgr {
             let cur_key = self.cur_page_key.read().unwrap();
 
             assert_eq!(last_cp.buf_key.file_id, LOG_REL_ID);
-            assert!(last_cp.buf_key.offset <= cur_key.offset);
-            if last_cp.buf_key.offset < cur_key.offset {
+            if *last_cp == LogMgr::default_checkpoint()
+                || last_cp.buf_key.offset < cur_key.offset
+            {
                 true
             } else {
                 let cur_page = buf_mgr.get_buf(&cur_key)?;
@@ -198,39 +428,100 @@ impl LogMgr {
         }
 
         buf_mgr.store_buf(&pending_cp.buf_key, None)?;
-        Ok(())
+        // The new LAST_CP_PTR must be on disk before older segments
+        // are removed
+        buf_mgr.store_buf(&LOG_META_KEY, None)?;
+        if buf_mgr.sync_mode() != SyncMode::Off {
+            buf_mgr.sync_file(&LOG_META_KEY)?;
+        }
+        self.remove_segments_before(pending_cp.buf_key.segment(), buf_mgr)
     }
 
-    fn recover(&mut self, buf_mgr: &mut BufMgr) -> Result<()> {
-        if !self.should_redo(buf_mgr)? {
+    /// Redo the entries written after the last checkpoint,
+    /// must be called once Meta is loaded
+    pub fn recover(&mut self, db_state: &mut DbState) -> Result<()> {
+        LogMgr::advance_id(db_state)?;
+        if !self.should_redo(&mut db_state.buf_mgr)? {
             return Ok(());
         }
+        self.redo(RecoveryTarget::default(), db_state)
+    }
+
+    /// Redo the entries after the last checkpoint up to target, even if
+    /// the log ends with a checkpoint. Used to restore a backup.
+    pub fn recover_to(
+        &mut self,
+        target: RecoveryTarget,
+        db_state: &mut DbState,
+    ) -> Result<()> {
+        LogMgr::advance_id(db_state)?;
+        self.redo(target, db_state)
+    }
+
+    /// IDs are not logged when files are created,
+    /// so the counter is advanced past the files on disk
+    fn advance_id(db_state: &mut DbState) -> Result<()> {
+        let data_dir = db_state.settings.get_data_dir();
+        if let Some(id) = utils::max_file_id(&data_dir)? {
+            db_state.meta.advance_counters(id + 1, 0);
+        }
+        Ok(())
+    }
+
+    /// The entries past target are skipped, they are before the new
+    /// checkpoint so they are never redone later. Group commit appends
+    /// entries out of LSN order, so the whole log is read.
+    fn redo(
+        &mut self,
+        target: RecoveryTarget,
+        db_state: &mut DbState,
+    ) -> Result<()> {
 
         let last_page_key = self.cur_page_key.read().unwrap().clone();
         let (mut cur_key, mut skip) = {
             let cp_guard = self.last_cp.read().unwrap();
-            (cp_guard.buf_key, cp_guard.buf_offset + 1)
+            if *cp_guard == LogMgr::default_checkpoint() {
+                (FIRST_LOG_KEY, 0)
+            } else {
+                (cp_guard.buf_key, cp_guard.buf_offset + 1)
+            }
         };
+        // Counters persisted before the checkpoint cover the entries
+        // before it, see BufMgr::persist_loop
+        let (mut max_id, mut max_lsn) = (0, 0);
         loop {
-            let log_page = buf_mgr.get_buf(&cur_key)?;
+            let log_page = db_state.buf_mgr.get_buf(&cur_key)?;
             let page_guard = log_page.read().unwrap();
 
-            for data in page_guard.iter().skip(skip) {
-                let entry: LogEntry = bincode::deserialize(data)?;
-                let buf = buf_mgr.get_buf(&entry.header.buf_key)?;
-                let mut buf_guard = buf.write().unwrap();
-
-                if buf_guard.lsn >= entry.header.lsn {
+            for (i, data) in page_guard.iter().enumerate().skip(skip) {
+                let ptr = TuplePtr::new(cur_key, i);
+                let entry = LogEntry::load(data, &ptr)?;
+                if target.is_past(&entry.header) {
                     continue;
                 }
-
+                max_id = max_id.max(entry.header.buf_key.file_id);
+                max_lsn = max_lsn.max(entry.header.lsn);
                 match entry.header.op {
-                    OpType::InsertTuple => {
-                        buf_guard.write_tuple_data(
-                            &entry.data,
-                            None,
-                            Some(entry.header.lsn),
-                        )?;
+                    OpType::InsertTuple | OpType::CompactPage => {
+                        LogMgr::redo_page_op(&entry, &mut db_state.buf_mgr)?;
+                    }
+                    OpType::PageImage => {
+                        LogMgr::redo_page_image(&entry, &mut db_state.buf_mgr)?;
+                    }
+                    OpType::TruncateFile => {
+                        LogMgr::redo_truncate(&entry, &mut db_state.buf_mgr)?;
+                    }
+                    OpType::DropRel => {
+                        Rel::redo_drop(&entry, db_state)?;
+                    }
+                    OpType::TruncateRel => {
+                        Rel::redo_truncate(&entry, db_state)?;
+                    }
+                    OpType::AlterRel => {
+                        Rel::redo_alter(&entry, db_state)?;
+                    }
+                    OpType::RenameAttr => {
+                        Rel::redo_rename_attr(&entry, db_state)?;
                     }
                     // TODO this entry should be deleted, but not possible yet
                     OpType::PendingCheckpoint => {}
@@ -245,23 +536,111 @@ impl LogMgr {
             skip = 0;
         }
 
+        db_state.meta.advance_counters(max_id + 1, max_lsn + 1);
+        // Index pages are not logged, so they can be torn or miss the
+        // redone tuples. The old files are dropped once the new indices
+        // are persisted, a crash before then rebuilds them again.
+        let old_ids = Rel::rebuild_all_indices(db_state)?;
+
         // Create a new Checkpoint and persist
-        let new_cp_ptr = self.create_checkpoint(buf_mgr)?;
-        buf_mgr.persist()?;
-        self.confirm_checkpoint(new_cp_ptr, buf_mgr)?;
+        let new_cp_ptr = self.create_checkpoint(&mut db_state.buf_mgr)?;
+        db_state.meta.persist_counters()?;
+        db_state.buf_mgr.persist()?;
+        self.confirm_checkpoint(new_cp_ptr, &mut db_state.buf_mgr)?;
+        for file_id in old_ids.into_iter() {
+            db_state.buf_mgr.drop_file(file_id, BufType::Data)?;
+        }
 
         Ok(())
     }
 
+    fn redo_page_op(entry: &LogEntry, buf_mgr: &mut BufMgr) -> Result<()> {
+        use std::io::ErrorKind;
+
+        // new_buf recreates pages that were appended but not persisted.
+        // The page is gone if its file was truncated or dropped after
+        // the entry was logged, then there is nothing to redo.
+        let buf = match buf_mgr.new_buf(&entry.header.buf_key) {
+            Ok(buf) => buf,
+            Err(e) => match e.io_kind() {
+                Some(ErrorKind::NotFound) | Some(ErrorKind::UnexpectedEof) => {
+                    return Ok(());
+                }
+                _ => return Err(e),
+            },
+        };
+        let mut buf_guard = buf.write().unwrap();
+
+        if buf_guard.lsn >= entry.header.lsn {
+            return Ok(());
+        }
+
+        match entry.header.op {
+            OpType::InsertTuple => {
+                buf_guard.write_tuple_data(
+                    &entry.data,
+                    None,
+                    Some(entry.header.lsn),
+                )?;
+            }
+            OpType::CompactPage => {
+                buf_guard.compact(Some(entry.header.lsn))?;
+            }
+            _ => {}
+        };
+        Ok(())
+    }
+
+    /// Images are restored even over a valid page, the entries logged
+    /// after the image redo the page's later changes
+    fn redo_page_image(entry: &LogEntry, buf_mgr: &mut BufMgr) -> Result<()> {
+        use std::io::ErrorKind;
+
+        let key = entry.header.buf_key;
+        let buf = match buf_mgr.new_buf(&key) {
+            Ok(buf) => buf,
+            Err(Error::CorruptedPage(_)) => buf_mgr.reset_buf(&key)?,
+            Err(e) => match e.io_kind() {
+                Some(ErrorKind::NotFound) | Some(ErrorKind::UnexpectedEof) => {
+                    return Ok(());
+                }
+                _ => return Err(e),
+            },
+        };
+        let data: PageImageData = bincode::deserialize(&entry.data)?;
+        let mut buf_guard = buf.write().unwrap();
+        buf_guard.restore_image(data.offset, &data.data)
+    }
+
+    /// The entry's buf_key is the first page that was truncated
+    fn redo_truncate(entry: &LogEntry, buf_mgr: &mut BufMgr) -> Result<()> {
+        let key = entry.header.buf_key;
+        let filename = buf_mgr.key_to_filename(key);
+        if utils::file_exists(&filename)
+            && utils::num_pages(&filename, buf_mgr.page_size())?
+                >= key.offset
+        {
+            buf_mgr.truncate_file(key.file_id, key.buf_type, key.offset)?;
+        }
+        Ok(())
+    }
+
     fn should_redo(&self, buf_mgr: &mut BufMgr) -> Result<bool> {
         let key_guard = self.cur_page_key.read().unwrap();
         let cur_page = buf_mgr.get_buf(&key_guard)?;
         let page_guard = cur_page.write().unwrap();
+        // The last page is empty if nothing has been logged yet,
+        // or if the db crashed right after adding the page
+        if page_guard.tuple_count() == 0 {
+            return Ok(key_guard.offset > 0);
+        }
 
         let last_entry_ptr =
             TuplePtr::new(*key_guard, page_guard.tuple_count() - 1);
-        let last_entry: LogEntry =
-            bincode::deserialize(page_guard.get_tuple_data(&last_entry_ptr)?)?;
+        let last_entry = LogEntry::load(
+            page_guard.get_tuple_data(&last_entry_ptr)?,
+            &last_entry_ptr,
+        )?;
 
         match last_entry.header.op {
             OpType::Checkpoint => Ok(false),
@@ -269,8 +648,137 @@ impl LogMgr {
         }
     }
 
+    /// Key of the log page entries are written to
+    pub fn cur_page_key(&self) -> BufKey {
+        *self.cur_page_key.read().unwrap()
+    }
+
+    pub fn last_checkpoint(&self) -> TuplePtr {
+        *self.last_cp.read().unwrap()
+    }
+
+    /// Point the log at a checkpoint of a restored backup
+    pub fn reset_checkpoint(
+        &mut self,
+        cp: TuplePtr,
+        buf_mgr: &mut BufMgr,
+    ) -> Result<()> {
+        {
+            let mut last_cp_guard = self.last_cp.write().unwrap();
+            let mut log_guard = self.meta_page.write().unwrap();
+            log_guard.write_tuple_data(
+                &bincode::serialize(&cp)?,
+                Some(&LAST_CP_PTR),
+                None,
+            )?;
+            *last_cp_guard = cp;
+        }
+        buf_mgr.store_buf(&LOG_META_KEY, None)
+    }
+
+    /// Keep the segments from the current one on until stop_backup,
+    /// so a backup can copy every entry after its checkpoint
+    pub fn start_backup(&self) -> Result<()> {
+        let mut backup_guard = self.backup_segment.lock().unwrap();
+        if backup_guard.is_some() {
+            return Err(Error::internal("A backup is already running"));
+        }
+        *backup_guard = Some(self.cur_page_key.read().unwrap().segment());
+        Ok(())
+    }
+
+    pub fn stop_backup(&self) {
+        *self.backup_segment.lock().unwrap() = None;
+    }
+
+    /// Copy the segments from segment on to dest_dir/wal. The log is
+    /// locked so no page is copied while it is being written.
+    pub fn copy_segments(
+        &self,
+        segment: u64,
+        dest_dir: &str,
+        buf_mgr: &BufMgr,
+    ) -> Result<()> {
+        let _log_guard = self.meta_page.write().unwrap();
+        fs::create_dir_all(format!("{}/wal", dest_dir))?;
+        for copied in LogMgr::segments(buf_mgr)? {
+            if copied >= segment {
+                fs::copy(
+                    BufKey::segment_filename(buf_mgr.data_dir(), copied),
+                    BufKey::segment_filename(dest_dir.to_string(), copied),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete the segments that only have entries before the checkpoint,
+    /// they are archived first if BufMgr has an archive_dir
+    fn remove_segments_before(
+        &self,
+        segment: u64,
+        buf_mgr: &mut BufMgr,
+    ) -> Result<()> {
+        let segment = match *self.backup_segment.lock().unwrap() {
+            Some(backup_segment) => segment.min(backup_segment),
+            None => segment,
+        };
+        buf_mgr.discard_bufs(|key| {
+            key.buf_type == BufType::Log && key.segment() < segment
+        })?;
+        let archive_dir = buf_mgr.archive_dir();
+        if let Some(dir) = &archive_dir {
+            fs::create_dir_all(dir)?;
+        }
+        for old in LogMgr::segments(buf_mgr)? {
+            if old < segment {
+                let filename =
+                    BufKey::segment_filename(buf_mgr.data_dir(), old);
+                if let Some(dir) = &archive_dir {
+                    fs::copy(&filename, format!("{}/{:08}.log", dir, old))?;
+                }
+                buf_mgr.close_file(&BufKey::new(
+                    LOG_REL_ID,
+                    old * LOG_SEGMENT_PAGES,
+                    BufType::Log,
+                ));
+                fs::remove_file(filename)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Numbers of the segment files on disk, in order
+    fn segments(buf_mgr: &BufMgr) -> Result<Vec<u64>> {
+        LogMgr::segments_in(&format!("{}/wal", buf_mgr.data_dir()))
+    }
+
+    /// Numbers of the segment files in dir, e.g. an archive, in order
+    pub fn segments_in(dir: &str) -> Result<Vec<u64>> {
+        let mut segments = vec![];
+        for entry in fs::read_dir(dir)? {
+            let segment = entry?
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_suffix(".log"))
+                .and_then(|segment| segment.parse::<u64>().ok());
+            if let Some(segment) = segment {
+                segments.push(segment);
+            }
+        }
+        segments.sort();
+        Ok(segments)
+    }
+
     /// Placeholder checkpoint when the log file is first created
-    fn default_checkpoint() -> TuplePtr {
+    pub fn default_checkpoint() -> TuplePtr {
         LAST_CP_PTR
     }
 }
+
+/// Part of a page image, copied to the page at offset
+#[derive(Serialize, Deserialize)]
+struct PageImageData {
+    offset: usize,
+    data: Vec<u8>,
+}
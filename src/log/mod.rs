@@ -2,12 +2,15 @@ mod log_entry;
 mod log_header;
 mod log_mgr;
 mod op_type;
+mod recovery_target;
 
 pub use self::log_entry::LogEntry;
 pub use self::log_header::LogHeader;
 pub use self::log_mgr::LogMgr;
+pub use self::log_mgr::LOG_META_KEY;
 pub use self::log_mgr::LOG_REL_ID;
 pub use self::op_type::OpType;
+pub use self::recovery_target::RecoveryTarget;
 
 #[cfg(test)]
 mod tests;
@@ -6,4 +6,11 @@ pub enum OpType {
     // UpdateTuple,
     Checkpoint,
     PendingCheckpoint,
+    CompactPage,
+    TruncateFile,
+    DropRel,
+    TruncateRel,
+    AlterRel,
+    PageImage,
+    RenameAttr,
 }
@@ -0,0 +1,19 @@
+use internal_types::LSN;
+use log::LogHeader;
+
+/// Where redo stops when restoring a backup, by default the whole log
+/// is replayed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RecoveryTarget {
+    pub lsn: Option<LSN>,
+    /// Milliseconds since the Unix epoch, see LogHeader::timestamp
+    pub time: Option<u64>,
+}
+
+impl RecoveryTarget {
+    /// Whether the entry is past the target and must not be redone
+    pub fn is_past(&self, header: &LogHeader) -> bool {
+        self.lsn.is_some_and(|lsn| header.lsn > lsn)
+            || self.time.is_some_and(|time| header.timestamp > time)
+    }
+}
@@ -26,7 +26,7 @@ fn test_write_log_entries() {
 
     let log_page = db_state
         .buf_mgr
-        .get_buf(&BufKey::new(LOG_REL_ID, 1, BufType::Data))
+        .get_buf(&BufKey::new(LOG_REL_ID, 0, BufType::Log))
         .unwrap();
     let guard = log_page.read().unwrap();
     let written_entry: LogEntry = bincode::deserialize(
@@ -87,13 +87,13 @@ fn test_log_checkpoints() {
 
     teardown(db_state);
 
-    assert_eq!(cp_1.buf_key, BufKey::new(LOG_REL_ID, 1, BufType::Data));
+    assert_eq!(cp_1.buf_key, BufKey::new(LOG_REL_ID, 0, BufType::Log));
     assert_eq!(cp_1.buf_offset, 0);
 
     assert_eq!(cp_2.buf_key, BufKey::new(LOG_REL_ID, 0, BufType::Data));
     assert_eq!(cp_2.buf_offset, 0);
 
-    assert_eq!(cp_3.buf_key, BufKey::new(LOG_REL_ID, 1, BufType::Data));
+    assert_eq!(cp_3.buf_key, BufKey::new(LOG_REL_ID, 0, BufType::Log));
     assert_eq!(cp_3.buf_offset, 2);
 }
 
@@ -170,3 +170,491 @@ fn test_recover_new_rel() {
 
     assert_eq!(rel.tuple_desc(), desc);
 }
+
+#[test]
+fn test_recover_counters() {
+    use nom_sql::Literal;
+
+    let data_dir = "test_recover_counters";
+    let mut db_state = setup_no_persist(data_dir);
+    db_state.buf_mgr.persist().unwrap();
+    // The counters on disk are stale from here on
+    let desc = TupleDesc::new(
+        vec![DataType::Char, DataType::U32],
+        vec!["char", "u32"],
+    );
+    let rel = Rel::new("rel", desc, &mut db_state).unwrap();
+    let tuples = rel
+        .literal_to_data(vec![
+            vec![Literal::String("a".to_string()), Literal::Integer(1)],
+        ])
+        .unwrap();
+    let ptrs = rel.write_tuples(&mut tuples.into_iter(), &mut db_state)
+        .unwrap();
+    db_state.buf_mgr.persist().unwrap();
+    let page_lsn = {
+        let page = db_state.buf_mgr.get_buf(&ptrs[0].buf_key).unwrap();
+        let guard = page.read().unwrap();
+        guard.lsn
+    };
+
+    // Crash and restart
+    let db_state = setup_no_persist(data_dir);
+    let new_id = db_state.meta.get_new_id();
+    let new_lsn = db_state.meta.get_new_lsn();
+    teardown(db_state);
+
+    assert!(new_id > rel.rel_id);
+    assert!(new_lsn > page_lsn);
+}
+
+#[test]
+fn test_recover_id_without_redo() {
+    let data_dir = "test_recover_id_without_redo";
+    let mut db_state = setup_no_persist(data_dir);
+    db_state.buf_mgr.persist().unwrap();
+    // Creating a rel is not logged, the last entry stays a checkpoint
+    let desc = TupleDesc::new(vec![DataType::U32], vec!["u32"]);
+    let rel = Rel::new("rel", desc, &mut db_state).unwrap();
+    let cp = db_state
+        .log_mgr
+        .create_checkpoint(&mut db_state.buf_mgr)
+        .unwrap();
+    db_state.buf_mgr.persist().unwrap();
+    db_state
+        .log_mgr
+        .confirm_checkpoint(cp, &mut db_state.buf_mgr)
+        .unwrap();
+
+    // Crash and restart
+    let db_state = setup_no_persist(data_dir);
+    let new_id = db_state.meta.get_new_id();
+    teardown(db_state);
+
+    assert!(new_id > rel.rel_id);
+}
+
+#[test]
+fn test_log_segments() {
+    use storage::LOG_SEGMENT_PAGES;
+    use utils::file_exists;
+
+    let data_dir = "test_log_segments";
+    let mut db_state = setup_no_persist(data_dir);
+    // One entry per page, the target file does not exist so there is
+    // nothing to redo
+    let entries = (0..LOG_SEGMENT_PAGES + 10)
+        .map(|_| {
+            LogEntry::new(
+                BufKey::new(1000, 1, BufType::Data),
+                OpType::InsertTuple,
+                vec![0u8; 3000],
+                &mut db_state,
+            )
+            .unwrap()
+        })
+        .collect::<Vec<_>>();
+    let ptrs = db_state
+        .log_mgr
+        .write_entries(entries, &mut db_state.buf_mgr)
+        .unwrap();
+    let segment_0 = BufKey::segment_filename(data_dir.to_string(), 0);
+    let segment_1 = BufKey::segment_filename(data_dir.to_string(), 1);
+    let had_segments = file_exists(&segment_0) && file_exists(&segment_1);
+
+    let cp = db_state
+        .log_mgr
+        .create_checkpoint(&mut db_state.buf_mgr)
+        .unwrap();
+    db_state.buf_mgr.persist().unwrap();
+    db_state
+        .log_mgr
+        .confirm_checkpoint(cp, &mut db_state.buf_mgr)
+        .unwrap();
+    let removed_segment_0 = !file_exists(&segment_0);
+
+    // Restart, the log continues in the last segment
+    let mut db_state = setup_no_persist(data_dir);
+    let entry = LogEntry::new(
+        BufKey::new(1000, 1, BufType::Data),
+        OpType::InsertTuple,
+        vec![0u8; 4],
+        &mut db_state,
+    )
+    .unwrap();
+    let new_ptr = db_state
+        .log_mgr
+        .write_entries(vec![entry], &mut db_state.buf_mgr)
+        .unwrap();
+    teardown(db_state);
+
+    assert_eq!(ptrs.last().unwrap().buf_key.segment(), 1);
+    assert!(had_segments);
+    assert_eq!(cp.buf_key.segment(), 1);
+    assert!(removed_segment_0);
+    assert!(new_ptr[0].buf_key.offset >= cp.buf_key.offset);
+}
+
+#[test]
+fn test_recovery_across_segments() {
+    use nom_sql::Literal;
+    use storage::LOG_SEGMENT_PAGES;
+
+    let data_dir = "test_recovery_across_segments";
+    let mut db_state = setup_no_persist(data_dir);
+    let desc = TupleDesc::new(
+        vec![DataType::U32, DataType::VarChar],
+        vec!["id", "text"],
+    );
+    let rel = Rel::new("rel", desc, &mut db_state).unwrap();
+    db_state.buf_mgr.persist().unwrap();
+    // Each entry fills a log page
+    let text = "a".repeat(3000);
+    let num_tuples = LOG_SEGMENT_PAGES + 10;
+    let tuples = rel
+        .literal_to_data((0..num_tuples).map(|i| vec![
+            Literal::Integer(i as i64),
+            Literal::String(text.clone()),
+        ]).collect())
+        .unwrap();
+    rel.write_tuples(&mut tuples.into_iter(), &mut db_state).unwrap();
+
+    // Crash before any data page is persisted
+    let mut db_state = setup_no_persist(data_dir);
+    let mut count = 0;
+    rel.scan(&mut db_state, |_| Ok(true), |_, _| {
+        count += 1;
+        Ok(())
+    }).unwrap();
+    teardown(db_state);
+
+    assert_eq!(count, num_tuples);
+}
+
+#[test]
+fn test_group_commit() {
+    use std::thread;
+
+    let data_dir = "test_group_commit";
+    let mut db_state = setup_no_persist(data_dir);
+
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let mut db_state = db_state.clone();
+            thread::spawn(move || {
+                for _ in 0..10 {
+                    let entry = LogEntry::new(
+                        BufKey::new(3, 1, BufType::Data),
+                        OpType::InsertTuple,
+                        vec![0u8; 4],
+                        &mut db_state,
+                    )
+                    .unwrap();
+                    db_state
+                        .log_mgr
+                        .write_entries(vec![entry], &mut db_state.buf_mgr)
+                        .unwrap();
+                    db_state.commit().unwrap();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let synced_threads = db_state.log_mgr.is_synced();
+
+    let entry = LogEntry::new(
+        BufKey::new(3, 1, BufType::Data),
+        OpType::InsertTuple,
+        vec![0u8; 4],
+        &mut db_state,
+    )
+    .unwrap();
+    db_state
+        .log_mgr
+        .write_entries(vec![entry], &mut db_state.buf_mgr)
+        .unwrap();
+    let synced_before = db_state.log_mgr.is_synced();
+    db_state.commit().unwrap();
+    let synced_after = db_state.log_mgr.is_synced();
+
+    teardown(db_state);
+
+    assert!(synced_threads);
+    assert!(!synced_before);
+    assert!(synced_after);
+}
+
+#[test]
+fn test_wal_before_data() {
+    use nom_sql::Literal;
+
+    let data_dir = "test_wal_before_data";
+    let mut db_state = setup_no_persist(data_dir);
+    let desc = TupleDesc::new(vec![DataType::U32], vec!["u32"]);
+    let rel = Rel::new("rel", desc, &mut db_state).unwrap();
+    db_state.buf_mgr.persist().unwrap();
+
+    let tuples = rel
+        .literal_to_data(vec![vec![Literal::Integer(1)]])
+        .unwrap();
+    let ptrs = rel
+        .write_tuples(&mut tuples.into_iter(), &mut db_state)
+        .unwrap();
+    let lsn = {
+        let page = db_state.buf_mgr.get_buf(&ptrs[0].buf_key).unwrap();
+        let guard = page.read().unwrap();
+        guard.lsn
+    };
+    let synced_before = db_state.log_mgr.is_synced();
+    // Writing the page forces the log up to the page's lsn
+    db_state.buf_mgr.store_buf(&ptrs[0].buf_key, None).unwrap();
+    let synced_after = db_state.log_mgr.is_synced();
+    let flushed_lsn = db_state.log_mgr.flushed_lsn();
+
+    teardown(db_state);
+
+    assert!(!synced_before);
+    assert!(synced_after);
+    assert!(lsn < flushed_lsn);
+}
+
+#[test]
+fn test_corrupted_log_entry() {
+    use error::Error;
+
+    let data_dir = "test_corrupted_log_entry";
+    let mut db_state = setup_no_persist(data_dir);
+
+    let entry = LogEntry::new(
+        BufKey::new(3, 1, BufType::Data),
+        OpType::InsertTuple,
+        vec![0u8; 4],
+        &mut db_state,
+    )
+    .unwrap();
+    let ptrs = db_state
+        .log_mgr
+        .write_entries(vec![entry], &mut db_state.buf_mgr)
+        .unwrap();
+    let mut data = {
+        let log_page = db_state.buf_mgr.get_buf(&ptrs[0].buf_key).unwrap();
+        let guard = log_page.read().unwrap();
+        guard.get_tuple_data(&ptrs[0]).unwrap().to_vec()
+    };
+
+    teardown(db_state);
+
+    assert!(LogEntry::load(&data, &ptrs[0]).is_ok());
+    // Flip a byte of the entry's data
+    let len = data.len();
+    data[len - 5] ^= 1;
+    match LogEntry::load(&data, &ptrs[0]) {
+        Err(Error::CorruptedLogEntry(ptr)) => assert_eq!(ptr, ptrs[0]),
+        _ => panic!("Expected a corrupted log entry"),
+    }
+}
+
+#[test]
+fn test_recover_torn_page() {
+    use nom_sql::Literal;
+    use std::io::{Seek, SeekFrom, Write};
+
+    let data_dir = "test_recover_torn_page";
+    let mut db_state = setup_no_persist(data_dir);
+    let desc = TupleDesc::new(vec![DataType::U32], vec!["u32"]);
+    let rel = Rel::new("rel", desc, &mut db_state).unwrap();
+    let rel_id = rel.rel_id;
+    let tuples = rel
+        .literal_to_data(vec![
+            vec![Literal::Integer(1)],
+            vec![Literal::Integer(2)],
+        ])
+        .unwrap();
+    rel.write_tuples(&mut tuples.into_iter(), &mut db_state).unwrap();
+    db_state.buf_mgr.persist().unwrap();
+
+    // Only part of page 1 reached the disk
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(format!("{}/{}.dat", data_dir, rel_id))
+        .unwrap();
+    let page_size = db_state.buf_mgr.page_size() as u64;
+    file.seek(SeekFrom::Start(2 * page_size - 4)).unwrap();
+    file.write_all(&[0; 4]).unwrap();
+
+    // Restart db, the page is restored from its image
+    let mut db_state = setup_no_persist(data_dir);
+    let rel = Rel::load(rel_id, BufType::Data, &mut db_state).unwrap();
+    let mut written_tuples = vec![];
+    rel.scan(
+        &mut db_state,
+        |_| Ok(true),
+        |data, _db_state| {
+            written_tuples.push(data.to_vec());
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    teardown(db_state);
+
+    assert_eq!(written_tuples.len(), 2);
+    let data = rel.data_to_strings(&written_tuples[1], None).unwrap();
+    assert_eq!(data[0], "2");
+}
+
+#[test]
+fn test_recover_torn_index_page() {
+    use index::{HashIndex, Index, IndexType};
+    use nom_sql::Literal;
+    use std::io::{Seek, SeekFrom, Write};
+    use utils;
+
+    let data_dir = "test_recover_torn_index_page";
+    let mut db_state = setup_no_persist(data_dir);
+    let desc = TupleDesc::new(vec![DataType::U32], vec!["u32"]);
+    let mut rel = Rel::new("rel", desc, &mut db_state).unwrap();
+    let info =
+        rel.new_index(vec![0], IndexType::Hash, &mut db_state).unwrap();
+    let tuples = rel
+        .literal_to_data(vec![vec![Literal::Integer(1)]])
+        .unwrap();
+    let ptrs = rel.write_tuples(&mut tuples.into_iter(), &mut db_state)
+        .unwrap();
+    db_state.buf_mgr.persist().unwrap();
+
+    // Only part of each bucket reached the disk, index pages are not
+    // logged
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(format!("{}/{}.dat", data_dir, info.file_id))
+        .unwrap();
+    let page_size = db_state.buf_mgr.page_size() as u64;
+    let num_pages = file.metadata().unwrap().len() / page_size;
+    for offset in 1..num_pages {
+        file.seek(SeekFrom::Start((offset + 1) * page_size - 4)).unwrap();
+        file.write_all(&[0xff; 4]).unwrap();
+    }
+
+    // Restart db, the index is rebuilt from the tuples
+    let mut db_state = setup_no_persist(data_dir);
+    let rel = Rel::load(rel.rel_id, BufType::Data, &mut db_state).unwrap();
+    let file_id = rel.indices()[0].file_id;
+    let index = HashIndex::load(file_id, &mut db_state).unwrap();
+    let found = index
+        .get(&bincode::serialize(&1u32).unwrap(), &mut db_state)
+        .unwrap();
+    let old_exists = utils::file_exists(
+        &format!("{}/{}.dat", data_dir, info.file_id));
+    teardown(db_state);
+
+    assert_eq!(found, ptrs);
+    assert!(!old_exists);
+}
+
+#[test]
+fn test_torn_last_log_page() {
+    use nom_sql::Literal;
+    use std::io::{Seek, SeekFrom, Write};
+    use storage::LOG_SEGMENT_PAGES;
+
+    let data_dir = "test_torn_last_log_page";
+    let mut db_state = setup_no_persist(data_dir);
+    let desc = TupleDesc::new(vec![DataType::U32], vec!["u32"]);
+    let rel = Rel::new("rel", desc, &mut db_state).unwrap();
+    let write = |value, db_state: &mut _| {
+        let tuples =
+            rel.literal_to_data(vec![vec![Literal::Integer(value)]]).unwrap();
+        rel.write_tuples(&mut tuples.into_iter(), db_state).unwrap();
+    };
+    write(1, &mut db_state);
+    db_state.buf_mgr.persist().unwrap();
+    write(2, &mut db_state);
+
+    // The last append to the log page is torn, the newest entry is
+    // written first, at the upper end of the page's data
+    let log_key = db_state.log_mgr.cur_page_key();
+    let upper = {
+        let page = db_state.buf_mgr.get_buf(&log_key).unwrap();
+        let upper = page.read().unwrap().upper_ptr as u64;
+        upper
+    };
+    let page_size = db_state.buf_mgr.page_size() as u64;
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(BufKey::segment_filename(
+            data_dir.to_string(), log_key.segment()))
+        .unwrap();
+    let page_start = (log_key.offset % LOG_SEGMENT_PAGES) * page_size;
+    file.seek(SeekFrom::Start(page_start + upper + 8)).unwrap();
+    file.write_all(&[0xff; 4]).unwrap();
+
+    // Restart db, the log ends before the torn entry
+    let mut db_state = setup_no_persist(data_dir);
+    let rel = Rel::load(rel.rel_id, BufType::Data, &mut db_state).unwrap();
+    let mut count = 0;
+    rel.scan(&mut db_state, |_| Ok(true), |_, _| {
+        count += 1;
+        Ok(())
+    })
+    .unwrap();
+    teardown(db_state);
+
+    assert_eq!(count, 1);
+}
+
+#[test]
+fn test_page_image_before_change() {
+    use nom_sql::Literal;
+    use tuple::TuplePtr;
+
+    let mut db_state = setup_no_persist("test_page_image_before_change");
+    let desc = TupleDesc::new(vec![DataType::U32], vec!["u32"]);
+    let rel = Rel::new("rel", desc, &mut db_state).unwrap();
+    let write = |value, db_state: &mut _| {
+        let tuples =
+            rel.literal_to_data(vec![vec![Literal::Integer(value)]]).unwrap();
+        rel.write_tuples(&mut tuples.into_iter(), db_state).unwrap();
+    };
+    let page_key = BufKey::new(rel.rel_id, 1, BufType::Data);
+    write(1, &mut db_state);
+    let before = {
+        let page = db_state.buf_mgr.get_buf(&page_key).unwrap();
+        let buf = page.read().unwrap().buf().clone();
+        buf
+    };
+    // The page is logged again on its first change after the checkpoint
+    db_state
+        .log_mgr
+        .create_checkpoint(&mut db_state.buf_mgr)
+        .unwrap();
+    write(2, &mut db_state);
+
+    let mut images = vec![];
+    let mut log_key = BufKey::new(LOG_REL_ID, 0, BufType::Log);
+    while let Ok(log_page) = db_state.buf_mgr.get_buf(&log_key) {
+        let guard = log_page.read().unwrap();
+        if guard.tuple_count() == 0 {
+            break;
+        }
+        for (i, data) in guard.iter().enumerate() {
+            let entry =
+                LogEntry::load(data, &TuplePtr::new(log_key, i)).unwrap();
+            if entry.header.op == OpType::PageImage
+                && entry.header.buf_key == page_key
+            {
+                let (_, part): (usize, Vec<u8>) =
+                    bincode::deserialize(&entry.data).unwrap();
+                images.push(part);
+            }
+        }
+        log_key = log_key.inc_offset();
+    }
+    teardown(db_state);
+
+    // Each image is logged in two parts
+    assert_eq!(images.len(), 4);
+    assert_eq!(images[2..].concat(), before);
+}
@@ -1,33 +1,44 @@
-#![allow(dead_code)]
-
-extern crate bincode;
-extern crate evmap;
-extern crate fasthash;
 extern crate linenoise;
 extern crate nom_sql;
-extern crate serde;
-
-#[macro_use]
-mod utils;
-#[macro_use]
-mod rel;
-
-mod data_type;
-mod db_state;
-mod error;
-mod exec;
-mod index;
-mod internal_types;
-mod log;
-mod meta;
-mod storage;
-mod test_utils;
-mod tuple;
+extern crate sueql;
 
-use db_state::{DbSettings, DbState};
+use sueql::db_state::{DbSettings, DbState};
+use sueql::{backup, error, exec, log};
 
 fn main() -> error::Result<()> {
-    let mut db_state = DbState::start_db(DbSettings::default())?;
+    let usage = "Usage: sueql [--data-dir dir] [--archive-dir dir] \
+                 [--metrics-port port] [--read-ahead pages] \
+                 [--direct-io on|off] [--page-size bytes] \
+                 [--temp-limit pages] | sueql restore ...";
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("restore") {
+        return restore(&args[1..]);
+    }
+
+    let mut settings = DbSettings::default();
+    for pair in args.chunks(2) {
+        let invalid = || error::Error::internal(usage);
+        settings = match (pair[0].as_str(), pair.get(1).map(String::as_str)) {
+            ("--data-dir", Some(dir)) => settings.data_dir(dir),
+            ("--archive-dir", Some(dir)) => settings.archive_dir(dir),
+            ("--metrics-port", Some(port)) => {
+                settings.metrics_port(port.parse().map_err(|_| invalid())?)
+            }
+            ("--read-ahead", Some(pages)) => {
+                settings.read_ahead(pages.parse().map_err(|_| invalid())?)
+            }
+            ("--page-size", Some(size)) => {
+                settings.page_size(size.parse().map_err(|_| invalid())?)
+            }
+            ("--temp-limit", Some(pages)) => {
+                settings.temp_limit(pages.parse().map_err(|_| invalid())?)
+            }
+            ("--direct-io", Some("on")) => settings.direct_io(true),
+            ("--direct-io", Some("off")) => settings.direct_io(false),
+            _ => return Err(invalid()),
+        };
+    }
+    let mut db_state = DbState::start_db(settings)?;
 
     let mut query = String::from("");
     loop {
@@ -37,14 +48,25 @@ fn main() -> error::Result<()> {
         match input {
             Some(input) => {
                 query.push_str(&input);
-                if input.find(';').is_some() {
-                    match nom_sql::parse_query(&query) {
-                        Ok(query) => {
-                            exec::exec(query, &mut db_state)?;
+                // Backslash shortcuts like \dt do not need a semicolon
+                if query.trim_start().starts_with('\\')
+                    || input.find(';').is_some()
+                {
+                    match exec::Command::parse(&query) {
+                        Some(Ok(cmd)) => {
+                            exec::exec_command(cmd, &mut db_state)?;
                         }
-                        Err(e) => {
-                            println!("{}", e);
+                        Some(Err(e)) => {
+                            println!("{:?}", e);
                         }
+                        None => match exec::parse_query(&query) {
+                            Ok(query) => {
+                                exec::exec(query, &mut db_state)?;
+                            }
+                            Err(e) => {
+                                println!("{:?}", e);
+                            }
+                        },
                     }
                     query.clear();
                 }
@@ -57,3 +79,48 @@ fn main() -> error::Result<()> {
     };
     Ok(())
 }
+
+/// sueql restore backup_dir [--archive dir] [--lsn lsn] [--time millis]
+/// [--data-dir dir] [--page-size bytes]
+fn restore(args: &[String]) -> error::Result<()> {
+    let usage = "Usage: sueql restore backup_dir [--archive dir] \
+                 [--lsn lsn] [--time millis_since_epoch] \
+                 [--data-dir dir] [--page-size bytes]";
+    let backup_dir = match args.first() {
+        Some(dir) => dir,
+        None => return Err(error::Error::internal(usage)),
+    };
+    let mut archive_dir = None;
+    let mut target = log::RecoveryTarget::default();
+    let mut settings = DbSettings::default();
+    for pair in args[1..].chunks(2) {
+        let invalid = || error::Error::internal(usage);
+        match (pair[0].as_str(), pair.get(1)) {
+            ("--archive", Some(dir)) => archive_dir = Some(dir.as_str()),
+            ("--lsn", Some(lsn)) => {
+                target.lsn = Some(lsn.parse().map_err(|_| invalid())?);
+            }
+            ("--time", Some(time)) => {
+                target.time = Some(time.parse().map_err(|_| invalid())?);
+            }
+            ("--data-dir", Some(dir)) => settings = settings.data_dir(dir),
+            ("--page-size", Some(size)) => {
+                settings =
+                    settings.page_size(size.parse().map_err(|_| invalid())?);
+            }
+            _ => return Err(invalid()),
+        }
+    }
+
+    let label = backup::restore(
+        backup_dir,
+        archive_dir,
+        target,
+        settings,
+    )?;
+    println!(
+        "Restored {} from the checkpoint at {:?}",
+        backup_dir, label.start_cp
+    );
+    Ok(())
+}
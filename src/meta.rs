@@ -1,13 +1,14 @@
 use bincode;
 use data_type::DataType;
 use db_state::State;
-use error::Result;
+use error::{Error, Result};
 use index::HashIndex;
 use internal_types::{ID, LSN};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU32, Ordering};
-use storage::buf_mgr::PageLock;
-use storage::{BufKey, BufMgr, BufType};
+use storage::buf_mgr::PinnedPage;
+use storage::buf_page::PAGE_FORMAT_VERSION;
+use storage::{BufKey, BufMgr, BufPage, BufType};
 use tuple::tuple_desc::TupleDesc;
 use tuple::tuple_ptr::TuplePtr;
 use utils;
@@ -22,11 +23,13 @@ static DEFAULT_LSN: LSN = 1;
 static STATE_PTR: TuplePtr = TuplePtr::new(META_BUF_KEY, 0);
 static CUR_ID_PTR: TuplePtr = TuplePtr::new(META_BUF_KEY, 1);
 static CUR_LSN_PTR: TuplePtr = TuplePtr::new(META_BUF_KEY, 2);
+static PAGE_SIZE_PTR: TuplePtr = TuplePtr::new(META_BUF_KEY, 3);
+static PAGE_FORMAT_PTR: TuplePtr = TuplePtr::new(META_BUF_KEY, 4);
 
 #[derive(Clone, Debug)]
 pub struct Meta {
     // Keep hold of page from BufMgr so it's never evicted
-    buf: PageLock,
+    buf: PinnedPage,
     cur_id: Arc<AtomicU32>,
     cur_lsn: Arc<AtomicU32>,
     pub table_index: HashIndex,
@@ -58,11 +61,34 @@ impl Meta {
         utils::assert_data_len(&lsn_data, 4)?;
         let cur_lsn = Arc::new(
             AtomicU32::new(bincode::deserialize(&lsn_data)?));
+
+        let page_size: u32 =
+            bincode::deserialize(lock.get_tuple_data(&PAGE_SIZE_PTR)?)?;
+        if page_size as usize != buf_mgr.page_size() {
+            return Err(Error::Internal(format!(
+                "Page size is {} but the meta page has {}",
+                buf_mgr.page_size(),
+                page_size
+            )));
+        }
+        let version = match lock.tuple_count() {
+            5 => bincode::deserialize(lock.get_tuple_data(&PAGE_FORMAT_PTR)?)?,
+            _ => 1u32,
+        };
+        if version != PAGE_FORMAT_VERSION {
+            return Err(Error::Internal(format!(
+                "Database has page format version {}, expected {}",
+                version, PAGE_FORMAT_VERSION
+            )));
+        }
+        let table_meta = buf_mgr.get_buf(&TABLE_BUF_KEY)?;
+        HashIndex::check_format(&table_meta.read().unwrap())?;
         let table_index = HashIndex {
             file_id: TABLE_REL_ID,
             overflow_file_id: TABLE_OVERFLOW_ID,
             key_desc: table_rel_desc(),
             rel_id: 0,
+            include_desc: TupleDesc::empty(),
         };
 
         Ok(Meta { buf: buf.clone(), cur_id, cur_lsn, table_index })
@@ -74,14 +100,18 @@ impl Meta {
         // State
         let state_data = bincode::serialize(&State::Down)?;
         guard.write_tuple_data(&state_data, None, None)?;
-        // TODO ID and LSN counter will be corrupted when the db crashes
-        // The recovery process should update ID and LSN based on the op.
         // ID Counter
         guard.write_tuple_data(
             &bincode::serialize(&DEFAULT_ID)?, None, None)?;
         // LSN Counter
         guard.write_tuple_data(
             &bincode::serialize(&DEFAULT_LSN)?, None, None)?;
+        // Page size
+        guard.write_tuple_data(
+            &bincode::serialize(&(buf_mgr.page_size() as u32))?, None, None)?;
+        // Page format
+        guard.write_tuple_data(
+            &bincode::serialize(&PAGE_FORMAT_VERSION)?, None, None)?;
         // Table name hash index
         let table_index = HashIndex::new_meta(
             TABLE_REL_ID, TABLE_OVERFLOW_ID, table_rel_desc(), buf_mgr)?;
@@ -94,6 +124,46 @@ impl Meta {
         })
     }
 
+    /// Page size of the database in data_dir, None if it is not created.
+    /// The slot of the page size tuple is at a fixed offset of the meta
+    /// page, the checksum of the page then confirms the size.
+    pub fn page_size_on_disk(data_dir: &str) -> Result<Option<usize>> {
+        use std::fs::File;
+        use std::io::{ErrorKind, Read};
+        use storage::buf_page::{HEADER_SIZE, LOWER_PTR_RANGE, SLOT_SIZE};
+        use storage::{MAX_PAGE_SIZE, MIN_PAGE_SIZE};
+
+        let filename = META_BUF_KEY.to_filename(data_dir.to_string());
+        let mut buf = vec![];
+        match File::open(filename) {
+            Ok(file) => file.take(MAX_PAGE_SIZE as u64).read_to_end(&mut buf)?,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if buf.is_empty() {
+            return Ok(None);
+        }
+
+        let corrupted = || Error::CorruptedPage(META_BUF_KEY);
+        let read_u32 = |start: usize| -> Result<usize> {
+            let data = buf.get(start..start + 4).ok_or_else(corrupted)?;
+            Ok(bincode::deserialize::<u32>(data)? as usize)
+        };
+        let slot = HEADER_SIZE + PAGE_SIZE_PTR.buf_offset * SLOT_SIZE;
+        if read_u32(LOWER_PTR_RANGE.start)? < slot + SLOT_SIZE {
+            return Err(corrupted());
+        }
+        let page_size = read_u32(read_u32(slot)?)?;
+        let valid = page_size.is_power_of_two()
+            && (MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size)
+            && buf.len() >= page_size
+            && BufPage::load_from(&buf[..page_size], &META_BUF_KEY).is_ok();
+        if !valid {
+            return Err(corrupted());
+        }
+        Ok(Some(page_size))
+    }
+
     pub fn set_state(&self, state: State) -> Result<()> {
         let mut guard = self.buf.write().unwrap();
         guard.write_tuple_data(
@@ -112,6 +182,19 @@ impl Meta {
         self.cur_lsn.fetch_add(1, Ordering::SeqCst)
     }
 
+    /// The LSN the next entry will get
+    pub fn cur_lsn(&self) -> LSN {
+        self.cur_lsn.load(Ordering::SeqCst)
+    }
+
+    /// Make sure the counters never hand out IDs or LSNs lower than
+    /// next_id and next_lsn, the persisted counters can be stale after
+    /// a crash
+    pub fn advance_counters(&self, next_id: ID, next_lsn: LSN) {
+        self.cur_id.fetch_max(next_id, Ordering::SeqCst);
+        self.cur_lsn.fetch_max(next_lsn, Ordering::SeqCst);
+    }
+
     pub fn persist_counters(&self) -> Result<()> {
         let mut guard = self.buf.write().unwrap();
         guard.write_tuple_data(
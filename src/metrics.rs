@@ -0,0 +1,39 @@
+use error::Result;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use storage::BufMgr;
+
+/// Serve the BufMgr stats in the Prometheus text format on a local port,
+/// every request gets the stats whatever its path
+pub fn serve(port: u16, buf_mgr: BufMgr) -> Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    std::thread::spawn(move || {
+        // A failed request must not stop the endpoint
+        for stream in listener.incoming().flatten() {
+            let _ = respond(stream, &buf_mgr);
+        }
+    });
+    Ok(())
+}
+
+fn respond(stream: TcpStream, buf_mgr: &BufMgr) -> Result<()> {
+    // Read the request up to the blank line ending its headers
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let body = buf_mgr.stats().to_prometheus();
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
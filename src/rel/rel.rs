@@ -1,23 +1,26 @@
 use bincode;
 use db_state::DbState;
-use error::Result;
+use error::{Error, Result};
 use index::{HashIndex, Index, IndexType};
 use internal_types::{TupleData, ID, LSN};
 use log::{LogEntry, OpType};
 use nom_sql::Literal;
 use serde::{Deserialize, Serialize};
+use storage::buf_page::SLOT_SIZE;
 use storage::{BufKey, BufMgr, BufPage, BufType};
 use tuple::{TupleDesc, TuplePtr};
 use utils;
 
 /// Represent a Relation on disk:
-///     - First page of file is metadata of the relation
+///     - First page of file is metadata of the relation:
+///       TupleDesc, indices, then the name if the rel is a table
 #[derive(Clone, Debug)]
 pub struct Rel {
     pub rel_id: ID,
     buf_type: BufType,
     tuple_desc: TupleDesc,
     indices: Vec<IndexInfo>,
+    name: Option<String>,
 }
 
 impl Rel {
@@ -31,18 +34,23 @@ impl Rel {
             .get_buf(&BufKey::new(rel_id, 0, buf_type))?;
         let lock = buf_page.read().unwrap();
 
-        assert!(lock.tuple_count() == 2);
+        assert!(lock.tuple_count() == 2 || lock.tuple_count() == 3);
 
         let mut iter = lock.iter();
         let tuple_desc: TupleDesc = bincode::deserialize(iter.next().unwrap())?;
         let indices: Vec<IndexInfo> =
             bincode::deserialize(iter.next().unwrap())?;
+        let name = match iter.next() {
+            Some(data) => Some(bincode::deserialize(data)?),
+            None => None,
+        };
 
         Ok(Rel {
             rel_id,
             buf_type,
             tuple_desc,
             indices,
+            name,
         })
     }
 
@@ -54,16 +62,18 @@ impl Rel {
         db_state: &mut DbState,
     ) -> Result<Rel> {
         let rel_id = db_state.meta.get_new_id();
+        let name = name.into();
         let rel = Rel {
             rel_id,
             tuple_desc,
             buf_type: BufType::Data,
             indices: vec![],
+            name: Some(name.clone()),
         };
         Rel::write_new_rel(&mut db_state.buf_mgr, &rel)?;
         // Add an entry to the table index
         let entry = (
-            bincode::serialize(&name.into())?,
+            bincode::serialize(&name)?,
             TuplePtr::new(rel.meta_buf_key(), 0));
         let index = db_state.meta.table_index.clone();
         index.insert(
@@ -81,6 +91,7 @@ impl Rel {
             tuple_desc,
             buf_type: BufType::Temp,
             indices: vec![],
+            name: None,
         };
         Rel::write_new_rel(&mut db_state.buf_mgr, &rel)?;
         Ok(rel)
@@ -97,6 +108,7 @@ impl Rel {
             tuple_desc,
             buf_type: BufType::Data,
             indices: vec![],
+            name: None,
         };
         Rel::write_new_rel(buf_mgr, &rel)?;
         Ok(rel)
@@ -116,54 +128,51 @@ impl Rel {
         let mem_page = db_state.buf_mgr.new_mem_buf()?;
         let mut mem_guard = mem_page.write().unwrap();
 
+        // Tuples are first written to a copy of the page, the page itself
+        // is only changed once their entries are logged
+        let copy_page = db_state.buf_mgr.new_mem_buf()?;
+        let mut copy_guard = copy_page.write().unwrap();
+
         let mut page_key = self.last_buf_key(&mut db_state.buf_mgr)?;
         let mut result = vec![];
-        let mut tup = match tuples.next() {
-            Some(tup) => tup,
-            None => return Ok(result)
-        };
-        loop {
-            let page = db_state.buf_mgr.get_buf(&page_key)?;
+        let mut next = tuples.next();
+        while next.is_some() {
+            // new_buf appends the page if it is past the end of the file
+            let page = db_state.buf_mgr.new_buf(&page_key)?;
             let mut guard = page.write().unwrap();
-            loop {
-                if guard.available_data_space() < tup.len() {
-                    page_key = page_key.inc_offset();
+            copy_guard.clone_from(&guard);
+            let mut page_tuples = vec![];
+            let mut entries = vec![];
+            while let Some(tup) = next.take() {
+                if copy_guard.available_data_space() < tup.len() {
+                    next = Some(tup);
                     break;
                 }
-                let ptr = self.write_tuple(
-                    &tup, &mut guard, db_state)?;
+                let entry = self.log_entry(
+                    page_key, OpType::InsertTuple, tup.clone(), db_state)?;
+                let lsn = entry.as_ref().map(|entry| entry.header.lsn);
+                entries.extend(entry);
+                copy_guard.write_tuple_data(&tup, None, lsn)?;
+                page_tuples.push((tup, lsn));
+                next = tuples.next();
+            }
+            if page_tuples.is_empty() && guard.tuple_count() == 0 {
+                return Err(Error::internal("Tuple does not fit in a page"));
+            }
+
+            db_state.log_mgr.write_page_entries(
+                entries, &mut guard, &mut db_state.buf_mgr)?;
+            for (tup, lsn) in page_tuples.into_iter() {
+                let ptr = guard.write_tuple_data(&tup, None, lsn)?;
                 self.handle_index_item(
                     &tup, &ptr, &index_writer_info, &mut mem_guard,
                     db_state)?;
                 result.push(ptr);
-                tup = match tuples.next() {
-                    Some(tup) => tup,
-                    None => {
-                        index_writer_info.write_items(
-                            &mut mem_guard, None, db_state)?;
-                        return Ok(result);
-                    }
-                };
             }
+            page_key = page_key.inc_offset();
         }
-    }
-
-    fn write_tuple(
-        &self,
-        tuple: &TupleData,
-        page: &mut BufPage,
-        db_state: &mut DbState,
-    ) -> Result<TuplePtr> {
-        let lsn = match self.buf_type {
-            BufType::Data => Some(self.write_insert_log(
-                page.buf_key,
-                tuple.clone(),
-                db_state,
-            )?),
-            _ => None,
-        };
-        let ptr = page.write_tuple_data(&tuple, None, lsn)?;
-        Ok(ptr)
+        index_writer_info.write_items(&mut mem_guard, None, db_state)?;
+        Ok(result)
     }
 
     fn handle_index_item(
@@ -183,16 +192,35 @@ impl Rel {
         Ok(())
     }
 
+    /// Add an index on the key columns, the tuples already in the rel
+    /// are indexed in bulk
     pub fn new_index(
         &mut self,
         key: Vec<usize>,
         index_type: IndexType,
         db_state: &mut DbState,
+    ) -> Result<IndexInfo> {
+        self.new_covering_index(key, vec![], index_type, db_state)
+    }
+
+    /// Add an index that also stores the include columns, queries that
+    /// only need key and include columns do not read the tuples
+    pub fn new_covering_index(
+        &mut self,
+        key: Vec<usize>,
+        include: Vec<usize>,
+        index_type: IndexType,
+        db_state: &mut DbState,
     ) -> Result<IndexInfo> {
         let key_desc = self.tuple_desc.subset(&key)?;
+        let include_desc = self.tuple_desc.subset(&include)?;
+        let cols = [key.clone(), include.clone()].concat();
+        let items = self.index_items(&cols, db_state)?;
         let file_id = match &index_type {
             &IndexType::Hash => {
-                HashIndex::new(self.rel_id, key_desc, db_state)?.file_id
+                HashIndex::build(
+                    self.rel_id, key_desc, include_desc, items, db_state)?
+                    .file_id
             }
         };
 
@@ -200,6 +228,7 @@ impl Rel {
             file_id,
             key,
             index_type,
+            include,
         };
         let meta_page = db_state.buf_mgr.get_buf(&self.meta_buf_key())?;
         let mut meta_lock = meta_page.write().unwrap();
@@ -213,23 +242,516 @@ impl Rel {
             Some(&indices_ptr),
             None,
         )?;
-        //TODO Scan through current data and update index
         Ok(info)
     }
 
-    fn write_insert_log(
+    /// Replace each index with one built from the tuples of the rel,
+    /// returns the files of the old indices
+    pub fn rebuild_indices(
+        &mut self,
+        db_state: &mut DbState,
+    ) -> Result<Vec<ID>> {
+        let infos = std::mem::take(&mut self.indices);
+        let mut old_ids = vec![];
+        for info in infos.into_iter() {
+            old_ids.push(info.file_id);
+            match info.index_type {
+                // The overflow file is unknown if the meta page is torn
+                IndexType::Hash => {
+                    let index = HashIndex::load(info.file_id, db_state).ok();
+                    old_ids.extend(index.map(|index| index.overflow_file_id));
+                }
+            };
+            self.new_covering_index(
+                info.key, info.include, info.index_type, db_state)?;
+        }
+        Ok(old_ids)
+    }
+
+    /// Rebuild the indices of every table, see Rel::rebuild_indices
+    pub fn rebuild_all_indices(db_state: &mut DbState) -> Result<Vec<ID>> {
+        let table_index = db_state.meta.table_index.clone();
+        let mut old_ids = vec![];
+        for ptr in table_index.all_ptrs(db_state)?.into_iter() {
+            let key = ptr.buf_key;
+            let mut rel = Rel::load(key.file_id, key.buf_type, db_state)?;
+            old_ids.extend(rel.rebuild_indices(db_state)?);
+        }
+        Ok(old_ids)
+    }
+
+    /// The cols and pointer of every tuple
+    fn index_items(
+        &self,
+        cols: &[usize],
+        db_state: &mut DbState,
+    ) -> Result<Vec<(TupleData, TuplePtr)>> {
+        let cols = cols.to_vec();
+        let mut items = vec![];
+        for page_idx in 1..self.num_pages(&mut db_state.buf_mgr)? + 1 {
+            let page = db_state.buf_mgr.get_buf(
+                &BufKey::new(self.rel_id, page_idx, self.buf_type))?;
+            let guard = page.read().unwrap();
+            for ptr in guard.get_all_ptrs().into_iter() {
+                let data = guard.get_tuple_data(&ptr)?.to_vec();
+                items.push((self.tuple_desc.data_subset(&data, &cols)?, ptr));
+            }
+        }
+        Ok(items)
+    }
+
+    /// Compact pages with gaps in their pointer section and truncate
+    /// trailing empty pages. Index entries of moved tuples are updated.
+    pub fn vacuum(&self, db_state: &mut DbState) -> Result<VacuumStats> {
+        let meta = db_state.buf_mgr.get_buf(&self.meta_buf_key())?;
+        let rel_lock = meta.write().unwrap();
+        let infos = self.load_indices(&rel_lock)?;
+        let index_writer_info = IndexWriterInfo::new(
+            infos.clone(), self.tuple_desc(), db_state)?;
+
+        let mut stats = VacuumStats::default();
+        let num_pages = self.num_pages(&mut db_state.buf_mgr)?;
+        // The first data page is always kept
+        let mut last_used = 1;
+        for page_idx in 1..num_pages + 1 {
+            let page_key = BufKey::new(self.rel_id, page_idx, self.buf_type);
+            let page = db_state.buf_mgr.get_buf(&page_key)?;
+            let mut guard = page.write().unwrap();
+            if guard.gap_count() > 0 {
+                let lsn = self.write_page_log(
+                    &mut guard, OpType::CompactPage, vec![], db_state)?;
+                stats.pages_compacted += 1;
+                stats.slots_reclaimed += guard.gap_count() as usize;
+                let moved = guard
+                    .compact(lsn)?
+                    .into_iter()
+                    .map(|(old, new)| {
+                        Ok((guard.get_tuple_data(&new)?.to_vec(), old, new))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                index_writer_info.update_ptrs(&moved, db_state)?;
+            }
+            if guard.tuple_count() > 0 {
+                last_used = page_idx;
+            }
+        }
+
+        if last_used < num_pages {
+            db_state.buf_mgr.check_unpinned(|key| {
+                key.file_id == self.rel_id
+                    && key.buf_type == self.buf_type
+                    && key.offset > last_used
+            })?;
+            self.write_log(
+                BufKey::new(self.rel_id, last_used + 1, self.buf_type),
+                OpType::TruncateFile,
+                vec![],
+                db_state,
+            )?;
+            db_state.buf_mgr.truncate_file(
+                self.rel_id, self.buf_type, last_used + 1)?;
+            stats.pages_truncated = (num_pages - last_used) as usize;
+        }
+
+        for info in infos.iter() {
+            stats.slots_reclaimed += match info.index_type {
+                IndexType::Hash => {
+                    HashIndex::load(info.file_id, db_state)?.vacuum(db_state)?
+                }
+            };
+        }
+        Ok(stats)
+    }
+
+    /// Remove the rel from the table index and delete its files,
+    /// including the files of its indices
+    pub fn drop_table<S: Into<String>>(
+        &self,
+        name: S,
+        db_state: &mut DbState,
+    ) -> Result<()> {
+        let data = {
+            let meta = db_state.buf_mgr.get_buf(&self.meta_buf_key())?;
+            let rel_lock = meta.write().unwrap();
+            DropRelData {
+                name: name.into(),
+                file_ids: self.file_ids(&rel_lock, db_state)?,
+            }
+        };
+        db_state.buf_mgr.check_unpinned(|key| {
+            data.file_ids.contains(&key.file_id)
+                && key.buf_type == self.buf_type
+        })?;
+        self.write_log(
+            self.meta_buf_key(),
+            OpType::DropRel,
+            bincode::serialize(&data)?,
+            db_state,
+        )?;
+        Rel::drop_files(&data, self.meta_buf_key(), db_state)
+    }
+
+    /// Remove every tuple of the rel and empty its indices
+    pub fn truncate(&self, db_state: &mut DbState) -> Result<()> {
+        let meta = db_state.buf_mgr.get_buf(&self.meta_buf_key())?;
+        let rel_lock = meta.write().unwrap();
+        // The data pages are discarded, the meta page is kept
+        let file_ids = self.file_ids(&rel_lock, db_state)?;
+        db_state.buf_mgr.check_unpinned(|key| {
+            file_ids.contains(&key.file_id)
+                && key.buf_type == self.buf_type
+                && (key.file_id != self.rel_id || key.offset > 0)
+        })?;
+        let lsn = self.write_log(
+            self.meta_buf_key(), OpType::TruncateRel, vec![], db_state)?;
+        self.reset(&rel_lock, lsn, db_state)
+    }
+
+    /// Copy the rel into a new file described by tuple_desc, mapping each
+    /// tuple with transform. The copy then replaces this rel under name.
+    pub fn rewrite<F>(
+        &self,
+        name: &str,
+        tuple_desc: TupleDesc,
+        indices: Vec<(Vec<usize>, Vec<usize>, IndexType)>,
+        transform: F,
+        db_state: &mut DbState,
+    ) -> Result<Rel>
+    where
+        F: Fn(&[u8]) -> Result<TupleData>,
+    {
+        let meta = db_state.buf_mgr.get_buf(&self.meta_buf_key())?;
+        let rel_lock = meta.write().unwrap();
+
+        let mut new_rel = Rel {
+            rel_id: db_state.meta.get_new_id(),
+            buf_type: self.buf_type,
+            tuple_desc,
+            indices: vec![],
+            name: Some(name.to_string()),
+        };
+        Rel::write_new_rel(&mut db_state.buf_mgr, &new_rel)?;
+        for page_idx in 1..self.num_pages(&mut db_state.buf_mgr)? + 1 {
+            let page = db_state.buf_mgr.get_buf(
+                &BufKey::new(self.rel_id, page_idx, self.buf_type))?;
+            let tuples = {
+                let guard = page.read().unwrap();
+                guard
+                    .iter()
+                    .map(&transform)
+                    .collect::<Result<Vec<_>>>()?
+            };
+            new_rel.write_tuples(&mut tuples.into_iter(), db_state)?;
+        }
+        // Indices are built once all the tuples are copied
+        for (key, include, index_type) in indices.into_iter() {
+            new_rel.new_covering_index(key, include, index_type, db_state)?;
+        }
+        // Creating the new rel is not logged, it must be on disk before
+        // it replaces this rel
+        let new_file_ids = {
+            let new_meta =
+                db_state.buf_mgr.get_buf(&new_rel.meta_buf_key())?;
+            let new_lock = new_meta.read().unwrap();
+            new_rel.file_ids(&new_lock, db_state)?
+        };
+        for file_id in new_file_ids.into_iter() {
+            db_state.buf_mgr.store_file(file_id, self.buf_type)?;
+        }
+
+        let data = AlterRelData {
+            old_name: name.to_string(),
+            new_name: name.to_string(),
+            old_meta_key: self.meta_buf_key(),
+            new_meta_key: new_rel.meta_buf_key(),
+            file_ids: self.file_ids(&rel_lock, db_state)?,
+        };
+        // The old files are dropped, so their pages must not be pinned
+        drop(rel_lock);
+        drop(meta);
+        db_state.buf_mgr.check_unpinned(|key| {
+            data.file_ids.contains(&key.file_id)
+                && key.buf_type == self.buf_type
+        })?;
+        self.write_log(
+            new_rel.meta_buf_key(),
+            OpType::AlterRel,
+            bincode::serialize(&data)?,
+            db_state,
+        )?;
+        Rel::replace(&data, db_state)?;
+        Ok(new_rel)
+    }
+
+    /// Register the rel under new_name instead of name
+    pub fn rename(
+        &self,
+        name: &str,
+        new_name: &str,
+        db_state: &mut DbState,
+    ) -> Result<()> {
+        if utils::find_table_id(new_name.to_string(), db_state)?.is_some() {
+            return Err(Error::Internal(
+                format!("Table {} already exists", new_name)));
+        }
+        let data = AlterRelData {
+            old_name: name.to_string(),
+            new_name: new_name.to_string(),
+            old_meta_key: self.meta_buf_key(),
+            new_meta_key: self.meta_buf_key(),
+            file_ids: vec![],
+        };
+        self.write_log(
+            self.meta_buf_key(),
+            OpType::AlterRel,
+            bincode::serialize(&data)?,
+            db_state,
+        )?;
+        Rel::replace(&data, db_state)
+    }
+
+    /// Rename column attr in the meta page of the rel and its indices,
+    /// the tuples do not change
+    pub fn rename_attr(
+        &self,
+        attr: usize,
+        new_name: &str,
+        db_state: &mut DbState,
+    ) -> Result<()> {
+        let meta = db_state.buf_mgr.get_buf(&self.meta_buf_key())?;
+        let mut rel_lock = meta.write().unwrap();
+        let data = RenameAttrData {
+            attr,
+            new_name: new_name.to_string(),
+        };
+        let lsn = self.write_log(
+            self.meta_buf_key(),
+            OpType::RenameAttr,
+            bincode::serialize(&data)?,
+            db_state,
+        )?;
+        self.write_attr_name(&mut rel_lock, &data, lsn, db_state)
+    }
+
+    /// Redo a RenameAttr log entry, the entry's buf_key is the meta key
+    pub fn redo_rename_attr(
+        entry: &LogEntry,
+        db_state: &mut DbState,
+    ) -> Result<()> {
+        let meta_key = entry.header.buf_key;
+        // The rel was dropped or rewritten after the rename
+        if !utils::file_exists(&db_state.buf_mgr.key_to_filename(meta_key)) {
+            return Ok(());
+        }
+        let data: RenameAttrData = bincode::deserialize(&entry.data)?;
+        let rel = Rel::load(meta_key.file_id, meta_key.buf_type, db_state)?;
+        let meta = db_state.buf_mgr.get_buf(&meta_key)?;
+        let mut rel_lock = meta.write().unwrap();
+        rel.write_attr_name(
+            &mut rel_lock, &data, Some(entry.header.lsn), db_state)
+    }
+
+    /// Redo an AlterRel log entry
+    pub fn redo_alter(entry: &LogEntry, db_state: &mut DbState) -> Result<()> {
+        let data: AlterRelData = bincode::deserialize(&entry.data)?;
+        Rel::replace(&data, db_state)
+    }
+
+    /// Redo a DropRel log entry, the entry's buf_key is the meta key
+    pub fn redo_drop(entry: &LogEntry, db_state: &mut DbState) -> Result<()> {
+        let data: DropRelData = bincode::deserialize(&entry.data)?;
+        Rel::drop_files(&data, entry.header.buf_key, db_state)
+    }
+
+    /// Redo a TruncateRel log entry, the entry's buf_key is the meta key
+    pub fn redo_truncate(
+        entry: &LogEntry,
+        db_state: &mut DbState,
+    ) -> Result<()> {
+        let meta_key = entry.header.buf_key;
+        // The rel was dropped after it was truncated
+        if !utils::file_exists(&db_state.buf_mgr.key_to_filename(meta_key)) {
+            return Ok(());
+        }
+        // Page 1 is stamped with the entry's LSN once the truncate is done
+        let done = match db_state.buf_mgr.get_buf(&meta_key.inc_offset()) {
+            Ok(page) => page.read().unwrap().lsn >= entry.header.lsn,
+            Err(_) => false,
+        };
+        if done {
+            return Ok(());
+        }
+
+        let rel = Rel::load(meta_key.file_id, meta_key.buf_type, db_state)?;
+        let meta = db_state.buf_mgr.get_buf(&meta_key)?;
+        let rel_lock = meta.write().unwrap();
+        rel.reset(&rel_lock, Some(entry.header.lsn), db_state)
+    }
+
+    fn drop_files(
+        data: &DropRelData,
+        meta_key: BufKey,
+        db_state: &mut DbState,
+    ) -> Result<()> {
+        let entry = (
+            bincode::serialize(&data.name)?,
+            TuplePtr::new(meta_key, 0));
+        let index = db_state.meta.table_index.clone();
+        index.remove(&mut vec![entry].into_iter(), db_state)?;
+        for file_id in data.file_ids.iter() {
+            db_state.buf_mgr.drop_file(*file_id, meta_key.buf_type)?;
+        }
+        Ok(())
+    }
+
+    fn replace(data: &AlterRelData, db_state: &mut DbState) -> Result<()> {
+        let index = db_state.meta.table_index.clone();
+        let old_entry = (
+            bincode::serialize(&data.old_name)?,
+            TuplePtr::new(data.old_meta_key, 0));
+        index.remove(&mut vec![old_entry].into_iter(), db_state)?;
+
+        let key = bincode::serialize(&data.new_name)?;
+        let new_ptr = TuplePtr::new(data.new_meta_key, 0);
+        // The entry is already there when the replace is redone
+        if !index.get(&key, db_state)?.contains(&new_ptr) {
+            index.insert(&mut vec![(key, new_ptr)].into_iter(), db_state)?;
+        }
+        // The name is also kept on the meta page of the rel
+        let filename = db_state.buf_mgr.key_to_filename(data.new_meta_key);
+        if utils::file_exists(&filename) {
+            let meta = db_state.buf_mgr.get_buf(&data.new_meta_key)?;
+            let mut rel_lock = meta.write().unwrap();
+            Rel::write_name(&mut rel_lock, &data.new_name)?;
+        }
+        for file_id in data.file_ids.iter() {
+            db_state.buf_mgr.drop_file(*file_id, data.old_meta_key.buf_type)?;
+        }
+        Ok(())
+    }
+
+    fn write_attr_name(
+        &self,
+        meta: &mut BufPage,
+        data: &RenameAttrData,
+        lsn: Option<LSN>,
+        db_state: &mut DbState,
+    ) -> Result<()> {
+        let mut attr_names = self.tuple_desc.attr_names();
+        attr_names[data.attr] = data.new_name.clone();
+        let desc = TupleDesc::new(self.tuple_desc.attr_types(), attr_names);
+        meta.write_tuple_data(
+            &bincode::serialize(&desc)?,
+            Some(&TuplePtr::new(meta.buf_key, 0)),
+            lsn,
+        )?;
+        // Indices find their columns in the rel by name
+        for info in self.load_indices(meta)? {
+            match info.index_type {
+                IndexType::Hash => HashIndex::load(info.file_id, db_state)?
+                    .write_descs(
+                        &desc.subset(&info.key)?,
+                        &desc.subset(&info.include)?,
+                        &mut db_state.buf_mgr,
+                    )?,
+            }
+        }
+        Ok(())
+    }
+
+    fn reset(
+        &self,
+        meta: &BufPage,
+        lsn: Option<LSN>,
+        db_state: &mut DbState,
+    ) -> Result<()> {
+        db_state.buf_mgr.truncate_file(self.rel_id, self.buf_type, 1)?;
+        let first_page = db_state.buf_mgr.new_buf(&meta.buf_key.inc_offset())?;
+        if let Some(lsn) = lsn {
+            first_page.write().unwrap().set_lsn(lsn)?;
+        }
+        for info in self.load_indices(meta)? {
+            match info.index_type {
+                IndexType::Hash => HashIndex::load(info.file_id, db_state)?
+                    .reset(&mut db_state.buf_mgr)?,
+            };
+        }
+        Ok(())
+    }
+
+    /// IDs of the rel's data file and all of its index files
+    fn file_ids(
+        &self,
+        meta: &BufPage,
+        db_state: &mut DbState,
+    ) -> Result<Vec<ID>> {
+        let mut file_ids = vec![self.rel_id];
+        for info in self.load_indices(meta)? {
+            match info.index_type {
+                IndexType::Hash => {
+                    let index = HashIndex::load(info.file_id, db_state)?;
+                    file_ids.push(index.file_id);
+                    file_ids.push(index.overflow_file_id);
+                }
+            };
+        }
+        Ok(file_ids)
+    }
+
+    /// Log an operation on a page of this rel, temp rels are not logged
+    fn write_log(
         &self,
         buf_key: BufKey,
+        op: OpType,
         data: Vec<u8>,
         db_state: &mut DbState,
-    ) -> Result<LSN> {
-        let entry =
-            LogEntry::new(buf_key, OpType::InsertTuple, data, db_state)?;
-        let lsn = entry.header.lsn;
-        db_state
-            .log_mgr
-            .write_entries(vec![entry], &mut db_state.buf_mgr)?;
-        Ok(lsn)
+    ) -> Result<Option<LSN>> {
+        match self.log_entry(buf_key, op, data, db_state)? {
+            Some(entry) => {
+                let lsn = entry.header.lsn;
+                db_state
+                    .log_mgr
+                    .write_entries(vec![entry], &mut db_state.buf_mgr)?;
+                Ok(Some(lsn))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Log an operation on a locked page of this rel,
+    /// see LogMgr::write_page_entries
+    fn write_page_log(
+        &self,
+        page: &mut BufPage,
+        op: OpType,
+        data: Vec<u8>,
+        db_state: &mut DbState,
+    ) -> Result<Option<LSN>> {
+        match self.log_entry(page.buf_key, op, data, db_state)? {
+            Some(entry) => {
+                let lsn = entry.header.lsn;
+                db_state.log_mgr.write_page_entries(
+                    vec![entry], page, &mut db_state.buf_mgr)?;
+                Ok(Some(lsn))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Entry for an operation on a page of this rel, without writing it
+    fn log_entry(
+        &self,
+        buf_key: BufKey,
+        op: OpType,
+        data: Vec<u8>,
+        db_state: &mut DbState,
+    ) -> Result<Option<LogEntry>> {
+        match self.buf_type {
+            BufType::Data => {
+                Ok(Some(LogEntry::new(buf_key, op, data, db_state)?))
+            }
+            _ => Ok(None),
+        }
     }
 
     pub fn scan<Filter, Then>(
@@ -245,12 +767,13 @@ impl Rel {
         let meta = db_state.buf_mgr.get_buf(&self.meta_buf_key())?;
         let _meta_guard = meta.read().unwrap();
 
-        for page_idx in 1..self.num_pages(&mut db_state.buf_mgr)? + 1 {
-            let page = db_state.buf_mgr.get_buf(&BufKey::new(
-                self.rel_id,
-                page_idx as u64,
-                self.buf_type,
-            ))?;
+        let keys = (1..self.num_pages(&mut db_state.buf_mgr)? + 1)
+            .map(|page_idx| {
+                BufKey::new(self.rel_id, page_idx, self.buf_type)
+            })
+            .collect();
+        for page in db_state.buf_mgr.read_ahead(keys) {
+            let page = page?;
             let guard = page.read().unwrap();
             for tup in guard.iter() {
                 if filter(&*tup)? {
@@ -285,8 +808,12 @@ impl Rel {
         self.indices.clone()
     }
 
+    pub fn name(&self) -> Option<String> {
+        self.name.clone()
+    }
+
     fn load_indices(&self, meta: &BufPage) -> Result<Vec<IndexInfo>> {
-        assert!(meta.tuple_count() == 2);
+        assert!(meta.tuple_count() >= 2);
         let mut iter = meta.iter();
         iter.next();
         Ok(bincode::deserialize(iter.next().unwrap())?)
@@ -309,6 +836,20 @@ impl Rel {
             None,
             None,
         )?;
+        if let Some(ref name) = rel.name {
+            Rel::write_name(&mut lock, name)?;
+        }
+        Ok(())
+    }
+
+    fn write_name(meta: &mut BufPage, name: &str) -> Result<()> {
+        let data = bincode::serialize(name)?;
+        // Rels created before names were stored only have 2 tuples
+        let ptr = match meta.tuple_count() {
+            2 => None,
+            _ => Some(TuplePtr::new(meta.buf_key, 2)),
+        };
+        meta.write_tuple_data(&data, ptr.as_ref(), None)?;
         Ok(())
     }
 
@@ -325,9 +866,9 @@ impl Rel {
     }
 
     //TODO Compare between saving num_pages in 1st page and getting file len
-    fn num_pages(&self, buf_mgr: &mut BufMgr) -> Result<u64> {
+    pub fn num_pages(&self, buf_mgr: &mut BufMgr) -> Result<u64> {
         let rel_filename = buf_mgr.key_to_filename(self.meta_buf_key());
-        utils::num_pages(&rel_filename)
+        utils::num_pages(&rel_filename, buf_mgr.page_size())
     }
 }
 
@@ -336,6 +877,53 @@ pub struct IndexInfo {
     pub file_id: ID,
     pub key: Vec<usize>,
     pub index_type: IndexType,
+    /// Columns stored in the index next to the key
+    pub include: Vec<usize>,
+}
+
+impl IndexInfo {
+    /// Whether the index has every column in cols
+    pub fn covers(&self, cols: &[usize]) -> bool {
+        cols.iter()
+            .all(|col| self.key.contains(col) || self.include.contains(col))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VacuumStats {
+    pub pages_compacted: usize,
+    pub slots_reclaimed: usize,
+    pub pages_truncated: usize,
+}
+
+impl VacuumStats {
+    pub fn bytes_reclaimed(&self, page_size: usize) -> usize {
+        self.slots_reclaimed * SLOT_SIZE + self.pages_truncated * page_size
+    }
+}
+
+/// The table index entry of old_name is replaced by one of new_name,
+/// file_ids are the files of the old rel that are deleted
+#[derive(Serialize, Deserialize)]
+struct AlterRelData {
+    old_name: String,
+    new_name: String,
+    old_meta_key: BufKey,
+    new_meta_key: BufKey,
+    file_ids: Vec<ID>,
+}
+
+/// Column attr of the rel is renamed to new_name
+#[derive(Serialize, Deserialize)]
+struct RenameAttrData {
+    attr: usize,
+    new_name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DropRelData {
+    name: String,
+    file_ids: Vec<ID>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -361,14 +949,15 @@ impl IndexWriterInfo {
                     .map(|hash_index| Box::new(hash_index) as Box<dyn Index>)
             })
             .collect::<Result<Vec<_>>>()?;
+        let indices_desc = TupleDesc::union(
+            indices.iter().map(|index| index.item_desc()).collect())?;
+        // Cached index items only hold the attributes in indices_desc
         let indices_subsets = indices
             .iter()
-            .map(|index| rel_desc.attr_indices(
-                    index.key_desc().attr_names().iter()))
+            .map(|index| indices_desc.attr_indices(
+                    index.item_desc().attr_names().iter()))
             .collect::<Option<Vec<Vec<usize>>>>()
             .unwrap();
-        let indices_desc = TupleDesc::union(
-            indices.iter().map(|index| index.key_desc()).collect())?;
         let indices_subset = rel_desc.attr_indices(
             indices_desc.attr_names().iter()).unwrap();
         Ok(Self {
@@ -414,6 +1003,36 @@ impl IndexWriterInfo {
         Ok(())
     }
 
+    /// Point the index entries of moved tuples to their new location,
+    /// moved items are (tuple, old ptr, new ptr)
+    fn update_ptrs(
+        &self,
+        moved: &[(TupleData, TuplePtr, TuplePtr)],
+        db_state: &mut DbState,
+    ) -> Result<()> {
+        for index in self.indices.iter() {
+            let subset = self
+                .rel_desc
+                .attr_indices(index.item_desc().attr_names().iter())
+                .unwrap();
+            let keys = moved
+                .iter()
+                .map(|(data, old, new)| {
+                    Ok((self.rel_desc.data_subset(data, &subset)?, *old, *new))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            index.remove(
+                &mut keys.iter().map(|(key, old, _)| (key.clone(), *old)),
+                db_state,
+            )?;
+            index.insert(
+                &mut keys.into_iter().map(|(key, _, new)| (key, new)),
+                db_state,
+            )?;
+        }
+        Ok(())
+    }
+
     fn index_item_data(&self, tuple: &TupleData, ptr: &TuplePtr) -> Result<TupleData> {
         let index_item = InsertTupleIndexItem {
             data: self.rel_desc.data_subset(tuple, &self.indices_subset)?,
@@ -1,7 +1,8 @@
 use data_type::DataType;
 use index::{Index, HashIndex, IndexType};
 use super::Rel;
-use storage::BufType;
+use db_state::DbState;
+use storage::{BufKey, BufType};
 use tuple::TupleDesc;
 use test_utils::{setup, setup_no_persist, teardown};
 use utils;
@@ -24,6 +25,7 @@ fn test_new_rel() {
     teardown(db_state);
 
     assert_eq!(rel.tuple_desc(), desc);
+    assert_eq!(rel.name(), Some("test_new_rel".to_string()));
     assert_eq!(returned_id, id);
 }
 
@@ -118,3 +120,496 @@ fn test_write_with_index() {
     assert_eq!(ptr1, ptrs[0]);
     assert_eq!(ptr2, ptrs[1]);
 }
+
+#[test]
+fn test_vacuum() {
+    use nom_sql::Literal;
+    let mut db_state = setup_no_persist("test_vacuum");
+    let desc = TupleDesc::new(
+        vec![DataType::U32, DataType::U32],
+        vec!["first", "second"],
+    );
+    let mut rel = Rel::new("test_vacuum", desc, &mut db_state).unwrap();
+    let index_info =
+        rel.new_index(vec![0], IndexType::Hash, &mut db_state).unwrap();
+    let tuples = rel
+        .literal_to_data(vec![
+            vec![Literal::Integer(1), Literal::Integer(10)],
+            vec![Literal::Integer(2), Literal::Integer(11)],
+            vec![Literal::Integer(3), Literal::Integer(12)],
+        ])
+        .unwrap();
+    let ptrs = rel.write_tuples(
+        &mut tuples.into_iter(), &mut db_state).unwrap();
+    let index = HashIndex::load(index_info.file_id, &mut db_state).unwrap();
+    let key = |i: u32| bincode::serialize(&i).unwrap();
+
+    // Remove the second tuple, leaving a gap in the pointer section
+    {
+        let page = db_state.buf_mgr.get_buf(&ptrs[1].buf_key).unwrap();
+        let mut guard = page.write().unwrap();
+        guard.remove_tuple(&ptrs[1], None).unwrap();
+    }
+    index.remove(&mut vec![(key(2), ptrs[1])].into_iter(), &mut db_state)
+        .unwrap();
+
+    let stats = rel.vacuum(&mut db_state).unwrap();
+    let third = index.get(&key(3), &mut db_state).unwrap();
+    let gap_count = {
+        let page = db_state.buf_mgr.get_buf(&ptrs[0].buf_key).unwrap();
+        let guard = page.read().unwrap();
+        guard.gap_count()
+    };
+    teardown(db_state);
+
+    assert_eq!(stats.pages_compacted, 1);
+    assert!(stats.slots_reclaimed >= 1);
+    assert_eq!(stats.pages_truncated, 0);
+    assert_eq!(gap_count, 0);
+    // The third tuple moved into the second slot
+    assert_eq!(third, vec![ptrs[1]]);
+}
+
+#[test]
+fn test_vacuum_truncate() {
+    use nom_sql::Literal;
+    let mut db_state = setup_no_persist("test_vacuum_truncate");
+    let desc = TupleDesc::new(
+        vec![DataType::U32, DataType::VarChar],
+        vec!["id", "text"],
+    );
+    let rel = Rel::new("test_vacuum_truncate", desc, &mut db_state).unwrap();
+    // Two tuples fit in a page
+    let text = "a".repeat(1500);
+    let tuples = rel
+        .literal_to_data((0..4).map(|i| vec![
+            Literal::Integer(i),
+            Literal::String(text.clone()),
+        ]).collect())
+        .unwrap();
+    let ptrs = rel.write_tuples(
+        &mut tuples.into_iter(), &mut db_state).unwrap();
+    assert_eq!(ptrs[3].buf_key.offset, 2);
+
+    // Empty the last page
+    {
+        let page = db_state.buf_mgr.get_buf(&ptrs[3].buf_key).unwrap();
+        let mut guard = page.write().unwrap();
+        guard.remove_tuple(&ptrs[3], None).unwrap();
+        guard.remove_tuple(&ptrs[2], None).unwrap();
+    }
+
+    let stats = rel.vacuum(&mut db_state).unwrap();
+    let num_pages = rel.num_pages(&mut db_state.buf_mgr).unwrap();
+    let has_page = db_state.buf_mgr.has_buf(&ptrs[3].buf_key);
+    teardown(db_state);
+
+    assert_eq!(stats.pages_truncated, 1);
+    assert_eq!(num_pages, 1);
+    assert!(!has_page);
+}
+
+#[test]
+fn test_drop_table() {
+    use nom_sql::Literal;
+    let mut db_state = setup_no_persist("test_drop_table");
+    let desc = TupleDesc::new(
+        vec![DataType::U32, DataType::U32],
+        vec!["first", "second"],
+    );
+    let mut rel = Rel::new("test_drop_table", desc, &mut db_state).unwrap();
+    let index_info =
+        rel.new_index(vec![0], IndexType::Hash, &mut db_state).unwrap();
+    let index = HashIndex::load(index_info.file_id, &mut db_state).unwrap();
+    let tuples = rel
+        .literal_to_data(vec![vec![Literal::Integer(1), Literal::Integer(10)]])
+        .unwrap();
+    rel.write_tuples(&mut tuples.into_iter(), &mut db_state).unwrap();
+
+    rel.drop_table("test_drop_table", &mut db_state).unwrap();
+    let table_id = utils::find_table_id(
+        "test_drop_table".to_string(), &mut db_state).unwrap();
+    let filenames: Vec<String> = vec![
+        rel.rel_id, index.file_id, index.overflow_file_id,
+    ]
+    .into_iter()
+    .map(|id| db_state.buf_mgr.key_to_filename(
+        BufKey::new(id, 0, BufType::Data)))
+    .collect();
+    let has_page = db_state.buf_mgr.has_buf(&rel.meta_buf_key());
+    teardown(db_state);
+
+    assert_eq!(table_id, None);
+    assert!(!has_page);
+    for filename in filenames.iter() {
+        assert!(!utils::file_exists(filename));
+    }
+}
+
+#[test]
+fn test_truncate() {
+    use nom_sql::Literal;
+    let mut db_state = setup_no_persist("test_truncate");
+    let desc = TupleDesc::new(
+        vec![DataType::U32, DataType::U32],
+        vec!["first", "second"],
+    );
+    let mut rel = Rel::new("test_truncate", desc, &mut db_state).unwrap();
+    let index_info =
+        rel.new_index(vec![0], IndexType::Hash, &mut db_state).unwrap();
+    let tuples = rel
+        .literal_to_data(vec![
+            vec![Literal::Integer(1), Literal::Integer(10)],
+            vec![Literal::Integer(2), Literal::Integer(11)],
+        ])
+        .unwrap();
+    rel.write_tuples(&mut tuples.clone().into_iter(), &mut db_state)
+        .unwrap();
+
+    rel.truncate(&mut db_state).unwrap();
+    let count_tuples = |db_state: &mut DbState| {
+        let mut count = 0;
+        rel.scan(db_state, |_| Ok(true), |_, _| {
+            count += 1;
+            Ok(())
+        }).unwrap();
+        count
+    };
+    let count_after_truncate = count_tuples(&mut db_state);
+
+    // The rel is still usable after being truncated
+    let ptrs = rel.write_tuples(&mut tuples.into_iter(), &mut db_state)
+        .unwrap();
+    let index = HashIndex::load(index_info.file_id, &mut db_state).unwrap();
+    let ptr = index
+        .get(&bincode::serialize(&1u32).unwrap(), &mut db_state)
+        .unwrap();
+    let count_after_write = count_tuples(&mut db_state);
+    teardown(db_state);
+
+    assert_eq!(count_after_truncate, 0);
+    assert_eq!(count_after_write, 2);
+    assert_eq!(ptr, vec![ptrs[0]]);
+}
+
+#[test]
+fn test_truncate_pinned() {
+    use nom_sql::Literal;
+    let mut db_state = setup_no_persist("test_truncate_pinned");
+    let desc = TupleDesc::new(vec![DataType::U32], vec!["first"]);
+    let rel = Rel::new("test_truncate_pinned", desc, &mut db_state).unwrap();
+    let tuples = rel
+        .literal_to_data(vec![vec![Literal::Integer(1)]])
+        .unwrap();
+    rel.write_tuples(&mut tuples.into_iter(), &mut db_state).unwrap();
+
+    let key = BufKey::new(rel.rel_id, 1, BufType::Data);
+    let page = db_state.buf_mgr.get_buf(&key).unwrap();
+    let pinned = rel.truncate(&mut db_state);
+    // The pinned page is still the one in the pool
+    let same_page = db_state.buf_mgr.get_buf(&key).unwrap();
+    page.write().unwrap().write_tuple_data(&[2, 0, 0, 0], None, None)
+        .unwrap();
+    let count = same_page.read().unwrap().iter().count();
+    drop((page, same_page));
+    let unpinned = rel.truncate(&mut db_state);
+    teardown(db_state);
+
+    assert!(pinned.is_err());
+    assert_eq!(count, 2);
+    assert!(unpinned.is_ok());
+}
+
+#[test]
+fn test_truncate_recovery() {
+    use log::{LogEntry, OpType};
+    use nom_sql::Literal;
+    let data_dir = "test_truncate_recovery";
+    let mut db_state = setup_no_persist(data_dir);
+    let desc = TupleDesc::new(
+        vec![DataType::U32, DataType::U32],
+        vec!["first", "second"],
+    );
+    let mut rel =
+        Rel::new("test_truncate_recovery", desc, &mut db_state).unwrap();
+    rel.new_index(vec![0], IndexType::Hash, &mut db_state).unwrap();
+    let tuples = rel
+        .literal_to_data(vec![vec![Literal::Integer(1), Literal::Integer(10)]])
+        .unwrap();
+    rel.write_tuples(&mut tuples.into_iter(), &mut db_state).unwrap();
+    db_state.buf_mgr.persist().unwrap();
+
+    // Crash after the truncate is logged but before it is done
+    let entry = LogEntry::new(
+        rel.meta_buf_key(), OpType::TruncateRel, vec![], &mut db_state)
+        .unwrap();
+    db_state
+        .log_mgr
+        .write_entries(vec![entry], &mut db_state.buf_mgr)
+        .unwrap();
+    drop(db_state);
+
+    let mut db_state = setup_no_persist(data_dir);
+    let mut count = 0;
+    rel.scan(&mut db_state, |_| Ok(true), |_, _| {
+        count += 1;
+        Ok(())
+    }).unwrap();
+    // Recovery rebuilds the index into new files
+    let rel = Rel::load(rel.rel_id, BufType::Data, &mut db_state).unwrap();
+    let file_id = rel.indices()[0].file_id;
+    let index = HashIndex::load(file_id, &mut db_state).unwrap();
+    let ptr = index
+        .get(&bincode::serialize(&1u32).unwrap(), &mut db_state)
+        .unwrap();
+    teardown(db_state);
+
+    assert_eq!(count, 0);
+    assert!(ptr.is_empty());
+}
+
+#[test]
+fn test_rewrite() {
+    use nom_sql::Literal;
+    let mut db_state = setup_no_persist("test_rewrite");
+    let desc = TupleDesc::new(
+        vec![DataType::U32, DataType::U32],
+        vec!["first", "second"],
+    );
+    let mut rel = Rel::new("test_rewrite", desc, &mut db_state).unwrap();
+    rel.new_index(vec![1], IndexType::Hash, &mut db_state).unwrap();
+    let tuples = rel
+        .literal_to_data(vec![
+            vec![Literal::Integer(1), Literal::Integer(10)],
+            vec![Literal::Integer(2), Literal::Integer(11)],
+        ])
+        .unwrap();
+    rel.write_tuples(&mut tuples.into_iter(), &mut db_state).unwrap();
+
+    // Add a column at the front
+    let new_desc = TupleDesc::new(
+        vec![DataType::Bool, DataType::U32, DataType::U32],
+        vec!["flag", "first", "second"],
+    );
+    let new_rel = rel.rewrite(
+        "test_rewrite",
+        new_desc.clone(),
+        vec![(vec![2], vec![], IndexType::Hash)],
+        |tup| Ok([&[1u8], tup].concat()),
+        &mut db_state,
+    ).unwrap();
+
+    let table_id = utils::get_table_id(
+        "test_rewrite".to_string(), &mut db_state).unwrap();
+    let loaded = Rel::load(table_id, BufType::Data, &mut db_state).unwrap();
+    let mut rows = vec![];
+    loaded.scan(&mut db_state, |_| Ok(true), |tup, _| {
+        rows.push(new_desc.data_to_strings(tup, None)?);
+        Ok(())
+    }).unwrap();
+    let index = HashIndex::load(
+        loaded.indices()[0].file_id, &mut db_state).unwrap();
+    let ptrs = index
+        .get(&bincode::serialize(&11u32).unwrap(), &mut db_state)
+        .unwrap();
+    let old_file_exists = utils::file_exists(
+        &db_state.buf_mgr.key_to_filename(rel.meta_buf_key()));
+    teardown(db_state);
+
+    assert_eq!(table_id, new_rel.rel_id);
+    assert_eq!(loaded.tuple_desc(), new_desc);
+    assert_eq!(rows, vec![
+        vec!["true", "1", "10"],
+        vec!["true", "2", "11"],
+    ]);
+    assert_eq!(ptrs.len(), 1);
+    assert!(!old_file_exists);
+}
+
+#[test]
+fn test_covering_index() {
+    use nom_sql::Literal;
+    let mut db_state = setup_no_persist("test_covering_index");
+    let desc = TupleDesc::new(
+        vec![DataType::U32, DataType::U32, DataType::U32],
+        vec!["id", "age", "score"],
+    );
+    let mut rel = Rel::new("test_covering_index", desc, &mut db_state).unwrap();
+    let tuple = |id, score| {
+        vec![Literal::Integer(id), Literal::Integer(0), Literal::Integer(score)]
+    };
+    let tuples = rel.literal_to_data(vec![tuple(1, 10)]).unwrap();
+    rel.write_tuples(&mut tuples.into_iter(), &mut db_state).unwrap();
+    // The tuple written before is indexed in bulk, the next one by insert
+    let info = rel
+        .new_covering_index(vec![0], vec![2], IndexType::Hash, &mut db_state)
+        .unwrap();
+    let tuples = rel.literal_to_data(vec![tuple(2, 20)]).unwrap();
+    rel.write_tuples(&mut tuples.into_iter(), &mut db_state).unwrap();
+
+    let index = HashIndex::load(info.file_id, &mut db_state).unwrap();
+    let scores = [1u32, 2]
+        .iter()
+        .map(|id| {
+            let key = bincode::serialize(id).unwrap();
+            index
+                .get_included(&key, &mut db_state)
+                .unwrap()
+                .into_iter()
+                .map(|(_, data)| bincode::deserialize::<u32>(&data).unwrap())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    teardown(db_state);
+
+    assert_eq!(info.include, vec![2]);
+    assert!(info.covers(&[0, 2]));
+    assert!(!info.covers(&[1]));
+    assert_eq!(scores, vec![vec![10], vec![20]]);
+}
+
+#[test]
+fn test_rename() {
+    let mut db_state = setup_no_persist("test_rename");
+    let desc = TupleDesc::new(vec![DataType::U32], vec!["first"]);
+    let rel = Rel::new("test_rename", desc.clone(), &mut db_state).unwrap();
+    let other = Rel::new("test_rename_other", desc, &mut db_state).unwrap();
+
+    rel.rename("test_rename", "test_rename_new", &mut db_state).unwrap();
+    let old_id = utils::find_table_id(
+        "test_rename".to_string(), &mut db_state).unwrap();
+    let new_id = utils::find_table_id(
+        "test_rename_new".to_string(), &mut db_state).unwrap();
+    let renamed = Rel::load(rel.rel_id, BufType::Data, &mut db_state).unwrap();
+    let taken = other.rename(
+        "test_rename_other", "test_rename_new", &mut db_state);
+    teardown(db_state);
+
+    assert_eq!(old_id, None);
+    assert_eq!(new_id, Some(rel.rel_id));
+    assert_eq!(renamed.name(), Some("test_rename_new".to_string()));
+    assert!(taken.is_err());
+}
+
+#[test]
+fn test_rename_attr() {
+    use nom_sql::Literal;
+    let data_dir = "test_rename_attr";
+    let mut db_state = setup_no_persist(data_dir);
+    let desc = TupleDesc::new(
+        vec![DataType::U32, DataType::U32],
+        vec!["first", "second"],
+    );
+    let mut rel = Rel::new(data_dir, desc, &mut db_state).unwrap();
+    rel.new_index(vec![1], IndexType::Hash, &mut db_state).unwrap();
+    let tuple = |first, second| {
+        rel.literal_to_data(vec![
+            vec![Literal::Integer(first), Literal::Integer(second)]])
+            .unwrap()
+    };
+    let tuples = tuple(1, 10);
+    rel.write_tuples(&mut tuples.into_iter(), &mut db_state).unwrap();
+    db_state.buf_mgr.persist().unwrap();
+
+    // Crash after the rename is logged, before the meta pages are written
+    rel.rename_attr(1, "renamed", &mut db_state).unwrap();
+    db_state.commit().unwrap();
+    drop(db_state);
+
+    let mut db_state = setup_no_persist(data_dir);
+    let table_id =
+        utils::get_table_id(data_dir.to_string(), &mut db_state).unwrap();
+    let loaded = Rel::load(table_id, BufType::Data, &mut db_state).unwrap();
+    let tuples = tuple(2, 10);
+    loaded.write_tuples(&mut tuples.into_iter(), &mut db_state).unwrap();
+    let file_id = loaded.indices()[0].file_id;
+    let index = HashIndex::load(file_id, &mut db_state).unwrap();
+    let ptrs = index
+        .get(&bincode::serialize(&10u32).unwrap(), &mut db_state)
+        .unwrap();
+    teardown(db_state);
+
+    assert_eq!(table_id, rel.rel_id);
+    assert_eq!(loaded.tuple_desc().attr_names(), vec!["first", "renamed"]);
+    assert_eq!(index.key_desc.attr_names(), vec!["renamed"]);
+    assert_eq!(ptrs.len(), 2);
+}
+
+#[test]
+fn test_temp_rel() {
+    use db_state::DbSettings;
+    use nom_sql::Literal;
+
+    // A temp file left by a crash is removed on start
+    let data_dir = "test_temp_rel";
+    let leftover =
+        BufKey::new(1, 0, BufType::Temp).to_filename(data_dir.to_string());
+    std::fs::create_dir_all(format!("{}/temp", data_dir)).unwrap();
+    utils::create_file(&leftover, 4096).unwrap();
+    let settings = DbSettings::default().data_dir(data_dir).temp_limit(3);
+    let mut db_state = DbState::start_db(settings).unwrap();
+
+    let desc = TupleDesc::new(vec![DataType::VarChar], vec!["text"]);
+    let rel = Rel::new_temp_rel(desc.clone(), &mut db_state).unwrap();
+    let tuples = rel
+        .literal_to_data(vec![vec![Literal::String("a".repeat(1000))]])
+        .unwrap();
+    rel.write_tuples(&mut tuples.clone().into_iter(), &mut db_state)
+        .unwrap();
+    let filename = db_state.buf_mgr.key_to_filename(rel.meta_buf_key());
+    let existed = utils::file_exists(&filename);
+    let pages = db_state.buf_mgr.temp_pages();
+
+    // Only one more page fits in the limit
+    let mut many = tuples.iter().cloned().cycle().take(8);
+    let exceeded = rel.write_tuples(&mut many, &mut db_state);
+
+    db_state.buf_mgr.drop_temp_files().unwrap();
+    let dropped = !utils::file_exists(&filename);
+    let cached = db_state.buf_mgr.has_buf(&rel.meta_buf_key());
+    let next = Rel::new_temp_rel(desc, &mut db_state).unwrap();
+    let pages_after = db_state.buf_mgr.temp_pages();
+    db_state.buf_mgr.drop_temp_files().unwrap();
+    teardown(db_state);
+
+    assert_eq!(rel.rel_id, 1);
+    assert!(existed);
+    assert_eq!(pages, 2);
+    assert!(exceeded.is_err());
+    assert!(dropped);
+    assert!(!cached);
+    assert_eq!(next.rel_id, 2);
+    assert_eq!(pages_after, 2);
+}
+
+#[test]
+fn test_temp_per_query() {
+    use db_state::DbSettings;
+    let data_dir = "test_temp_per_query";
+    let settings = DbSettings::default().data_dir(data_dir).temp_limit(2);
+    let db_state = DbState::start_db(settings).unwrap();
+    let desc = TupleDesc::new(vec![DataType::U32], vec!["a"]);
+
+    // Two statements running at once
+    let mut first = db_state.for_query();
+    let mut second = db_state.for_query();
+    let first_rel = Rel::new_temp_rel(desc.clone(), &mut first).unwrap();
+    let second_rel = Rel::new_temp_rel(desc, &mut second).unwrap();
+    let filename = |rel: &Rel, db_state: &DbState| {
+        db_state.buf_mgr.key_to_filename(rel.meta_buf_key())
+    };
+    let (first_file, second_file) =
+        (filename(&first_rel, &first), filename(&second_rel, &second));
+    // Each query is under the limit on its own
+    let second_pages = second.buf_mgr.temp_pages();
+    first.buf_mgr.drop_temp_files().unwrap();
+    let first_dropped = !utils::file_exists(&first_file);
+    let second_kept = utils::file_exists(&second_file);
+    second.buf_mgr.drop_temp_files().unwrap();
+    teardown(db_state);
+
+    assert_ne!(first_rel.rel_id, second_rel.rel_id);
+    assert_eq!(second_pages, 2);
+    assert!(first_dropped);
+    assert!(second_kept);
+}
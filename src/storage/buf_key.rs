@@ -1,8 +1,11 @@
 use internal_types::ID;
 use serde::{Deserialize, Serialize};
-use storage::{BufType, PAGE_SIZE};
+use storage::{BufType, LOG_SEGMENT_PAGES};
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+#[derive(
+    Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Serialize,
+    Deserialize,
+)]
 pub struct BufKey {
     pub file_id: ID,
     pub offset: u64,
@@ -23,11 +26,27 @@ impl BufKey {
             &BufType::Data => format!("{}/{}.dat", data_dir, self.file_id),
             &BufType::Temp => format!("{}/temp/{}.dat", data_dir, self.file_id),
             &BufType::Mem => format!("{}/mem.dat", data_dir),
+            &BufType::Log => BufKey::segment_filename(data_dir, self.segment()),
         }
     }
 
-    pub fn byte_offset(&self) -> u64 {
-        self.offset * (PAGE_SIZE as u64)
+    pub fn segment_filename(data_dir: String, segment: u64) -> String {
+        format!("{}/wal/{:08}.log", data_dir, segment)
+    }
+
+    pub fn byte_offset(&self, page_size: usize) -> u64 {
+        match self.buf_type {
+            // Log pages are numbered across all segments
+            BufType::Log => {
+                (self.offset % LOG_SEGMENT_PAGES) * (page_size as u64)
+            }
+            _ => self.offset * (page_size as u64),
+        }
+    }
+
+    /// The WAL segment of a log page
+    pub fn segment(&self) -> u64 {
+        self.offset / LOG_SEGMENT_PAGES
     }
 
     pub fn inc_offset(mut self) -> BufKey {
@@ -0,0 +1,177 @@
+use error::Result;
+use internal_types::ID;
+use libc;
+use std::alloc::{self, Layout};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
+use std::sync::{Arc, Mutex};
+use storage::{BufKey, BufType, MIN_PAGE_SIZE};
+
+/// Files are cached by file id and type, log files by their segment
+pub type FileKey = (ID, BufType, u64);
+
+/// Open files of BufMgr, pages are read and written at their offset
+/// without seeking so a file can be shared between threads
+#[derive(Clone, Debug)]
+pub struct FileCache {
+    files: Arc<Mutex<HashMap<FileKey, Arc<File>>>>,
+    data_dir: Arc<String>,
+    capacity: usize,
+    /// Open files with O_DIRECT, skipping the OS page cache
+    direct: bool,
+    page_size: usize,
+}
+
+impl FileCache {
+    pub fn new(
+        data_dir: Arc<String>,
+        capacity: usize,
+        direct: bool,
+        page_size: usize,
+    ) -> Self {
+        FileCache {
+            files: Arc::new(Mutex::new(HashMap::new())),
+            data_dir,
+            capacity,
+            direct,
+            page_size,
+        }
+    }
+
+    pub fn read_at(&self, key: &BufKey, buf: &mut AlignedBuf) -> Result<()> {
+        let offset = key.byte_offset(self.page_size);
+        Ok(self.get(key)?.read_exact_at(buf, offset)?)
+    }
+
+    pub fn write_at(&self, key: &BufKey, buf: &AlignedBuf) -> Result<()> {
+        let offset = key.byte_offset(self.page_size);
+        Ok(self.get(key)?.write_all_at(buf, offset)?)
+    }
+
+    /// Write the page of a single page file to a temp file that is then
+    /// renamed over it, so a crash leaves the old or the new page whole
+    pub fn replace(
+        &self,
+        key: &BufKey,
+        buf: &AlignedBuf,
+        sync: bool,
+    ) -> Result<()> {
+        let filename = key.to_filename(self.data_dir.to_string());
+        let temp_name = format!("{}.tmp", filename);
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp_name)?;
+        file.write_all_at(buf, 0)?;
+        if sync {
+            file.sync_all()?;
+        }
+        // No handle to the old file is opened while it is replaced
+        let mut files = self.files.lock().unwrap();
+        fs::rename(&temp_name, &filename)?;
+        files.remove(&file_key(key));
+        drop(files);
+        if sync {
+            File::open(self.data_dir.as_str())?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: &BufKey) -> Result<Arc<File>> {
+        let file_key = file_key(key);
+        let mut files = self.files.lock().unwrap();
+        if let Some(file) = files.get(&file_key) {
+            return Ok(file.clone());
+        }
+        if files.len() >= self.capacity {
+            // Threads using the closed file keep their handle
+            let old = *files.keys().next().unwrap();
+            files.remove(&old);
+        }
+        let file = Arc::new(self.open(key)?);
+        files.insert(file_key, file.clone());
+        Ok(file)
+    }
+
+    /// Close the file of key, it must be called before the file is
+    /// removed so later pages are not written to the removed file
+    pub fn close(&self, key: &BufKey) {
+        self.files.lock().unwrap().remove(&file_key(key));
+    }
+
+    fn open(&self, key: &BufKey) -> Result<File> {
+        let filename = key.to_filename(self.data_dir.to_string());
+        let mut options = OpenOptions::new();
+        options.read(true).write(true);
+        if !self.direct {
+            return Ok(options.open(filename)?);
+        }
+        match options.custom_flags(libc::O_DIRECT).open(&filename) {
+            Ok(file) => Ok(file),
+            // Some file systems, e.g. tmpfs, do not support O_DIRECT
+            Err(ref e) if e.raw_os_error() == Some(libc::EINVAL) => {
+                Ok(options.custom_flags(0).open(filename)?)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+fn file_key(key: &BufKey) -> FileKey {
+    match key.buf_type {
+        BufType::Log => (key.file_id, key.buf_type, key.segment()),
+        _ => (key.file_id, key.buf_type, 0),
+    }
+}
+
+/// Buffer aligned to MIN_PAGE_SIZE, as required by O_DIRECT
+pub struct AlignedBuf {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+// AlignedBuf owns its memory like a Vec
+unsafe impl Send for AlignedBuf {}
+
+impl AlignedBuf {
+    /// len is rounded up to a multiple of MIN_PAGE_SIZE
+    pub fn new(len: usize) -> AlignedBuf {
+        let len = len.div_ceil(MIN_PAGE_SIZE).max(1) * MIN_PAGE_SIZE;
+        let layout = Layout::from_size_align(len, MIN_PAGE_SIZE).unwrap();
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        if ptr.is_null() {
+            alloc::handle_alloc_error(layout);
+        }
+        AlignedBuf { ptr, layout }
+    }
+
+    pub fn from_slice(data: &[u8]) -> AlignedBuf {
+        let mut buf = AlignedBuf::new(data.len());
+        buf[..data.len()].copy_from_slice(data);
+        buf
+    }
+}
+
+impl std::ops::Deref for AlignedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.layout.size()) }
+    }
+}
+
+impl std::ops::DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            std::slice::from_raw_parts_mut(self.ptr, self.layout.size())
+        }
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr, self.layout) }
+    }
+}
@@ -1,23 +1,38 @@
-use db_state::DbSettings;
+use db_state::{DbSettings, SyncMode};
 use error::{Error, Result};
 use evmap;
 use internal_types::ID;
-use log::LogMgr;
-use meta::Meta;
+use log::{LogMgr, LOG_META_KEY};
+use meta::{Meta, META_BUF_KEY};
 use std::collections::VecDeque;
 use std::fs;
 use std::io;
-use std::io::{Read, Seek, Write};
 use std::sync::{Arc, Mutex, RwLock};
-use storage;
 use storage::BufType;
 use storage::buf_key::BufKey;
 use storage::buf_page::BufPage;
 use utils;
 
+use self::file_cache::AlignedBuf;
+
+pub mod file_cache;
+pub mod read_ahead;
+pub mod replacement;
+pub mod stats;
+pub mod temp_space;
+
+pub use self::file_cache::FileCache;
+pub use self::read_ahead::{Prefetcher, ReadAhead};
+pub use self::replacement::ReplacementPolicy;
+pub use self::stats::{BufStats, Counters, StatsSnapshot};
+pub use self::temp_space::TempSpace;
+
 #[cfg(test)]
 mod tests;
 
+/// Files BufMgr keeps open, see FileCache
+const MAX_OPEN_FILES: usize = 256;
+
 #[macro_use]
 macro_rules! insert {
     ($evmap_lock:expr, $key:expr, $val:expr) => {
@@ -50,8 +65,8 @@ impl PageLock {
         PageLock {
             page: Arc::new(RwLock::new(page)),
             info: Arc::new(RwLock::new(BufInfo {
-                ref_bit: false,
                 dirty: false,
+                pins: 0,
             })),
         }
     }
@@ -74,8 +89,9 @@ impl PageLock {
         self.page.try_write()
     }
 
-    pub fn ref_count(&self) -> usize {
-        Arc::strong_count(&self.page)
+    /// Number of PinnedPages of this page
+    pub fn pin_count(&self) -> usize {
+        self.info.read().unwrap().pins
     }
 
     fn set_dirty(&self) {
@@ -101,35 +117,113 @@ impl Eq for PageLock {}
 
 #[derive(Debug)]
 pub struct BufInfo {
-    ref_bit: bool,
     dirty: bool,
+    /// A page is only evicted when it has no pins, see PinnedPage
+    pins: usize,
+}
+
+/// A page that is not evicted while this or a clone of it is alive,
+/// returned by get_buf and new_buf
+#[derive(Debug)]
+pub struct PinnedPage {
+    lock: PageLock,
+}
+
+impl PinnedPage {
+    /// BufMgr must make sure the page is not being evicted, e.g. by
+    /// checking it is still in the pool once pinned
+    fn new(lock: PageLock) -> PinnedPage {
+        lock.info.write().unwrap().pins += 1;
+        PinnedPage { lock }
+    }
+}
+
+impl Clone for PinnedPage {
+    fn clone(&self) -> PinnedPage {
+        PinnedPage::new(self.lock.clone())
+    }
+}
+
+impl Drop for PinnedPage {
+    fn drop(&mut self) {
+        // Unpin even if another thread panicked with the lock
+        let mut info =
+            self.lock.info.write().unwrap_or_else(|e| e.into_inner());
+        info.pins -= 1;
+    }
+}
+
+impl std::ops::Deref for PinnedPage {
+    type Target = PageLock;
+
+    fn deref(&self) -> &PageLock {
+        &self.lock
+    }
 }
 
 #[derive(Clone)]
 pub struct BufMgr {
     buf_table_r: evmap::ReadHandle<BufKey, PageLock>,
     buf_table_w: Arc<Mutex<evmap::WriteHandle<BufKey, PageLock>>>,
-    evict_queue: Arc<Mutex<VecDeque<BufKey>>>,
+    policy: Arc<Mutex<Box<dyn ReplacementPolicy + Send>>>,
     max_size: Arc<usize>,
+    /// Pages read_ahead loads before they are used
+    read_ahead: usize,
+    /// None for the reader thread's own handle, see Prefetcher::start
+    prefetcher: Option<Prefetcher>,
+    page_size: usize,
     data_dir: Arc<String>,
-    temp_counter: Arc<Mutex<ID>>,
+    files: FileCache,
+    temp: TempSpace,
     mem_counter: Arc<Mutex<ID>>,
+    sync_mode: SyncMode,
+    archive_dir: Arc<Option<String>>,
+    /// Set once the log is loaded, see set_log_mgr
+    log_mgr: Arc<RwLock<Option<LogMgr>>>,
+    stats: BufStats,
 }
 
 impl BufMgr {
     pub fn new(settings: DbSettings) -> BufMgr {
         let buf_table = evmap::new::<BufKey, PageLock>();
-
-        BufMgr {
+        let sync_mode = settings.get_sync_mode();
+        // Default size a bit less than 4GB
+        let max_size = settings.buf_mgr_size.unwrap_or(80000);
+        let policy =
+            replacement::new_policy(settings.get_replacement(), max_size);
+        let data_dir = Arc::new(settings.get_data_dir());
+        // An existing database keeps the page size it was created with,
+        // a corrupted meta file is reported by DbState::check_page_size
+        let page_size = Meta::page_size_on_disk(&data_dir)
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| settings.get_page_size());
+
+        let mut buf_mgr = BufMgr {
             buf_table_r: buf_table.0,
             buf_table_w: Arc::new(Mutex::new(buf_table.1)),
-            evict_queue: Arc::new(Mutex::new(VecDeque::new())),
-            // Default size a bit less than 4GB
-            max_size: Arc::new(settings.buf_mgr_size.unwrap_or(80000)),
-            data_dir: Arc::new(settings.data_dir.unwrap_or("data".to_string())),
-            temp_counter: Arc::new(Mutex::new(0)),
+            policy: Arc::new(Mutex::new(policy)),
+            max_size: Arc::new(max_size),
+            // Keep most of the pool for pages that are not prefetched
+            read_ahead: settings.get_read_ahead().min(max_size / 4).max(1),
+            prefetcher: None,
+            data_dir: data_dir.clone(),
+            files: FileCache::new(
+                data_dir,
+                MAX_OPEN_FILES,
+                settings.get_direct_io(),
+                page_size,
+            ),
+            page_size,
+            temp: TempSpace::new(settings.temp_limit),
             mem_counter: Arc::new(Mutex::new(0)),
-        }
+            sync_mode,
+            archive_dir: Arc::new(settings.archive_dir.clone()),
+            log_mgr: Arc::new(RwLock::new(None)),
+            stats: BufStats::default(),
+        };
+        buf_mgr.prefetcher = Some(Prefetcher::start(&buf_mgr));
+        buf_mgr
     }
 
     pub fn start_persist(&self, meta: &Meta, log_mgr: &LogMgr) -> Result<()> {
@@ -142,11 +236,17 @@ impl BufMgr {
         Ok(())
     }
 
+    /// Data pages are only written once the log is flushed up to their
+    /// LSN, before this is set pages are written without checking
+    pub fn set_log_mgr(&self, log_mgr: &LogMgr) {
+        *self.log_mgr.write().unwrap() = Some(log_mgr.clone());
+    }
+
     pub fn has_buf(&self, key: &BufKey) -> bool {
         self.buf_table_r.contains_key(key)
     }
 
-    pub fn get_buf(&mut self, key: &BufKey) -> Result<PageLock> {
+    pub fn get_buf(&mut self, key: &BufKey) -> Result<PinnedPage> {
         match &key.buf_type {
             // TODO might want to make Mem buffers retrievable
             // They are not right now because if the buffer is evicted,
@@ -156,14 +256,21 @@ impl BufMgr {
                 "In-memory buffers are not retrievable",
             ))),
             _ => {
-                let buf = match self.get_item(key) {
-                    Some(buf) => buf,
-                    None => self.add_buf(self.read_buf(key)?, key)?,
+                let buf = loop {
+                    let pinned = match self.get_item(key) {
+                        Some(buf) => PinnedPage::new(buf),
+                        None => {
+                            self.stats.update(key, |c| c.misses += 1);
+                            break self.add_buf(self.read_buf(key)?, key)?;
+                        }
+                    };
+                    // The page could be evicted before it was pinned
+                    if self.get_item(key).as_ref() == Some(&pinned.lock) {
+                        self.stats.update(key, |c| c.hits += 1);
+                        break pinned;
+                    }
                 };
-
-                let info = self.get_info_arc(key).unwrap();
-                let mut write = info.write().unwrap();
-                write.ref_bit = true;
+                self.policy.lock().unwrap().access(key);
                 Ok(buf)
             }
         }
@@ -172,14 +279,18 @@ impl BufMgr {
     pub fn store_buf(
         &self,
         key: &BufKey,
-        info_lock: Option<std::sync::RwLockWriteGuard<BufInfo>>,
+        info_lock: Option<&mut BufInfo>,
     ) -> Result<()> {
         match self.get_item(key) {
             Some(item) => {
                 let page_lock = item.read().unwrap();
-                let mut info_lock = match info_lock {
+                let mut guard;
+                let info_lock = match info_lock {
                     Some(lock) => lock,
-                    None => item.info.write().unwrap(),
+                    None => {
+                        guard = item.info.write().unwrap();
+                        &mut *guard
+                    }
                 };
 
                 if !info_lock.dirty {
@@ -194,11 +305,24 @@ impl BufMgr {
                     _ => {}
                 };
 
-                let mut file = fs::OpenOptions::new()
-                    .write(true)
-                    .open(key.to_filename(self.data_dir()))?;
-                file.seek(io::SeekFrom::Start(key.byte_offset()))?;
-                file.write_all(page_lock.buf().as_slice())?;
+                // The log entries that changed the page must be durable
+                // before the page is written
+                if page_lock.buf_key.buf_type == BufType::Data {
+                    if let Some(log_mgr) = &*self.log_mgr.read().unwrap() {
+                        log_mgr.flush_to(page_lock.lsn, self)?;
+                    }
+                }
+
+                let mut buf = AlignedBuf::from_slice(page_lock.buf());
+                BufPage::set_checksum(&mut buf);
+                // Neither page is logged, see LogMgr::write_page_entries
+                if key == &META_BUF_KEY || key == &LOG_META_KEY {
+                    let sync = self.sync_mode != SyncMode::Off;
+                    self.files.replace(key, &buf, sync)?;
+                } else {
+                    self.files.write_at(key, &buf)?;
+                }
+                self.stats.update(key, |c| c.writes += 1);
 
                 info_lock.dirty = false;
                 Ok(())
@@ -207,14 +331,27 @@ impl BufMgr {
         }
     }
 
-    pub fn new_buf(&mut self, key: &BufKey) -> Result<PageLock> {
+    /// The page as it is on disk, even if it fails its checksum
+    pub fn read_raw(&self, key: &BufKey) -> Result<Vec<u8>> {
+        self.read_buf(key)
+    }
+
+    /// Add an empty page in place of a page that cannot be loaded,
+    /// e.g. a torn page that recovery restores from a logged image
+    pub fn reset_buf(&mut self, key: &BufKey) -> Result<PinnedPage> {
+        self.add_buf(BufPage::default_buf(self.page_size), key)
+    }
+
+    pub fn new_buf(&mut self, key: &BufKey) -> Result<PinnedPage> {
         match &key.buf_type {
             // Add a non-persistent buf to BufMgr if type is Mem
-            &BufType::Mem => self.add_buf(BufPage::default_buf(), key),
+            &BufType::Mem => {
+                self.add_buf(BufPage::default_buf(self.page_size), key)
+            }
             // Otherwise, create buf on disk
             _ => {
                 // Create new file
-                if key.byte_offset() == 0 {
+                if key.byte_offset(self.page_size) == 0 {
                     // Check if the file already exists
                     let fname = key.to_filename(self.data_dir());
                     if utils::file_exists(&fname) {
@@ -223,7 +360,10 @@ impl BufMgr {
                             "File already exists",
                         )))
                     } else {
-                        utils::create_file(&fname)?;
+                        self.count_temp_page(key)?;
+                        // A file with the same name could have been removed
+                        self.files.close(key);
+                        utils::create_file(&fname, self.page_size)?;
                         self.get_buf(key)
                     }
                 }
@@ -231,13 +371,13 @@ impl BufMgr {
                 else {
                     // If the offset is at the end of file, create new buf
                     if utils::file_len(&key.to_filename(self.data_dir()))?
-                        == key.byte_offset()
+                        == key.byte_offset(self.page_size)
                     {
-                        let mut file = fs::OpenOptions::new()
-                            .write(true)
-                            .open(key.to_filename(self.data_dir()))?;
-                        file.seek(io::SeekFrom::Start(key.byte_offset()))?;
-                        file.write_all(&BufPage::default_buf())?;
+                        self.count_temp_page(key)?;
+                        let buf = AlignedBuf::from_slice(
+                            &BufPage::default_buf(self.page_size),
+                        );
+                        self.files.write_at(key, &buf)?;
                     }
 
                     self.get_buf(key)
@@ -246,16 +386,40 @@ impl BufMgr {
         }
     }
 
-    pub fn new_mem_buf(&mut self) -> Result<PageLock> {
+    pub fn new_mem_buf(&mut self) -> Result<PinnedPage> {
         let id = self.new_mem_id();
         self.new_buf(&BufKey::new(id, 0, BufType::Mem))
     }
 
-    // TODO refactor, combine with new_mem_id
+    /// Give this handle its own temp files and page count for a query,
+    /// other handles keep theirs
+    pub fn start_query(&mut self) {
+        self.temp = self.temp.for_query();
+    }
+
+    /// Id of a temp file that is dropped when the query ends
     pub fn new_temp_id(&mut self) -> ID {
-        let mut temp_cnt = self.temp_counter.lock().unwrap();
-        *temp_cnt += 1;
-        *temp_cnt
+        self.temp.new_id()
+    }
+
+    /// Drop the temp files of the query that ended
+    pub fn drop_temp_files(&mut self) -> Result<()> {
+        for file_id in self.temp.take_files() {
+            self.drop_file(file_id, BufType::Temp)?;
+        }
+        Ok(())
+    }
+
+    /// Temp pages added by the running query
+    pub fn temp_pages(&self) -> u64 {
+        self.temp.pages()
+    }
+
+    fn count_temp_page(&self, key: &BufKey) -> Result<()> {
+        match key.buf_type {
+            BufType::Temp => self.temp.add_page(),
+            _ => Ok(()),
+        }
     }
 
     pub fn new_mem_id(&mut self) -> ID {
@@ -267,7 +431,7 @@ impl BufMgr {
     pub fn allocate_mem_bufs(
         &mut self,
         num: Option<usize>,
-    ) -> Result<Vec<PageLock>> {
+    ) -> Result<Vec<PinnedPage>> {
         let num = match num {
             Some(num) => if num > self.num_available_bufs() {
                 return Err(Error::Internal(
@@ -295,118 +459,283 @@ impl BufMgr {
         end: BufKey,
         mut func: F,
     ) -> Result<()>
-    where F: FnMut(PageLock, &mut BufMgr) -> Result<()>
+    where F: FnMut(PinnedPage, &mut BufMgr) -> Result<()>
     {
-        use std::thread;
-        use std::sync::mpsc;
         assert_eq!(start.file_id, end.file_id);
         assert!(start.offset <= end.offset);
         if let BufType::Mem = start.buf_type {
             todo!("Sequential scan is not supported for mem bufs");
         }
 
-        let (sender, receiver) = mpsc::channel::<PageLock>();
-        let mut buf_mgr = self.clone();
-        thread::spawn(move || buf_mgr.sequential_get_buf(start, end, sender));
-        for buf in receiver {
-            func(buf, self)?;
+        // Pages are pinned until func drops them, at most read_ahead
+        // pages are loaded ahead of func
+        let keys = (start.offset..end.offset + 1)
+            .map(|offset| BufKey::new(start.file_id, offset, start.buf_type))
+            .collect();
+        for page in self.read_ahead(keys) {
+            func(page?, self)?;
         }
         Ok(())
     }
 
-    fn sequential_get_buf(
-        &mut self,
-        start: BufKey,
-        end: BufKey,
-        sender: std::sync::mpsc::Sender<PageLock>,
-    ) -> Result<()> {
-        let mut buf = [0u8; storage::PAGE_SIZE];
-        let mut file = fs::File::open(start.to_filename(self.data_dir()))?;
-        file.seek(io::SeekFrom::Start(start.byte_offset()))?;
-
-        let mut cur = start;
-        while cur.offset <= end.offset {
-            let page = match self.get_item(&cur) {
-                Some(page) => {
-                    file.seek(
-                        io::SeekFrom::Current(storage::PAGE_SIZE as i64))?;
-                    page
-                }
-                None => {
-                    file.read_exact(&mut buf)?;
-                    self.add_buf(buf.to_vec(), &cur)?
+    /// Load pages in the order of keys. Runs of contiguous pages that
+    /// are not cached are read with one call, and up to read_ahead pages
+    /// after the ones returned are loaded by the reader thread meanwhile.
+    pub fn read_ahead(&self, keys: Vec<BufKey>) -> ReadAhead {
+        ReadAhead {
+            buf_mgr: self.clone(),
+            keys,
+            next_idx: 0,
+            prefetched_idx: 0,
+            pages: VecDeque::new(),
+            failed: false,
+            in_flight: Default::default(),
+        }
+    }
+
+    /// Read the pages of keys that are not cached into the pool without
+    /// pinning them, contiguous ones with one call
+    fn load_uncached(&mut self, keys: &[BufKey]) -> Result<()> {
+        let mut idx = 0;
+        while idx < keys.len() {
+            match self.uncached_run(&keys[idx..]) {
+                0 => idx += 1,
+                run => {
+                    self.read_run(&keys[idx..idx + run])?;
+                    idx += run;
                 }
-            };
-            let info = self.get_info_arc(&cur).unwrap();
-            let mut info_guard = info.write().unwrap();
-            info_guard.ref_bit = true;
-            sender.send(page).unwrap();
-            cur = cur.inc_offset();
+            }
         }
         Ok(())
     }
 
+    /// Number of contiguous pages at the start of keys that are not
+    /// cached, up to read_ahead
+    fn uncached_run(&self, keys: &[BufKey]) -> usize {
+        if let BufType::Mem = keys[0].buf_type {
+            return 0;
+        }
+        let max_run = keys.len().min(self.read_ahead);
+        let mut run = 0;
+        while run < max_run && !self.has_buf(&keys[run]) {
+            let contiguous = run == 0
+                || read_ahead::is_next_page(
+                    &keys[run - 1],
+                    &keys[run],
+                    self.page_size,
+                );
+            if !contiguous {
+                break;
+            }
+            run += 1;
+        }
+        run
+    }
+
+    fn read_run(&mut self, keys: &[BufKey]) -> Result<Vec<PinnedPage>> {
+        let mut buf = AlignedBuf::new(keys.len() * self.page_size);
+        self.files.read_at(&keys[0], &mut buf)?;
+
+        let mut pages = vec![];
+        for (key, data) in keys.iter().zip(buf.chunks(self.page_size)) {
+            self.stats.update(key, |c| {
+                c.misses += 1;
+                c.reads += 1;
+            });
+            pages.push(self.add_buf(data.to_vec(), key)?);
+            self.policy.lock().unwrap().access(key);
+        }
+        Ok(pages)
+    }
+
     fn num_available_bufs(&self) -> usize {
         *self.max_size - self.buf_table_r.len()
     }
 
     fn read_buf(&self, key: &BufKey) -> Result<Vec<u8>> {
-        let mut file = fs::File::open(key.to_filename(self.data_dir()))?;
-        file.seek(io::SeekFrom::Start(key.byte_offset()))?;
-
-        let mut buf = [0u8; storage::PAGE_SIZE];
-        file.read_exact(&mut buf)?;
+        let mut buf = AlignedBuf::new(self.page_size);
+        self.files.read_at(key, &mut buf)?;
+        self.stats.update(key, |c| c.reads += 1);
         Ok(buf.to_vec())
     }
 
-    fn add_buf(&mut self, buf: Vec<u8>, key: &BufKey) -> Result<PageLock> {
-        let mut buf_w = self.buf_table_w.lock().unwrap();
-        let mut evict_q = self.evict_queue.lock().unwrap();
+    /// Pages are pinned before the locks are released,
+    /// so they cannot be evicted before they are returned. A dirty
+    /// victim is written after the locks are released, so other threads
+    /// do not wait for the log flush, and evicted on the next round.
+    fn add_buf(&mut self, buf: Vec<u8>, key: &BufKey) -> Result<PinnedPage> {
+        let mut written: Option<BufKey> = None;
+        loop {
+            let (victim, _pinned) = {
+                let mut buf_w = self.buf_table_w.lock().unwrap();
+                let mut policy = self.policy.lock().unwrap();
 
-        // Could have been loaded after acquiring the locks
-        match self.get_item(key) {
-            Some(buf) => {
-                return Ok(buf);
-            }
-            None => {}
-        };
+                // Could have been loaded after acquiring the locks
+                if let Some(buf) = self.get_item(key) {
+                    return Ok(PinnedPage::new(buf));
+                }
 
-        // Evict
-        if self.buf_table_r.len() >= *(self.max_size) {
-            loop {
-                let key = evict_q.pop_front().unwrap();
-                // Holding a lock of buf's info will make sure
-                // another thread doesn't get this buf while it is
-                // being evicted
-                let info = self.get_info_arc(&key).unwrap();
-                match info.try_write() {
-                    Ok(mut guard) => {
-                        // Evict the page IF:
-                        //      its ref_bit is false
-                        //      its ref_count is 0
-                        if !guard.ref_bit && self.ref_count(&key) == 0 {
-                            self.store_buf(&key, Some(guard))?;
-                            remove!(buf_w, key.clone());
-                            break;
-                        } else {
-                            guard.ref_bit = false;
-                        }
+                if self.buf_table_r.len() < *(self.max_size) {
+                    insert!(
+                        buf_w,
+                        key.clone(),
+                        PageLock::new(BufPage::load_from(&buf, key)?)
+                    );
+                    policy.insert(*key);
+                    return Ok(PinnedPage::new(self.get_item(key).unwrap()));
+                }
+
+                // Evicted unless it was pinned or changed since it was
+                // written
+                if let Some(victim) = written.take() {
+                    let clean = self.get_info_arc(&victim).is_some_and(
+                        |info| {
+                            let guard = info.write().unwrap();
+                            guard.pins == 0 && !guard.dirty
+                        });
+                    if clean {
+                        policy.remove(&victim);
+                        remove!(buf_w, victim);
+                        self.stats.update(&victim, |c| c.evictions += 1);
+                        continue;
+                    }
+                }
+
+                let mut can_evict = |key: &BufKey| {
+                    let info = self.get_info_arc(key).unwrap();
+                    let unpinned =
+                        info.try_read().is_ok_and(|info| info.pins == 0);
+                    unpinned
+                };
+                let victim = match policy.evict(&mut can_evict) {
+                    Some(victim) => victim,
+                    None => {
+                        return Err(Error::internal(
+                            "Cannot evict a buffer, every page is pinned",
+                        ));
                     }
-                    Err(_) => {}
                 };
+                // Holding a lock of buf's info will make sure
+                // another thread doesn't pin this buf while it is
+                // being evicted
+                let info = self.get_info_arc(&victim).unwrap();
+                let guard = info.write().unwrap();
+                // Pinned after can_evict, another page is picked
+                if guard.pins > 0 {
+                    policy.insert(victim);
+                    continue;
+                }
+                if !guard.dirty {
+                    remove!(buf_w, victim);
+                    self.stats.update(&victim, |c| c.evictions += 1);
+                    continue;
+                }
+                // The pin keeps the victim cached while it is written
+                drop(guard);
+                policy.insert(victim);
+                (victim, PinnedPage::new(self.get_item(&victim).unwrap()))
+            };
+            self.store_buf(&victim, None)?;
+            written = Some(victim);
+        }
+    }
 
-                evict_q.push_back(key);
-            }
+    /// Drop cached pages matching pred without writing them to disk,
+    /// used when the pages on disk are about to be removed. Nothing is
+    /// dropped if one of the pages is pinned, its holder would keep
+    /// using a page that is no longer in the pool.
+    pub fn discard_bufs<F>(&mut self, pred: F) -> Result<()>
+    where
+        F: Fn(&BufKey) -> bool,
+    {
+        let mut buf_w = self.buf_table_w.lock().unwrap();
+        let mut policy = self.policy.lock().unwrap();
+
+        let keys: Vec<BufKey> =
+            policy.keys().into_iter().filter(|key| pred(key)).collect();
+        let infos = keys
+            .iter()
+            .filter_map(|key| self.get_info_arc(key))
+            .collect::<Vec<_>>();
+        // Holding the infos keeps the pages from being pinned meanwhile
+        let guards = infos
+            .iter()
+            .map(|info| info.write().unwrap())
+            .collect::<Vec<_>>();
+        if guards.iter().any(|guard| guard.pins > 0) {
+            return Err(Error::internal("Cannot discard a pinned page"));
+        }
+        for key in keys.into_iter() {
+            policy.remove(&key);
+            remove!(buf_w, key);
+        }
+        Ok(())
+    }
+
+    /// Fail if a cached page matching pred is pinned, checked before
+    /// logging a change that discards the pages
+    pub fn check_unpinned<F>(&self, pred: F) -> Result<()>
+    where
+        F: Fn(&BufKey) -> bool,
+    {
+        if self.pinned_keys().iter().any(pred) {
+            return Err(Error::internal("Cannot discard a pinned page"));
+        }
+        Ok(())
+    }
+
+    /// Write the cached pages of a file to disk
+    pub fn store_file(&self, file_id: ID, buf_type: BufType) -> Result<()> {
+        let keys: Vec<BufKey> = self
+            .policy
+            .lock()
+            .unwrap()
+            .keys()
+            .into_iter()
+            .filter(|key| key.file_id == file_id && key.buf_type == buf_type)
+            .collect();
+        for key in keys.iter() {
+            self.store_buf(key, None)?;
         }
+        Ok(())
+    }
 
-        insert!(
-            buf_w,
-            key.clone(),
-            PageLock::new(BufPage::load_from(&buf, key)?)
-        );
-        evict_q.push_back(key.clone());
+    /// Delete a file and drop its cached pages
+    pub fn drop_file(&mut self, file_id: ID, buf_type: BufType) -> Result<()> {
+        self.discard_bufs(|key| {
+            key.file_id == file_id && key.buf_type == buf_type
+        })?;
+        let key = BufKey::new(file_id, 0, buf_type);
+        self.files.close(&key);
+        match fs::remove_file(key.to_filename(self.data_dir())) {
+            Ok(()) => Ok(()),
+            Err(e) => match e.kind() {
+                io::ErrorKind::NotFound => Ok(()),
+                _ => Err(Error::from(e)),
+            },
+        }
+    }
+
+    /// Shrink a file to num_pages, dropping the cached pages past its end
+    pub fn truncate_file(
+        &mut self,
+        file_id: ID,
+        buf_type: BufType,
+        num_pages: u64,
+    ) -> Result<()> {
+        self.discard_bufs(|key| {
+            key.file_id == file_id
+                && key.buf_type == buf_type
+                && key.offset >= num_pages
+        })?;
+        let key = BufKey::new(file_id, num_pages, buf_type);
+        self.files.get(&key)?.set_len(key.byte_offset(self.page_size))?;
+        Ok(())
+    }
 
-        Ok(self.get_item(key).unwrap())
+    /// Close the file of key before it is removed outside of BufMgr
+    pub fn close_file(&self, key: &BufKey) {
+        self.files.close(key);
     }
 
     pub fn key_to_filename(&self, key: BufKey) -> String {
@@ -421,11 +750,9 @@ impl BufMgr {
         self.buf_table_r.get_and(key, |items| items[0].info.clone())
     }
 
-    // Return number of threads that are using a page
-    fn ref_count(&self, key: &BufKey) -> usize {
-        // -3 because evmap has 2 refs
-        // and calling get_item will create a ref
-        self.get_item(key).unwrap().ref_count() - 3
+    /// Size of every page, fixed when the database is created
+    pub fn page_size(&self) -> usize {
+        self.page_size
     }
 
     pub fn data_dir(&self) -> String {
@@ -437,16 +764,18 @@ impl BufMgr {
         loop {
             thread::sleep(time::Duration::from_millis(200));
 
-            match meta.persist_counters() {
-                Ok(()) => {},
-                Err(e) => panic!("Persisting counters failed\nError: {:?}", e),
-            };
-
             let cp_ptr = match log_mgr.create_checkpoint(&mut self) {
                 Ok(ptr) => ptr,
                 Err(e) => panic!("Creating checkpoint failed\nError: {:?}", e),
             };
 
+            // Counters are persisted after the checkpoint is created, so
+            // they are past every LSN logged before the checkpoint
+            match meta.persist_counters() {
+                Ok(()) => {},
+                Err(e) => panic!("Persisting counters failed\nError: {:?}", e),
+            };
+
             if let Err(e) = self.persist() {
                 panic!("Persist failed\nError: {:?}", e);
             }
@@ -458,13 +787,99 @@ impl BufMgr {
     }
 
     pub fn persist(&mut self) -> Result<()> {
-        let keys = self.evict_queue.lock().unwrap().clone();
+        use std::collections::HashMap;
+
+        let keys = self.policy.lock().unwrap().keys();
         for it in keys.iter() {
-            self.store_buf(&*it, None)?;
+            // Pages can be evicted or discarded after keys are cloned
+            if let Err(e) = self.store_buf(it, None) {
+                if self.has_buf(it) {
+                    return Err(e);
+                }
+            }
+        }
+
+        if self.sync_mode != SyncMode::Off {
+            // One key per file
+            let files: HashMap<String, &BufKey> = keys
+                .iter()
+                .filter(|key| key.buf_type != BufType::Mem)
+                .map(|key| (key.to_filename(self.data_dir()), key))
+                .collect();
+            for key in files.values() {
+                self.sync_file(key)?;
+            }
         }
 
         Ok(())
     }
+
+    /// Counters since the last reset_stats, with the number of cached,
+    /// dirty and pinned pages
+    pub fn stats(&self) -> StatsSnapshot {
+        let keys = self.policy.lock().unwrap().keys();
+        let (mut dirty, mut pinned) = (0, 0);
+        for key in keys.iter() {
+            // The page can be evicted after keys are cloned
+            let item = match self.get_item(key) {
+                Some(item) => item,
+                None => continue,
+            };
+            let info = item.info.read().unwrap();
+            if info.dirty {
+                dirty += 1;
+            }
+            if info.pins > 0 {
+                pinned += 1;
+            }
+        }
+        StatsSnapshot {
+            counters: self.stats.counters(),
+            cached: keys.len(),
+            dirty,
+            pinned,
+            capacity: *self.max_size,
+        }
+    }
+
+    /// Pages that are pinned, used to find leaked pins
+    pub fn pinned_keys(&self) -> Vec<BufKey> {
+        self.policy
+            .lock()
+            .unwrap()
+            .keys()
+            .into_iter()
+            .filter(|key| {
+                self.get_item(key)
+                    .is_some_and(|item| item.pin_count() > 0)
+            })
+            .collect()
+    }
+
+    pub fn reset_stats(&self) {
+        self.stats.reset();
+    }
+
+    pub fn sync_mode(&self) -> SyncMode {
+        self.sync_mode
+    }
+
+    pub fn archive_dir(&self) -> Option<String> {
+        (*self.archive_dir).clone()
+    }
+
+    /// Fsync the file of key, writes by store_buf are not durable until
+    /// their file is synced
+    pub fn sync_file(&self, key: &BufKey) -> Result<()> {
+        match self.files.get(key) {
+            Ok(file) => Ok(file.sync_data()?),
+            // The file may be dropped or removed after it was written
+            Err(e) => match e.io_kind() {
+                Some(io::ErrorKind::NotFound) => Ok(()),
+                _ => Err(e),
+            },
+        }
+    }
 }
 
 impl std::fmt::Debug for BufMgr {
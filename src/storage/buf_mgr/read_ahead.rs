@@ -0,0 +1,165 @@
+use error::Result;
+use std::collections::VecDeque;
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::sync::{Arc, Condvar, Mutex};
+use storage::buf_mgr::{BufMgr, PinnedPage};
+use storage::BufKey;
+
+/// Requests the reader thread can queue, scans skip prefetching when
+/// the queue is full
+const PREFETCH_QUEUE: usize = 8;
+
+/// Pages loaded in order, returned by BufMgr::read_ahead. Runs of
+/// contiguous pages that are not cached are read with one call, and
+/// the reader thread loads the pages after them while the caller uses
+/// the ones returned. Nothing is read after an error.
+pub struct ReadAhead {
+    pub(super) buf_mgr: BufMgr,
+    pub(super) keys: Vec<BufKey>,
+    /// Index in keys of the first page that is not loaded yet
+    pub(super) next_idx: usize,
+    /// Index in keys of the first page not sent to the reader thread
+    pub(super) prefetched_idx: usize,
+    /// Pages loaded but not returned yet, they stay pinned until then
+    pub(super) pages: VecDeque<PinnedPage>,
+    pub(super) failed: bool,
+    pub(super) in_flight: Arc<InFlight>,
+}
+
+impl ReadAhead {
+    /// Keep at least half of the next read_ahead pages requested from
+    /// the reader thread
+    fn prefetch(&mut self) {
+        let window = self.buf_mgr.read_ahead;
+        if self.failed || self.prefetched_idx >= self.next_idx + window / 2 {
+            return;
+        }
+        let start = self.prefetched_idx.max(self.next_idx);
+        let end = self.keys.len().min(self.next_idx + window);
+        if start >= end {
+            return;
+        }
+        if let Some(prefetcher) = &self.buf_mgr.prefetcher {
+            let keys = self.keys[start..end].to_vec();
+            if prefetcher.request(keys, &self.in_flight) {
+                self.prefetched_idx = end;
+            }
+        }
+    }
+}
+
+impl Iterator for ReadAhead {
+    type Item = Result<PinnedPage>;
+
+    fn next(&mut self) -> Option<Result<PinnedPage>> {
+        if let Some(page) = self.pages.pop_front() {
+            self.prefetch();
+            return Some(Ok(page));
+        }
+        if self.failed || self.next_idx >= self.keys.len() {
+            return None;
+        }
+        let keys = &self.keys[self.next_idx..];
+        let pages = match self.buf_mgr.uncached_run(keys) {
+            0 => self.buf_mgr.get_buf(&keys[0]).map(|page| vec![page]),
+            run => self.buf_mgr.read_run(&keys[..run]),
+        };
+        match pages {
+            Ok(pages) => {
+                self.next_idx += pages.len();
+                self.pages.extend(pages);
+                self.prefetch();
+                self.pages.pop_front().map(Ok)
+            }
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// The pages a scan requested are loaded before it ends, so they are
+/// not added to the pool after the scan's rel is changed
+impl Drop for ReadAhead {
+    fn drop(&mut self) {
+        self.in_flight.wait();
+    }
+}
+
+/// Requests of a ReadAhead that the reader thread has not finished
+#[derive(Debug, Default)]
+pub struct InFlight {
+    count: Mutex<usize>,
+    done: Condvar,
+}
+
+impl InFlight {
+    fn start(&self) {
+        *self.count.lock().unwrap() += 1;
+    }
+
+    fn finish(&self) {
+        *self.count.lock().unwrap() -= 1;
+        self.done.notify_all();
+    }
+
+    fn wait(&self) {
+        let mut count = self.count.lock().unwrap();
+        while *count > 0 {
+            count = self.done.wait(count).unwrap();
+        }
+    }
+}
+
+/// Bounded queue of a thread that loads pages into the pool ahead of
+/// scans. The pages are not pinned, they can be evicted before the
+/// scan gets to them.
+#[derive(Clone)]
+pub struct Prefetcher {
+    sender: SyncSender<(Vec<BufKey>, Arc<InFlight>)>,
+}
+
+impl Prefetcher {
+    /// Start the reader thread of buf_mgr, it stops once every clone
+    /// of the Prefetcher is dropped
+    pub(super) fn start(buf_mgr: &BufMgr) -> Prefetcher {
+        let (sender, receiver) =
+            sync_channel::<(Vec<BufKey>, Arc<InFlight>)>(PREFETCH_QUEUE);
+        let mut reader = buf_mgr.clone();
+        // The reader must not keep its own queue open
+        reader.prefetcher = None;
+        std::thread::spawn(move || {
+            for (keys, in_flight) in receiver {
+                // Errors are returned when the scan reads the page
+                let _ = reader.load_uncached(&keys);
+                in_flight.finish();
+            }
+        });
+        Prefetcher { sender }
+    }
+
+    /// Queue keys to be loaded, false if the queue is full
+    fn request(&self, keys: Vec<BufKey>, in_flight: &Arc<InFlight>) -> bool {
+        in_flight.start();
+        match self.sender.try_send((keys, in_flight.clone())) {
+            Ok(()) => true,
+            Err(_) => {
+                in_flight.finish();
+                false
+            }
+        }
+    }
+}
+
+/// Whether next is the page right after prev in the same file
+pub(super) fn is_next_page(
+    prev: &BufKey,
+    next: &BufKey,
+    page_size: usize,
+) -> bool {
+    prev.file_id == next.file_id
+        && prev.buf_type == next.buf_type
+        && prev.byte_offset(page_size) + page_size as u64
+            == next.byte_offset(page_size)
+}
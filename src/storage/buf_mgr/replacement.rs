@@ -0,0 +1,271 @@
+use db_state::Replacement;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use storage::BufKey;
+
+/// Decides which page BufMgr evicts when it is full. BufMgr calls
+/// insert when a page is added, then access every time it is returned.
+pub trait ReplacementPolicy {
+    fn insert(&mut self, key: BufKey);
+
+    fn access(&mut self, key: &BufKey);
+
+    /// The page was dropped without being evicted
+    fn remove(&mut self, key: &BufKey);
+
+    /// Remove and return a page that can_evict allows, None if no page
+    /// can be evicted, e.g. when every page is pinned
+    fn evict(
+        &mut self,
+        can_evict: &mut dyn FnMut(&BufKey) -> bool,
+    ) -> Option<BufKey>;
+
+    /// Every page the policy tracks
+    fn keys(&self) -> Vec<BufKey>;
+}
+
+pub fn new_policy(
+    replacement: Replacement,
+    capacity: usize,
+) -> Box<dyn ReplacementPolicy + Send> {
+    match replacement {
+        Replacement::Clock => Box::new(Clock::default()),
+        Replacement::LruK(k) => Box::new(LruK::new(k)),
+        Replacement::TwoQ => Box::new(TwoQ::new(capacity)),
+    }
+}
+
+/// Second chance, a page's ref bit is cleared the first time the hand
+/// passes it and it is evicted the next time
+#[derive(Default)]
+pub struct Clock {
+    queue: VecDeque<BufKey>,
+    ref_bits: HashMap<BufKey, bool>,
+}
+
+impl ReplacementPolicy for Clock {
+    fn insert(&mut self, key: BufKey) {
+        self.queue.push_back(key);
+        self.ref_bits.insert(key, false);
+    }
+
+    fn access(&mut self, key: &BufKey) {
+        if let Some(ref_bit) = self.ref_bits.get_mut(key) {
+            *ref_bit = true;
+        }
+    }
+
+    fn remove(&mut self, key: &BufKey) {
+        if self.ref_bits.remove(key).is_some() {
+            self.queue.retain(|k| k != key);
+        }
+    }
+
+    fn evict(
+        &mut self,
+        can_evict: &mut dyn FnMut(&BufKey) -> bool,
+    ) -> Option<BufKey> {
+        // Every ref bit is cleared in the first round
+        for _ in 0..self.queue.len() * 2 {
+            let key = self.queue.pop_front()?;
+            let ref_bit = self.ref_bits.get_mut(&key).unwrap();
+            if *ref_bit {
+                *ref_bit = false;
+            } else if can_evict(&key) {
+                self.ref_bits.remove(&key);
+                return Some(key);
+            }
+            self.queue.push_back(key);
+        }
+        None
+    }
+
+    fn keys(&self) -> Vec<BufKey> {
+        self.queue.iter().cloned().collect()
+    }
+}
+
+/// Evicts the page whose k-th most recent access is the oldest, pages
+/// accessed fewer than k times go first, in LRU order
+pub struct LruK {
+    k: usize,
+    time: u64,
+    history: HashMap<BufKey, PageHistory>,
+    order: BTreeMap<(u64, u64), BufKey>,
+}
+
+struct PageHistory {
+    /// The last k access times, oldest first
+    times: VecDeque<u64>,
+    /// Key of the page in LruK::order
+    rank: (u64, u64),
+}
+
+impl LruK {
+    pub fn new(k: usize) -> LruK {
+        assert!(k > 0);
+        LruK {
+            k,
+            time: 0,
+            history: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.time += 1;
+        self.time
+    }
+}
+
+impl ReplacementPolicy for LruK {
+    fn insert(&mut self, key: BufKey) {
+        let rank = (0, self.tick());
+        let times = VecDeque::with_capacity(self.k);
+        self.history.insert(key, PageHistory { times, rank });
+        self.order.insert(rank, key);
+    }
+
+    fn access(&mut self, key: &BufKey) {
+        let now = self.tick();
+        let history = match self.history.get_mut(key) {
+            Some(history) => history,
+            None => return,
+        };
+        if history.times.len() == self.k {
+            history.times.pop_front();
+        }
+        history.times.push_back(now);
+        let kth = if history.times.len() == self.k {
+            history.times[0]
+        } else {
+            0
+        };
+        self.order.remove(&history.rank);
+        history.rank = (kth, now);
+        self.order.insert(history.rank, *key);
+    }
+
+    fn remove(&mut self, key: &BufKey) {
+        if let Some(history) = self.history.remove(key) {
+            self.order.remove(&history.rank);
+        }
+    }
+
+    fn evict(
+        &mut self,
+        can_evict: &mut dyn FnMut(&BufKey) -> bool,
+    ) -> Option<BufKey> {
+        let key = *self.order.values().find(|key| can_evict(key))?;
+        self.remove(&key);
+        Some(key)
+    }
+
+    fn keys(&self) -> Vec<BufKey> {
+        self.history.keys().cloned().collect()
+    }
+}
+
+/// Pages seen once wait in a FIFO queue, a page is only kept in the LRU
+/// queue of hot pages if it is loaded again soon after being evicted.
+/// Scans go through the FIFO queue without flushing the hot pages.
+pub struct TwoQ {
+    /// Max size of a1in before it is evicted from first
+    kin: usize,
+    /// Max number of remembered keys in a1out
+    kout: usize,
+    time: u64,
+    a1in: VecDeque<BufKey>,
+    a1out: VecDeque<BufKey>,
+    a1out_keys: HashSet<BufKey>,
+    am_times: HashMap<BufKey, u64>,
+    am: BTreeMap<u64, BufKey>,
+}
+
+impl TwoQ {
+    pub fn new(capacity: usize) -> TwoQ {
+        TwoQ {
+            kin: (capacity / 4).max(1),
+            kout: (capacity / 2).max(1),
+            time: 0,
+            a1in: VecDeque::new(),
+            a1out: VecDeque::new(),
+            a1out_keys: HashSet::new(),
+            am_times: HashMap::new(),
+            am: BTreeMap::new(),
+        }
+    }
+
+    fn touch(&mut self, key: BufKey) {
+        self.time += 1;
+        if let Some(time) = self.am_times.insert(key, self.time) {
+            self.am.remove(&time);
+        }
+        self.am.insert(self.time, key);
+    }
+
+    fn evict_a1in(
+        &mut self,
+        can_evict: &mut dyn FnMut(&BufKey) -> bool,
+    ) -> Option<BufKey> {
+        let pos = self.a1in.iter().position(can_evict)?;
+        let key = self.a1in.remove(pos).unwrap();
+        if self.a1out.len() == self.kout {
+            let forgotten = self.a1out.pop_front().unwrap();
+            self.a1out_keys.remove(&forgotten);
+        }
+        self.a1out.push_back(key);
+        self.a1out_keys.insert(key);
+        Some(key)
+    }
+
+    fn evict_am(
+        &mut self,
+        can_evict: &mut dyn FnMut(&BufKey) -> bool,
+    ) -> Option<BufKey> {
+        let key = *self.am.values().find(|key| can_evict(key))?;
+        self.remove(&key);
+        Some(key)
+    }
+}
+
+impl ReplacementPolicy for TwoQ {
+    fn insert(&mut self, key: BufKey) {
+        if self.a1out_keys.remove(&key) {
+            self.a1out.retain(|k| *k != key);
+            self.touch(key);
+        } else {
+            self.a1in.push_back(key);
+        }
+    }
+
+    fn access(&mut self, key: &BufKey) {
+        // Accesses while in a1in are treated as correlated
+        if self.am_times.contains_key(key) {
+            self.touch(*key);
+        }
+    }
+
+    fn remove(&mut self, key: &BufKey) {
+        match self.am_times.remove(key) {
+            Some(time) => {
+                self.am.remove(&time);
+            }
+            None => self.a1in.retain(|k| k != key),
+        }
+    }
+
+    fn evict(
+        &mut self,
+        can_evict: &mut dyn FnMut(&BufKey) -> bool,
+    ) -> Option<BufKey> {
+        if self.a1in.len() > self.kin {
+            self.evict_a1in(can_evict).or_else(|| self.evict_am(can_evict))
+        } else {
+            self.evict_am(can_evict).or_else(|| self.evict_a1in(can_evict))
+        }
+    }
+
+    fn keys(&self) -> Vec<BufKey> {
+        self.a1in.iter().chain(self.am.values()).cloned().collect()
+    }
+}
@@ -0,0 +1,148 @@
+use internal_types::ID;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use storage::{BufKey, BufType};
+
+/// Counters of BufMgr activity, per BufType and per rel
+#[derive(Clone, Debug, Default)]
+pub struct BufStats {
+    counters: Arc<Mutex<HashMap<StatsKey, Counters>>>,
+}
+
+/// Temp and Mem pages are only counted per BufType,
+/// their file ids are short lived
+pub type StatsKey = (BufType, Option<ID>);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counters {
+    /// get_buf found the page in the pool
+    pub hits: u64,
+    /// get_buf had to load the page
+    pub misses: u64,
+    /// Pages read from disk
+    pub reads: u64,
+    /// Pages written to disk
+    pub writes: u64,
+    pub evictions: u64,
+}
+
+impl Counters {
+    fn add(&mut self, other: &Counters) {
+        self.hits += other.hits;
+        self.misses += other.misses;
+        self.reads += other.reads;
+        self.writes += other.writes;
+        self.evictions += other.evictions;
+    }
+
+    pub fn hit_ratio(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+
+    fn values(&self) -> [(&'static str, u64); 5] {
+        [
+            ("hits", self.hits),
+            ("misses", self.misses),
+            ("reads", self.reads),
+            ("writes", self.writes),
+            ("evictions", self.evictions),
+        ]
+    }
+}
+
+impl BufStats {
+    pub fn update<F>(&self, key: &BufKey, func: F)
+    where
+        F: FnOnce(&mut Counters),
+    {
+        let file_id = match key.buf_type {
+            BufType::Data | BufType::Log => Some(key.file_id),
+            BufType::Temp | BufType::Mem => None,
+        };
+        let mut counters = self.counters.lock().unwrap();
+        func(counters.entry((key.buf_type, file_id)).or_default());
+    }
+
+    pub fn counters(&self) -> Vec<(StatsKey, Counters)> {
+        let counters = self.counters.lock().unwrap();
+        let mut counters: Vec<_> =
+            counters.iter().map(|(key, c)| (*key, *c)).collect();
+        counters.sort_by_key(|(key, _)| *key);
+        counters
+    }
+
+    pub fn reset(&self) {
+        self.counters.lock().unwrap().clear();
+    }
+}
+
+/// Counters and the state of the pool when the snapshot was taken
+#[derive(Clone, Debug)]
+pub struct StatsSnapshot {
+    pub counters: Vec<(StatsKey, Counters)>,
+    pub cached: usize,
+    pub dirty: usize,
+    /// Pages used by a thread, these cannot be evicted
+    pub pinned: usize,
+    pub capacity: usize,
+}
+
+impl StatsSnapshot {
+    /// Counters summed per BufType
+    pub fn by_buf_type(&self) -> Vec<(BufType, Counters)> {
+        let mut result: Vec<(BufType, Counters)> = vec![];
+        for ((buf_type, _), counters) in self.counters.iter() {
+            match result.iter_mut().find(|(t, _)| t == buf_type) {
+                Some((_, total)) => total.add(counters),
+                None => result.push((*buf_type, *counters)),
+            }
+        }
+        result
+    }
+
+    pub fn total(&self) -> Counters {
+        let mut total = Counters::default();
+        for (_, counters) in self.counters.iter() {
+            total.add(counters);
+        }
+        total
+    }
+
+    /// Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let gauges = [
+            ("cached", "Pages in the buffer pool", self.cached),
+            ("dirty", "Pages changed since they were written", self.dirty),
+            ("pinned", "Pages in use by a thread", self.pinned),
+            ("capacity", "Max number of pages in the pool", self.capacity),
+        ];
+        for (name, help, value) in gauges.iter() {
+            writeln!(out, "# HELP sueql_buf_{}_pages {}", name, help).unwrap();
+            writeln!(out, "# TYPE sueql_buf_{}_pages gauge", name).unwrap();
+            writeln!(out, "sueql_buf_{}_pages {}", name, value).unwrap();
+        }
+
+        let names = Counters::default().values();
+        for (i, (name, _)) in names.iter().enumerate() {
+            writeln!(out, "# TYPE sueql_buf_{}_total counter", name).unwrap();
+            for ((buf_type, file_id), counters) in self.counters.iter() {
+                let rel = file_id.map_or(String::new(), |id| id.to_string());
+                writeln!(
+                    out,
+                    "sueql_buf_{}_total{{buf_type=\"{:?}\",rel=\"{}\"}} {}",
+                    name,
+                    buf_type,
+                    rel,
+                    counters.values()[i].1
+                )
+                .unwrap();
+            }
+        }
+        out
+    }
+}
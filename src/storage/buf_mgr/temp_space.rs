@@ -0,0 +1,74 @@
+use error::{Error, Result};
+use internal_types::ID;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Temp files of the running query and the pages they use. The files
+/// are dropped when the query ends, see BufMgr::drop_temp_files
+#[derive(Clone, Debug)]
+pub struct TempSpace {
+    /// Shared by all queries, ids are not reused in a session since the
+    /// temp dir is only emptied on start
+    last_id: Arc<AtomicU32>,
+    inner: Arc<Mutex<TempFiles>>,
+    /// Max temp pages of a query, None for no limit
+    limit: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct TempFiles {
+    files: Vec<ID>,
+    pages: u64,
+}
+
+impl TempSpace {
+    pub fn new(limit: Option<u64>) -> TempSpace {
+        TempSpace {
+            last_id: Arc::new(AtomicU32::new(0)),
+            inner: Arc::new(Mutex::new(TempFiles::default())),
+            limit,
+        }
+    }
+
+    /// An empty space for a new query, only ids are shared with self
+    pub fn for_query(&self) -> TempSpace {
+        TempSpace {
+            last_id: self.last_id.clone(),
+            inner: Arc::new(Mutex::new(TempFiles::default())),
+            limit: self.limit,
+        }
+    }
+
+    pub fn new_id(&self) -> ID {
+        let id = self.last_id.fetch_add(1, Ordering::SeqCst) + 1;
+        self.inner.lock().unwrap().files.push(id);
+        id
+    }
+
+    /// Count a page added to a temp file, fails past the limit
+    pub fn add_page(&self) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(limit) = self.limit {
+            if inner.pages >= limit {
+                return Err(Error::Internal(format!(
+                    "Query exceeded the temp space limit of {} pages",
+                    limit
+                )));
+            }
+        }
+        inner.pages += 1;
+        Ok(())
+    }
+
+    /// Pages added since the last take_files
+    pub fn pages(&self) -> u64 {
+        self.inner.lock().unwrap().pages
+    }
+
+    /// The files of the query that ended, the page count starts over
+    pub fn take_files(&self) -> Vec<ID> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.pages = 0;
+        std::mem::take(&mut inner.files)
+    }
+}
@@ -1,9 +1,11 @@
 use crate::error::Result;
-use crate::db_state::DbSettings;
-use crate::storage::buf_page::HEADER_SIZE;
-use crate::storage::{BufKey, BufPage, BufType, PAGE_SIZE};
+use crate::meta::Meta;
+use crate::db_state::{DbSettings, Replacement};
+use crate::storage::buf_page::{HEADER_SIZE, SLOT_SIZE};
+use crate::storage::{BufKey, BufPage, BufType, DEFAULT_PAGE_SIZE};
 use std::io::Write;
-use super::BufMgr;
+use super::{BufMgr, Counters};
+use super::replacement::{LruK, ReplacementPolicy, TwoQ};
 
 #[test]
 fn test_bufmgr_get() {
@@ -13,7 +15,7 @@ fn test_bufmgr_get() {
     teardown_bufmgr(data_dir);
 
     let lock = buf_page.read().unwrap();
-    assert_eq!(lock.buf().len(), PAGE_SIZE);
+    assert_eq!(lock.buf().len(), DEFAULT_PAGE_SIZE);
 }
 
 #[test]
@@ -36,16 +38,57 @@ fn test_bufmgr_store() {
     let mut buf_mgr = BufMgr::new(DbSettings {
         buf_mgr_size: None,
         data_dir: Some(data_dir.to_string()),
+        sync_mode: None,
+        archive_dir: None,
+        replacement: None,
+        metrics_port: None,
+        read_ahead: None,
+        direct_io: None,
+        page_size: None,
+        temp_limit: None,
     });
     let buf_page = buf_mgr.get_buf(&BufKey::new(0, 0, BufType::Data)).unwrap();
     teardown_bufmgr(data_dir);
 
     let lock = buf_page.read().unwrap();
-    assert_eq!(lock.upper_ptr, PAGE_SIZE - 4);
-    assert_eq!(lock.lower_ptr, HEADER_SIZE + 4);
+    assert_eq!(lock.upper_ptr, DEFAULT_PAGE_SIZE - 4);
+    assert_eq!(lock.lower_ptr, HEADER_SIZE + SLOT_SIZE);
     assert_eq!(lock.iter().next().unwrap().to_vec(), vec![1, 1, 1, 1]);
 }
 
+#[test]
+fn test_bufmgr_corrupted_page() {
+    use crate::error::Error;
+    use std::io::{Seek, SeekFrom};
+
+    let data_dir = "test_bufmgr_corrupted_page";
+    let key = BufKey::new(0, 0, BufType::Data);
+    let mut buf_mgr = setup_bufmgr(data_dir, None);
+    {
+        let buf_page = buf_mgr.get_buf(&key).unwrap();
+        let mut lock = buf_page.write().unwrap();
+        lock.write_tuple_data(&[1, 1, 1, 1], None, None).unwrap();
+    }
+    buf_mgr.store_buf(&key, None).unwrap();
+
+    // Overwrite the tuple, as if the page was only partially written
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(format!("{}/0.dat", data_dir))
+        .unwrap();
+    file.seek(SeekFrom::Start((DEFAULT_PAGE_SIZE - 4) as u64)).unwrap();
+    file.write_all(&[2, 2]).unwrap();
+
+    let mut buf_mgr = setup_bufmgr_no_file(data_dir);
+    let res = buf_mgr.get_buf(&key);
+    teardown_bufmgr(data_dir);
+
+    match res {
+        Err(Error::CorruptedPage(corrupted)) => assert_eq!(corrupted, key),
+        _ => panic!("Expected a corrupted page"),
+    }
+}
+
 #[test]
 fn test_bufmgr_new_buf() {
     let data_dir = "test_bufmgr_new_buf";
@@ -107,6 +150,122 @@ fn test_bufmgr_evict() {
     teardown_bufmgr(data_dir);
 }
 
+#[test]
+fn test_bufmgr_all_pinned() {
+    let data_dir = "test_bufmgr_all_pinned";
+
+    let mut buf_mgr = setup_bufmgr(data_dir, Some(2));
+    let _buf_zero = buf_mgr.get_buf(&BufKey::new(0, 0, BufType::Data)).unwrap();
+    let _buf_one = buf_mgr.new_buf(&BufKey::new(0, 1, BufType::Data)).unwrap();
+    let res = buf_mgr.new_buf(&BufKey::new(0, 2, BufType::Data));
+    teardown_bufmgr(data_dir);
+
+    assert!(res.is_err());
+}
+
+#[test]
+fn test_lru_k() {
+    let keys: Vec<BufKey> =
+        (0..3).map(|i| BufKey::new(0, i, BufType::Data)).collect();
+    let mut policy = LruK::new(2);
+    for key in keys.iter() {
+        policy.insert(*key);
+        policy.access(key);
+    }
+    // Page 0 is accessed twice, the others once
+    policy.access(&keys[0]);
+    policy.access(&keys[1]);
+    policy.access(&keys[2]);
+    policy.access(&keys[2]);
+
+    // Pages 1 and 2 have 2 accesses now, page 1's 2nd last is older
+    assert_eq!(policy.evict(&mut |key| *key != keys[0]), Some(keys[1]));
+    assert_eq!(policy.evict(&mut |_| true), Some(keys[0]));
+    assert_eq!(policy.evict(&mut |_| false), None);
+    assert_eq!(policy.keys(), vec![keys[2]]);
+}
+
+#[test]
+fn test_two_q() {
+    let keys: Vec<BufKey> =
+        (0..5).map(|i| BufKey::new(0, i, BufType::Data)).collect();
+    // a1in holds 1 page before it is evicted from first
+    let mut policy = TwoQ::new(4);
+    policy.insert(keys[0]);
+    policy.insert(keys[1]);
+    assert_eq!(policy.evict(&mut |_| true), Some(keys[0]));
+    // Page 0 was evicted from a1in recently, so it is hot when reloaded
+    policy.insert(keys[0]);
+    policy.insert(keys[2]);
+    policy.insert(keys[3]);
+    policy.access(&keys[0]);
+
+    // A scan of pages seen once does not evict the hot page
+    assert_eq!(policy.evict(&mut |_| true), Some(keys[1]));
+    assert_eq!(policy.evict(&mut |_| true), Some(keys[2]));
+    assert_eq!(policy.evict(&mut |_| true), Some(keys[0]));
+    assert_eq!(policy.evict(&mut |_| true), Some(keys[3]));
+}
+
+#[test]
+fn test_bufmgr_replacement() {
+    let data_dir = "test_bufmgr_replacement";
+    setup_bufmgr(data_dir, None);
+    let settings = DbSettings::default()
+        .data_dir(data_dir)
+        .replacement(Replacement::LruK(2));
+    let mut buf_mgr = BufMgr::new(DbSettings {
+        buf_mgr_size: Some(3),
+        ..settings
+    });
+    buf_mgr.get_buf(&BufKey::new(0, 0, BufType::Data)).unwrap();
+    buf_mgr.get_buf(&BufKey::new(0, 0, BufType::Data)).unwrap();
+    buf_mgr.new_buf(&BufKey::new(0, 1, BufType::Data)).unwrap();
+    buf_mgr.new_buf(&BufKey::new(0, 2, BufType::Data)).unwrap();
+    buf_mgr.new_buf(&BufKey::new(0, 3, BufType::Data)).unwrap();
+    teardown_bufmgr(data_dir);
+
+    // Page 0 is the only page accessed twice
+    assert!(buf_mgr.has_buf(&BufKey::new(0, 0, BufType::Data)));
+    assert!(!buf_mgr.has_buf(&BufKey::new(0, 1, BufType::Data)));
+}
+
+#[test]
+fn test_bufmgr_stats() {
+    let data_dir = "test_bufmgr_stats";
+
+    let mut buf_mgr = setup_bufmgr(data_dir, Some(2));
+    let key = BufKey::new(0, 0, BufType::Data);
+    let page = buf_mgr.get_buf(&key).unwrap();
+    page.write().unwrap().write_tuple_data(&[1], None, None).unwrap();
+    let pinned = buf_mgr.stats();
+    drop(page);
+    buf_mgr.get_buf(&key).unwrap();
+    buf_mgr.new_buf(&BufKey::new(0, 1, BufType::Data)).unwrap();
+    // Evicts and writes page 0
+    buf_mgr.new_buf(&BufKey::new(0, 2, BufType::Data)).unwrap();
+    let stats = buf_mgr.stats();
+    buf_mgr.reset_stats();
+    let reset = buf_mgr.stats();
+    teardown_bufmgr(data_dir);
+
+    assert_eq!((pinned.cached, pinned.dirty, pinned.pinned), (1, 1, 1));
+    let expected = Counters {
+        hits: 1,
+        misses: 3,
+        reads: 3,
+        writes: 1,
+        evictions: 1,
+    };
+    assert_eq!(stats.counters, vec![((BufType::Data, Some(0)), expected)]);
+    assert_eq!(stats.by_buf_type(), vec![(BufType::Data, expected)]);
+    assert_eq!((stats.cached, stats.dirty, stats.pinned), (2, 0, 0));
+    assert!(stats
+        .to_prometheus()
+        .contains("sueql_buf_hits_total{buf_type=\"Data\",rel=\"0\"} 1"));
+    assert!(reset.counters.is_empty());
+}
+
 #[test]
 fn test_bufmgr_ref() {
     let data_dir = "test_bufmgr_ref";
@@ -117,8 +276,13 @@ fn test_bufmgr_ref() {
     let _clone2 = buf_mgr.clone();
 
     let buf = buf_mgr.get_buf(&BufKey::new(0, 0, BufType::Data)).unwrap();
-    let _buf_clone = clone.get_buf(&BufKey::new(0, 0, BufType::Data)).unwrap();
-    assert_eq!(buf.ref_count(), 3);
+    let buf_clone = clone.get_buf(&BufKey::new(0, 0, BufType::Data)).unwrap();
+    assert_eq!(buf.pin_count(), 2);
+    drop(buf_clone);
+    assert_eq!(buf.pin_count(), 1);
+    assert_eq!(buf_mgr.pinned_keys(), vec![BufKey::new(0, 0, BufType::Data)]);
+    drop(buf);
+    assert!(buf_mgr.pinned_keys().is_empty());
     teardown_bufmgr(data_dir);
 }
 
@@ -146,6 +310,14 @@ fn test_sequential_scan() -> Result<()> {
     let mut buf_mgr = BufMgr::new(DbSettings {
         data_dir: Some(data_dir.to_string()),
         buf_mgr_size: Some(5),
+        sync_mode: None,
+        archive_dir: None,
+        replacement: None,
+        metrics_port: None,
+        read_ahead: None,
+        direct_io: None,
+        page_size: None,
+        temp_limit: None,
     });
     buf_mgr.get_buf(&BufKey::new(0, 0, BufType::Data))?;
     let start = BufKey::new(1, 0, BufType::Data);
@@ -168,6 +340,182 @@ fn test_sequential_scan() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_read_ahead() -> Result<()> {
+    let data_dir = "test_read_ahead";
+
+    let mut buf_mgr = setup_bufmgr(data_dir, None);
+    for offset in 0..5 {
+        buf_mgr.new_buf(&BufKey::new(1, offset, BufType::Data))?;
+    }
+    let mut buf_mgr =
+        BufMgr::new(DbSettings::default().data_dir(data_dir).read_ahead(4));
+    buf_mgr.get_buf(&BufKey::new(1, 2, BufType::Data))?;
+    let keys = (0..5).map(|offset| BufKey::new(1, offset, BufType::Data));
+    let offsets = buf_mgr
+        .read_ahead(keys.collect())
+        .map(|page| Ok(page?.read().unwrap().buf_key.offset))
+        .collect::<Result<Vec<_>>>()?;
+    // Past the end of the file
+    let keys = vec![
+        BufKey::new(1, 4, BufType::Data),
+        BufKey::new(1, 5, BufType::Data),
+    ];
+    let results: Vec<_> = buf_mgr.read_ahead(keys).collect();
+
+    teardown_bufmgr(data_dir);
+    assert_eq!(offsets, vec![0, 1, 2, 3, 4]);
+    assert_eq!(results.len(), 2);
+    assert!(results[0].is_ok());
+    assert!(results[1].is_err());
+    Ok(())
+}
+
+#[test]
+fn test_prefetch() -> Result<()> {
+    let data_dir = "test_prefetch";
+
+    let mut buf_mgr = setup_bufmgr(data_dir, None);
+    for offset in 0..8 {
+        buf_mgr.new_buf(&BufKey::new(1, offset, BufType::Data))?;
+    }
+    let buf_mgr =
+        BufMgr::new(DbSettings::default().data_dir(data_dir).read_ahead(2));
+    let keys = (0..8).map(|offset| BufKey::new(1, offset, BufType::Data));
+    let mut pages = buf_mgr.read_ahead(keys.collect());
+    // Pages 0 and 1 are read for the caller, 2 and 3 by the reader thread
+    pages.next().unwrap()?;
+    // Waits for the reader thread
+    drop(pages);
+    let cached = (0..8)
+        .filter(|&offset| {
+            buf_mgr.has_buf(&BufKey::new(1, offset, BufType::Data))
+        })
+        .collect::<Vec<_>>();
+    let stats = buf_mgr.stats();
+
+    teardown_bufmgr(data_dir);
+    assert_eq!(cached, vec![0, 1, 2, 3]);
+    assert_eq!(stats.total().reads, 4);
+    Ok(())
+}
+
+#[test]
+fn test_discard_pinned() -> Result<()> {
+    let data_dir = "test_discard_pinned";
+    let mut buf_mgr = setup_bufmgr(data_dir, None);
+    let key = BufKey::new(0, 1, BufType::Data);
+    let page = buf_mgr.new_buf(&key)?;
+    let pinned = buf_mgr.truncate_file(0, BufType::Data, 1);
+    let cached = buf_mgr.pinned_keys();
+    drop(page);
+    let unpinned = buf_mgr.truncate_file(0, BufType::Data, 1);
+    let cached_after = buf_mgr.pinned_keys();
+    teardown_bufmgr(data_dir);
+
+    assert!(pinned.is_err());
+    assert_eq!(cached, vec![key]);
+    assert!(unpinned.is_ok());
+    assert!(cached_after.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_bufmgr_direct_io() -> Result<()> {
+    let data_dir = "test_bufmgr_direct_io";
+    let settings = DbSettings::default().data_dir(data_dir).direct_io(true);
+    let key = BufKey::new(1, 1, BufType::Data);
+
+    let _ = setup_bufmgr(data_dir, None);
+    let mut buf_mgr = BufMgr::new(settings.clone());
+    buf_mgr.new_buf(&BufKey::new(1, 0, BufType::Data))?;
+    let page = buf_mgr.new_buf(&key)?;
+    page.write().unwrap().write_tuple_data(&[1, 2, 3, 4], None, None)?;
+    buf_mgr.store_buf(&key, None)?;
+    // Files are reopened once dropped
+    buf_mgr.new_buf(&BufKey::new(2, 0, BufType::Data))?;
+    buf_mgr.drop_file(2, BufType::Data)?;
+    buf_mgr.new_buf(&BufKey::new(2, 0, BufType::Data))?;
+    drop(page);
+
+    let mut loaded = BufMgr::new(settings);
+    let page = loaded.get_buf(&key)?;
+    let tuple = page.read().unwrap().iter().next().map(|t| t.to_vec());
+    teardown_bufmgr(data_dir);
+    assert_eq!(tuple, Some(vec![1, 2, 3, 4]));
+    Ok(())
+}
+
+#[test]
+fn test_meta_page_format() -> Result<()> {
+    use crate::error::Error;
+    use crate::meta::META_BUF_KEY;
+    use crate::tuple::TuplePtr;
+
+    let data_dir = "test_meta_page_format";
+    std::fs::create_dir_all(data_dir)?;
+    let mut buf_mgr = setup_bufmgr_no_file(data_dir);
+    drop(Meta::new(&mut buf_mgr)?);
+    let current = Meta::load(&mut buf_mgr).map(|_| ());
+    // Meta pages of version 1 have no format tuple
+    let page = buf_mgr.get_buf(&META_BUF_KEY)?;
+    page.write()
+        .unwrap()
+        .remove_tuple(&TuplePtr::new(META_BUF_KEY, 4), None)?;
+    drop(page);
+    let old = Meta::load(&mut buf_mgr).map(|_| ());
+    teardown_bufmgr(data_dir);
+
+    assert!(current.is_ok());
+    match old {
+        Err(Error::Internal(msg)) => assert!(msg.contains("version 1")),
+        _ => panic!("Expected a format version error"),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_bufmgr_page_size() -> Result<()> {
+    use crate::utils::file_len;
+
+    let data_dir = "test_bufmgr_page_size";
+    let page_size = 16384;
+    std::fs::create_dir_all(data_dir)?;
+    let settings = DbSettings::default().data_dir(data_dir);
+
+    let mut buf_mgr = BufMgr::new(settings.clone().page_size(page_size));
+    // The meta page stores the page size
+    drop(Meta::new(&mut buf_mgr)?);
+    let key = BufKey::new(4, 1, BufType::Data);
+    buf_mgr.new_buf(&BufKey::new(4, 0, BufType::Data))?;
+    let page = buf_mgr.new_buf(&key)?;
+    page.write().unwrap().write_tuple_data(&[1; 10000], None, None)?;
+    drop(page);
+    buf_mgr.persist()?;
+    let len = file_len(&format!("{}/4.dat", data_dir))?;
+    // The page size of an existing database is kept
+    let mut loaded = BufMgr::new(settings);
+    let page = loaded.get_buf(&key)?;
+    let tuple = page.read().unwrap().iter().next().map(|t| t.len());
+
+    // The meta file is longer after a crash, the page size is the same
+    let meta_file = format!("{}/0.dat", data_dir);
+    let file = std::fs::OpenOptions::new().write(true).open(&meta_file)?;
+    file.set_len(page_size as u64 + 100)?;
+    let extended = Meta::page_size_on_disk(data_dir)?;
+    // A truncated meta file is an error, not another page size
+    file.set_len(page_size as u64 / 2)?;
+    let truncated = Meta::page_size_on_disk(data_dir);
+
+    teardown_bufmgr(data_dir);
+    assert_eq!(len, 2 * page_size as u64);
+    assert_eq!(loaded.page_size(), page_size);
+    assert_eq!(tuple, Some(10000));
+    assert_eq!(extended, Some(page_size));
+    assert!(truncated.is_err());
+    Ok(())
+}
+
 fn setup_bufmgr(data_dir: &str, buf_mgr_size: Option<usize>) -> BufMgr {
     use std::fs::{create_dir, File};
     use std::io::ErrorKind;
@@ -189,16 +537,28 @@ fn setup_bufmgr(data_dir: &str, buf_mgr_size: Option<usize>) -> BufMgr {
     };
 
     let mut file = File::create(format!("{}/0.dat", data_dir)).unwrap();
-    file.write_all(&BufPage::default_buf()).unwrap();
+    file.write_all(&BufPage::default_buf(DEFAULT_PAGE_SIZE)).unwrap();
 
     let settings = DbSettings {
         buf_mgr_size,
         data_dir: Some(data_dir.to_string()),
+        sync_mode: None,
+        archive_dir: None,
+        replacement: None,
+        metrics_port: None,
+        read_ahead: None,
+        direct_io: None,
+        page_size: None,
+        temp_limit: None,
     };
 
     BufMgr::new(settings)
 }
 
+fn setup_bufmgr_no_file(data_dir: &str) -> BufMgr {
+    BufMgr::new(DbSettings::default().data_dir(data_dir))
+}
+
 fn teardown_bufmgr(data_dir: &str) {
     use std::fs::remove_dir_all;
     remove_dir_all(data_dir).unwrap();
@@ -1,16 +1,22 @@
 use bincode;
+use crc32fast;
 use error::{Error, Result};
 use internal_types::LSN;
 use std::iter::Iterator;
 use storage::buf_key::BufKey;
-use storage::PAGE_SIZE;
 use tuple::tuple_ptr::TuplePtr;
 
-pub const HEADER_SIZE: usize = 12;
+/// Version of the page layout, pages of version 1 had a 12 byte header
+/// without a checksum. It is stored on the meta page, see Meta::load.
+pub const PAGE_FORMAT_VERSION: u32 = 2;
+pub const HEADER_SIZE: usize = 20;
 pub const LSN_RANGE: std::ops::Range<usize> = 0..4;
-pub const UPPER_PTR_RANGE: std::ops::Range<usize> = 4..6;
-pub const LOWER_PTR_RANGE: std::ops::Range<usize> = 6..8;
-pub const GAP_COUNT_RANGE: std::ops::Range<usize> = 8..12;
+pub const UPPER_PTR_RANGE: std::ops::Range<usize> = 4..8;
+pub const LOWER_PTR_RANGE: std::ops::Range<usize> = 8..12;
+pub const GAP_COUNT_RANGE: std::ops::Range<usize> = 12..16;
+pub const CHECKSUM_RANGE: std::ops::Range<usize> = 16..20;
+/// Start and end of a tuple, u32 so they can point past 64K
+pub const SLOT_SIZE: usize = 8;
 
 #[cfg(test)]
 mod tests;
@@ -26,32 +32,101 @@ pub struct BufPage {
     gap_count: u32,
     // BufKey for assertions
     pub buf_key: BufKey,
+    /// Checkpoint count when the page's image was last logged,
+    /// only kept in memory, see LogMgr::write_page_entries
+    pub image_cp: u64,
 }
 
 pub type PagePtr = usize;
 
 impl BufPage {
-    pub fn default_buf() -> Vec<u8> {
-        let mut vec = vec![0 as u8; PAGE_SIZE];
+    pub fn default_buf(page_size: usize) -> Vec<u8> {
+        let mut vec = vec![0u8; page_size];
         vec[LSN_RANGE].clone_from_slice(&bincode::serialize(&0u32).unwrap());
         vec[UPPER_PTR_RANGE].clone_from_slice(
-            &bincode::serialize(&(PAGE_SIZE as u16)).unwrap(),
+            &bincode::serialize(&(page_size as u32)).unwrap(),
         );
         vec[LOWER_PTR_RANGE].clone_from_slice(
-            &bincode::serialize(&(HEADER_SIZE as u16)).unwrap(),
+            &bincode::serialize(&(HEADER_SIZE as u32)).unwrap(),
         );
         vec[GAP_COUNT_RANGE]
             .clone_from_slice(&bincode::serialize(&0u32).unwrap());
+        BufPage::set_checksum(&mut vec);
         vec
     }
 
+    /// CRC32 of the page without its checksum field
+    pub fn checksum(buffer: &[u8]) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&buffer[..CHECKSUM_RANGE.start]);
+        hasher.update(&buffer[CHECKSUM_RANGE.end..]);
+        hasher.finalize()
+    }
+
+    /// Checksums are only written to the buffer when it is stored,
+    /// see BufMgr::store_buf
+    pub fn set_checksum(buffer: &mut [u8]) {
+        let checksum = BufPage::checksum(buffer);
+        buffer[CHECKSUM_RANGE]
+            .clone_from_slice(&bincode::serialize(&checksum).unwrap());
+    }
+
     pub fn load_from(buffer: &[u8], buf_key: &BufKey) -> Result<BufPage> {
-        assert_eq!(buffer.len(), PAGE_SIZE);
+        let checksum: u32 = bincode::deserialize(&buffer[CHECKSUM_RANGE])?;
+        if checksum != BufPage::checksum(buffer) {
+            return Err(Error::CorruptedPage(*buf_key));
+        }
+        BufPage::parse(buffer, buf_key)
+    }
+
+    /// Tuples of a page that fails its checksum, up to the first slot
+    /// out of range. The caller checks them, e.g. the entries of a torn
+    /// log page, as they can be from before or after the write.
+    pub fn salvage_tuples(buffer: &[u8]) -> Vec<Vec<u8>> {
+        let read = |start: usize| -> Option<usize> {
+            let data = buffer.get(start..start + 4)?;
+            bincode::deserialize::<u32>(data).ok().map(|n| n as usize)
+        };
+        let lower = read(LOWER_PTR_RANGE.start).unwrap_or(HEADER_SIZE);
+        let mut tuples = vec![];
+        let mut slot = HEADER_SIZE;
+        while slot + SLOT_SIZE <= lower {
+            let tuple = match (read(slot), read(slot + 4)) {
+                (Some(start), Some(end)) if start <= end => {
+                    buffer.get(start..end)
+                }
+                _ => None,
+            };
+            match tuple {
+                Some(tuple) => tuples.push(tuple.to_vec()),
+                None => break,
+            }
+            slot += SLOT_SIZE;
+        }
+        tuples
+    }
+
+    /// Copy part of a logged page image, the header is read again once
+    /// the last part is restored
+    pub fn restore_image(&mut self, offset: usize, data: &[u8]) -> Result<()> {
+        if offset + data.len() > self.page_size() {
+            return Err(Error::internal("Page image out of range"));
+        }
+        self.buf[offset..offset + data.len()].clone_from_slice(data);
+        if offset + data.len() == self.page_size() {
+            let image_cp = self.image_cp;
+            *self = BufPage::parse(&self.buf, &self.buf_key)?;
+            self.image_cp = image_cp;
+        }
+        Ok(())
+    }
+
+    fn parse(buffer: &[u8], buf_key: &BufKey) -> Result<BufPage> {
         let lsn: u32 = bincode::deserialize(&buffer[LSN_RANGE])?;
         let upper_ptr: PagePtr =
-            bincode::deserialize::<u16>(&buffer[UPPER_PTR_RANGE])? as PagePtr;
+            bincode::deserialize::<u32>(&buffer[UPPER_PTR_RANGE])? as PagePtr;
         let lower_ptr: PagePtr =
-            bincode::deserialize::<u16>(&buffer[LOWER_PTR_RANGE])? as PagePtr;
+            bincode::deserialize::<u32>(&buffer[LOWER_PTR_RANGE])? as PagePtr;
         let gap_count: u32 = bincode::deserialize(&buffer[GAP_COUNT_RANGE])?;
 
         Ok(BufPage {
@@ -61,19 +136,20 @@ impl BufPage {
             lower_ptr: lower_ptr as PagePtr,
             buf_key: buf_key.clone(),
             gap_count,
+            image_cp: 0,
         })
     }
 
     pub fn clear(&mut self) {
-        self.buf = BufPage::default_buf();
+        self.buf = BufPage::default_buf(self.page_size());
         self.lsn = 0;
-        self.upper_ptr = PAGE_SIZE;
+        self.upper_ptr = self.page_size();
         self.lower_ptr = HEADER_SIZE;
         self.gap_count = 0;
     }
 
     pub fn clone_from(&mut self, other: &BufPage) {
-        assert_eq!(other.buf.len(), PAGE_SIZE);
+        assert_eq!(other.buf.len(), self.page_size());
 
         self.buf = other.buf.clone();
         self.lsn = other.lsn;
@@ -114,10 +190,15 @@ impl BufPage {
                     )));
                 }
                 let new_ptr = match self.gap_count {
-                    0 => TuplePtr::new(
-                        self.buf_key.clone(),
-                        BufPage::ptr_to_offset(self.lower_ptr),
-                    ),
+                    0 => {
+                        let ptr = TuplePtr::new(
+                            self.buf_key,
+                            BufPage::ptr_to_offset(self.lower_ptr),
+                        );
+                        self.set_lower_ptr(self.lower_ptr + SLOT_SIZE)?;
+                        ptr
+                    }
+                    // A gap is reused without adding a slot
                     _ => {
                         self.set_gap_count(self.gap_count - 1)?;
                         self.get_gap().unwrap()
@@ -126,7 +207,6 @@ impl BufPage {
                 let new_start = self.upper_ptr - tuple_data.len();
                 let new_end = self.upper_ptr;
                 self.write_start_end(&new_ptr, (new_start, new_end))?;
-                self.set_lower_ptr(self.lower_ptr + 4)?;
                 self.set_upper_ptr(self.upper_ptr - tuple_data.len())?;
                 (new_ptr, new_start)
             }
@@ -162,12 +242,12 @@ impl BufPage {
         self.shift_data(start, tup_len)?;
 
         if last_ptr == *tuple_ptr {
-            self.set_lower_ptr(self.lower_ptr - 4)?;
+            self.set_lower_ptr(self.lower_ptr - SLOT_SIZE)?;
         } else {
             // Make ptr invalid
             self.buf[BufPage::offset_to_ptr(tuple_ptr.buf_offset)
                 ..BufPage::offset_to_ptr(tuple_ptr.buf_offset + 1)]
-                .clone_from_slice(&[0u8; 4]);
+                .clone_from_slice(&[0u8; SLOT_SIZE]);
             // Update gap_count
             self.set_gap_count(self.gap_count + 1)?;
         }
@@ -176,6 +256,109 @@ impl BufPage {
         Ok(())
     }
 
+    /// Rewrite the pointer section without the gaps left by remove_tuple.
+    /// Tuples keep their relative order, but the ones after a gap get a
+    /// new TuplePtr, so the returned (old, new) pairs must be used to
+    /// update indices.
+    pub fn compact(
+        &mut self,
+        lsn: Option<LSN>,
+    ) -> Result<Vec<(TuplePtr, TuplePtr)>> {
+        let tuples = self
+            .get_all_ptrs()
+            .into_iter()
+            .map(|ptr| Ok((ptr, self.get_tuple_data(&ptr)?.to_vec())))
+            .collect::<Result<Vec<_>>>()?;
+        let old_lsn = self.lsn;
+
+        self.clear();
+        let mut moved = vec![];
+        for (old_ptr, data) in tuples.iter() {
+            let new_ptr = self.write_tuple_data(data, None, None)?;
+            if new_ptr != *old_ptr {
+                moved.push((*old_ptr, new_ptr));
+            }
+        }
+
+        self.update_lsn(Some(lsn.unwrap_or(old_lsn)))?;
+        Ok(moved)
+    }
+
+    /// Check that the header and the slot ranges are consistent
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |msg: String| {
+            Err(Error::Internal(format!("{:?}: {}", self.buf_key, msg)))
+        };
+        if self.lower_ptr < HEADER_SIZE
+            || self.lower_ptr > self.upper_ptr
+            || self.upper_ptr > self.page_size()
+            || !(self.lower_ptr - HEADER_SIZE).is_multiple_of(SLOT_SIZE)
+        {
+            return invalid(format!(
+                "invalid lower ptr {} and upper ptr {}",
+                self.lower_ptr, self.upper_ptr
+            ));
+        }
+
+        let slots = self.slots()?;
+        let num_slots = slots.len();
+        let mut ranges = vec![];
+        for (offset, slot) in slots.into_iter().enumerate() {
+            match slot {
+                (0, 0) => {}
+                (start, end) if self.upper_ptr <= start
+                    && start <= end
+                    && end <= self.page_size() =>
+                {
+                    ranges.push((start, end));
+                }
+                (start, end) => {
+                    return invalid(format!(
+                        "slot {} has invalid range {}..{}",
+                        offset, start, end
+                    ));
+                }
+            }
+        }
+        if num_slots - ranges.len() != self.gap_count as usize {
+            return invalid(format!(
+                "{} empty slots but gap count is {}",
+                num_slots - ranges.len(),
+                self.gap_count
+            ));
+        }
+        ranges.sort();
+        for pair in ranges.windows(2) {
+            if pair[0].1 > pair[1].0 {
+                return invalid(format!(
+                    "ranges {}..{} and {}..{} overlap",
+                    pair[0].0, pair[0].1, pair[1].0, pair[1].1
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Start and end of the tuple in every slot, (0, 0) for gaps
+    pub fn slots(&self) -> Result<Vec<(PagePtr, PagePtr)>> {
+        if self.lower_ptr < HEADER_SIZE || self.lower_ptr > self.page_size() {
+            return Err(Error::CorruptedData);
+        }
+        (0..(self.lower_ptr - HEADER_SIZE) / SLOT_SIZE)
+            .map(|offset| {
+                self.get_tuple_range(&TuplePtr::new(self.buf_key, offset))
+            })
+            .collect()
+    }
+
+    pub fn set_lsn(&mut self, lsn: LSN) -> Result<()> {
+        self.update_lsn(Some(lsn))
+    }
+
+    pub fn gap_count(&self) -> u32 {
+        self.gap_count
+    }
+
     /// Shift data from upper_ptr to end dist bytes
     fn shift_data(&mut self, end: PagePtr, dist: usize) -> Result<()> {
         let data = self.buf[self.upper_ptr..end].to_vec();
@@ -231,16 +414,20 @@ impl BufPage {
         &self.buf
     }
 
+    pub fn page_size(&self) -> usize {
+        self.buf.len()
+    }
+
     fn offset_to_ptr(buf_offset: usize) -> PagePtr {
-        HEADER_SIZE + buf_offset * 4
+        HEADER_SIZE + buf_offset * SLOT_SIZE
     }
 
     fn ptr_to_offset(ptr: PagePtr) -> usize {
-        (ptr - HEADER_SIZE) / 4
+        (ptr - HEADER_SIZE) / SLOT_SIZE
     }
 
     pub fn tuple_count(&self) -> usize {
-        (self.lower_ptr - HEADER_SIZE) / 4 - self.gap_count as usize
+        (self.lower_ptr - HEADER_SIZE) / SLOT_SIZE - self.gap_count as usize
     }
 
     fn is_valid_tuple_ptr(&self, tuple_ptr: &TuplePtr) -> Result<()> {
@@ -282,8 +469,8 @@ impl BufPage {
     ) -> Result<(PagePtr, PagePtr)> {
         let ptr: PagePtr = BufPage::offset_to_ptr(tuple_ptr.buf_offset);
         let start =
-            bincode::deserialize::<u16>(&self.buf[ptr..ptr + 2])? as PagePtr;
-        let end = bincode::deserialize::<u16>(&self.buf[ptr + 2..ptr + 4])?
+            bincode::deserialize::<u32>(&self.buf[ptr..ptr + 4])? as PagePtr;
+        let end = bincode::deserialize::<u32>(&self.buf[ptr + 4..ptr + 8])?
             as PagePtr;
         Ok((start, end))
     }
@@ -293,7 +480,7 @@ impl BufPage {
             0 => None,
             _ => Some(TuplePtr {
                 buf_key: self.buf_key.clone(),
-                buf_offset: (self.lower_ptr - HEADER_SIZE) / 4 - 1,
+                buf_offset: (self.lower_ptr - HEADER_SIZE) / SLOT_SIZE - 1,
             }),
         }
     }
@@ -304,24 +491,24 @@ impl BufPage {
         (start, end): (PagePtr, PagePtr),
     ) -> Result<()> {
         let ptr = BufPage::offset_to_ptr(tuple_ptr.buf_offset);
-        self.buf[ptr..ptr + 2]
-            .clone_from_slice(&bincode::serialize(&(start as u16))?);
-        self.buf[ptr + 2..ptr + 4]
-            .clone_from_slice(&bincode::serialize(&(end as u16))?);
+        self.buf[ptr..ptr + 4]
+            .clone_from_slice(&bincode::serialize(&(start as u32))?);
+        self.buf[ptr + 4..ptr + 8]
+            .clone_from_slice(&bincode::serialize(&(end as u32))?);
         Ok(())
     }
 
     fn set_upper_ptr(&mut self, ptr: PagePtr) -> Result<()> {
         self.upper_ptr = ptr;
         self.buf[UPPER_PTR_RANGE]
-            .clone_from_slice(&bincode::serialize(&(ptr as u16))?);
+            .clone_from_slice(&bincode::serialize(&(ptr as u32))?);
         Ok(())
     }
 
     fn set_lower_ptr(&mut self, ptr: PagePtr) -> Result<()> {
         self.lower_ptr = ptr;
         self.buf[LOWER_PTR_RANGE]
-            .clone_from_slice(&bincode::serialize(&(ptr as u16))?);
+            .clone_from_slice(&bincode::serialize(&(ptr as u32))?);
         Ok(())
     }
 
@@ -359,7 +546,7 @@ impl BufPage {
 
     pub fn available_data_space(&self) -> usize {
         match self.gap_count {
-            0 => self.upper_ptr - self.lower_ptr - 4,
+            0 => self.upper_ptr - self.lower_ptr - SLOT_SIZE,
             _ => self.upper_ptr - self.lower_ptr,
         }
     }
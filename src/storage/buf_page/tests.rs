@@ -1,6 +1,6 @@
 use bincode;
-use storage::buf_page::HEADER_SIZE;
-use storage::{BufKey, BufType, PAGE_SIZE};
+use storage::buf_page::{HEADER_SIZE, SLOT_SIZE};
+use storage::{BufKey, BufType, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
 use super::BufPage;
 use tuple::tuple_ptr::TuplePtr;
 
@@ -15,28 +15,26 @@ fn test_write_new_tuple() {
 
     // upper_ptr
     assert_eq!(
-        bincode::deserialize::<u16>(&buf_page.buf()[UPPER_PTR_RANGE]).unwrap()
+        bincode::deserialize::<u32>(&buf_page.buf()[UPPER_PTR_RANGE]).unwrap()
             as usize,
-        PAGE_SIZE - 16
+        DEFAULT_PAGE_SIZE - 16
     );
     // lower_ptr
     assert_eq!(
-        bincode::deserialize::<u16>(&buf_page.buf()[LOWER_PTR_RANGE]).unwrap()
+        bincode::deserialize::<u32>(&buf_page.buf()[LOWER_PTR_RANGE]).unwrap()
             as usize,
-        HEADER_SIZE + 4
+        HEADER_SIZE + SLOT_SIZE
     );
     // tuple_ptr
     assert_eq!(
-        bincode::deserialize::<u16>(
-            &buf_page.buf()[HEADER_SIZE..HEADER_SIZE + 2]
+        bincode::deserialize::<u32>(
+            &buf_page.buf()[HEADER_SIZE..HEADER_SIZE + 4]
         )
         .unwrap() as usize,
-        PAGE_SIZE - 16
+        DEFAULT_PAGE_SIZE - 16
     );
     // tuple_data
-    for byte in
-        buf_page.buf()[(PAGE_SIZE - 16) as usize..PAGE_SIZE as usize].iter()
-    {
+    for byte in buf_page.buf()[DEFAULT_PAGE_SIZE - 16..].iter() {
         assert_eq!(*byte, 5);
     }
 }
@@ -122,8 +120,8 @@ fn test_remove_tuple() {
         buf_page.write_tuple_data(&[2u8; 1], None, None).unwrap();
         // Remove
         buf_page.remove_tuple(&to_remove, None).unwrap();
-        assert_eq!(buf_page.upper_ptr, PAGE_SIZE - 2);
-        assert_eq!(buf_page.lower_ptr, HEADER_SIZE + (4 * 3));
+        assert_eq!(buf_page.upper_ptr, DEFAULT_PAGE_SIZE - 2);
+        assert_eq!(buf_page.lower_ptr, HEADER_SIZE + (SLOT_SIZE * 3));
         assert_eq!(buf_page.iter().count(), 2);
 
         let mut iter = buf_page.iter();
@@ -141,11 +139,11 @@ fn test_remove_tuple() {
         buf_page.write_tuple_data(&[1u8; 1], None, None).unwrap();
         let to_remove =
             buf_page.write_tuple_data(&[2u8; 1], None, None).unwrap();
-        assert_eq!(buf_page.lower_ptr, HEADER_SIZE + (4 * 3));
+        assert_eq!(buf_page.lower_ptr, HEADER_SIZE + (SLOT_SIZE * 3));
         // Remove
         buf_page.remove_tuple(&to_remove, None).unwrap();
-        assert_eq!(buf_page.upper_ptr, PAGE_SIZE - 2);
-        assert_eq!(buf_page.lower_ptr, HEADER_SIZE + (4 * 2));
+        assert_eq!(buf_page.upper_ptr, DEFAULT_PAGE_SIZE - 2);
+        assert_eq!(buf_page.lower_ptr, HEADER_SIZE + (SLOT_SIZE * 2));
 
         let mut iter = buf_page.iter();
         assert_eq!(iter.next().unwrap(), [0u8]);
@@ -165,6 +163,9 @@ fn test_remove_and_write() {
 
     buf_page.remove_tuple(&to_remove, None).unwrap();
     buf_page.write_tuple_data(&[3u8; 1], None, None).unwrap();
+    // The gap is reused, no slot is added
+    assert_eq!(buf_page.lower_ptr, HEADER_SIZE + (SLOT_SIZE * 3));
+    assert_eq!(buf_page.tuple_count(), 3);
 
     let mut iter = buf_page.iter();
     assert_eq!(iter.next().unwrap(), [0u8]);
@@ -173,9 +174,62 @@ fn test_remove_and_write() {
     assert!(iter.next().is_none());
 }
 
+#[test]
+fn test_compact() {
+    let mut buf_page = new_page();
+    // Write some tuples
+    buf_page.write_tuple_data(&[0u8; 1], None, None).unwrap();
+    let to_remove = buf_page.write_tuple_data(&[1u8; 2], None, None).unwrap();
+    let two = buf_page.write_tuple_data(&[2u8; 3], None, None).unwrap();
+    let three = buf_page.write_tuple_data(&[3u8; 4], None, None).unwrap();
+    buf_page.remove_tuple(&to_remove, None).unwrap();
+    assert_eq!(buf_page.gap_count(), 1);
+
+    let moved = buf_page.compact(Some(10)).unwrap();
+    assert_eq!(moved, vec![(two, to_remove), (three, two)]);
+    assert_eq!(buf_page.gap_count(), 0);
+    assert_eq!(buf_page.lsn, 10);
+    assert_eq!(buf_page.lower_ptr, HEADER_SIZE + (SLOT_SIZE * 3));
+    assert_eq!(buf_page.upper_ptr, DEFAULT_PAGE_SIZE - 8);
+
+    let mut iter = buf_page.iter();
+    assert_eq!(iter.next().unwrap(), [0u8; 1]);
+    assert_eq!(iter.next().unwrap(), [2u8; 3]);
+    assert_eq!(iter.next().unwrap(), [3u8; 4]);
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn test_checksum() {
+    let mut buf = BufPage::default_buf(DEFAULT_PAGE_SIZE);
+    let key = BufKey::new(0, 0, BufType::Data);
+    assert!(BufPage::load_from(&buf, &key).is_ok());
+
+    buf[DEFAULT_PAGE_SIZE - 1] = 1;
+    assert!(BufPage::load_from(&buf, &key).is_err());
+    BufPage::set_checksum(&mut buf);
+    assert!(BufPage::load_from(&buf, &key).is_ok());
+}
+
+#[test]
+fn test_max_page_size() {
+    let key = BufKey::new(0, 0, BufType::Data);
+    let buf = BufPage::default_buf(MAX_PAGE_SIZE);
+    let mut buf_page = BufPage::load_from(&buf, &key).unwrap();
+    assert_eq!(buf_page.upper_ptr, MAX_PAGE_SIZE);
+
+    let ptr = buf_page.write_tuple_data(&[7u8; 100], None, None).unwrap();
+    let mut buf = buf_page.buf().clone();
+    BufPage::set_checksum(&mut buf);
+    let loaded = BufPage::load_from(&buf, &key).unwrap();
+    assert_eq!(loaded.upper_ptr, MAX_PAGE_SIZE - 100);
+    assert_eq!(loaded.get_tuple_data(&ptr).unwrap(), &[7u8; 100][..]);
+    assert!(loaded.validate().is_ok());
+}
+
 fn new_page() -> BufPage {
     BufPage::load_from(
-        &BufPage::default_buf(),
+        &BufPage::default_buf(DEFAULT_PAGE_SIZE),
         &BufKey::new(0, 0, BufType::Data),
     )
     .unwrap()
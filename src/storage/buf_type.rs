@@ -1,8 +1,13 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize,
+    Deserialize,
+)]
 pub enum BufType {
     Data,
     Temp,
     Mem,
+    /// Pages of the WAL, stored in segment files
+    Log,
 }
@@ -1,4 +1,10 @@
-pub const PAGE_SIZE: usize = 4096;
+/// Page size of new databases, the page size of a database is fixed
+/// when it is created, see BufMgr::page_size
+pub const DEFAULT_PAGE_SIZE: usize = 4096;
+pub const MIN_PAGE_SIZE: usize = 4096;
+pub const MAX_PAGE_SIZE: usize = 65536;
+/// Number of pages in a WAL segment file
+pub const LOG_SEGMENT_PAGES: u64 = 256;
 
 pub mod buf_key;
 pub mod buf_mgr;
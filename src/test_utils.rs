@@ -9,11 +9,15 @@ pub fn setup(data_dir: &str) -> DbState {
 }
 
 pub fn setup_no_persist(data_dir: &str) -> DbState {
-    // Similar to start_db but persist loop is not started
+    setup_with_settings(DbSettings::default().data_dir(data_dir))
+}
+
+/// Similar to start_db but persist loop is not started
+pub fn setup_with_settings(settings: DbSettings) -> DbState {
     use std::fs::create_dir;
     use std::io::ErrorKind;
 
-    match create_dir(data_dir) {
+    match create_dir(settings.get_data_dir()) {
         Ok(_) => {}
         Err(e) => match e.kind() {
             ErrorKind::AlreadyExists => {}
@@ -21,21 +25,19 @@ pub fn setup_no_persist(data_dir: &str) -> DbState {
         },
     };
 
-    let settings = DbSettings {
-        buf_mgr_size: None,
-        data_dir: Some(data_dir.to_string()),
-    };
-
     let mut buf_mgr = BufMgr::new(settings.clone());
     let log_mgr = LogMgr::create_and_load(&mut buf_mgr).unwrap();
+    buf_mgr.set_log_mgr(&log_mgr);
     let meta = Meta::create_and_load(&mut buf_mgr).unwrap();
 
-    DbState {
+    let mut db_state = DbState {
         buf_mgr,
         log_mgr,
         meta,
         settings,
-    }
+    };
+    db_state.log_mgr.clone().recover(&mut db_state).unwrap();
+    db_state
 }
 
 pub fn teardown(mut db_state: DbState) {
@@ -26,6 +26,11 @@ impl TupleDesc {
         }
     }
 
+    /// Desc without attributes, e.g. of an index without included columns
+    pub fn empty() -> TupleDesc {
+        TupleDesc::new(vec![], Vec::<String>::new())
+    }
+
     pub fn union(descs: Vec<TupleDesc>) -> Result<TupleDesc> {
         use std::collections::HashMap;
         let mut map = HashMap::new();
@@ -137,6 +142,9 @@ impl TupleDesc {
         for attr in self.attr_types.iter() {
             let attr_len =
                 attr.data_size(Some(&bytes[cur_bytes..bytes.len()]))?;
+            if cur_bytes + attr_len > bytes.len() {
+                return Err(Error::CorruptedData);
+            }
             cols.push(&bytes[cur_bytes..cur_bytes + attr_len]);
             cur_bytes += attr_len;
         }
@@ -147,6 +155,9 @@ impl TupleDesc {
     pub fn assert_data_len(&self, data: &[u8]) -> Result<()> {
         let mut sum = 0;
         for attr in self.attr_types.iter() {
+            if sum > data.len() {
+                break;
+            }
             sum += attr.data_size(Some(&data[sum..data.len()]))?;
         }
 
@@ -160,6 +171,16 @@ impl TupleDesc {
         }
     }
 
+    /// Decode every attribute of data, without panicking on bad data
+    pub fn decode(&self, data: &[u8]) -> Result<Vec<String>> {
+        self.assert_data_len(data)?;
+        self.attr_types
+            .iter()
+            .zip(self.cols(data)?)
+            .map(|(attr, col)| attr.data_to_string(col))
+            .collect()
+    }
+
     pub fn num_attrs(&self) -> u32 {
         self.attr_types.len() as u32
     }
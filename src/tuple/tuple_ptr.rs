@@ -3,7 +3,10 @@ use storage::BufKey;
 
 /// Struct that specifies location of tuple in a buffer
 ///     * buf_offset: starting from 0
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize,
+    Deserialize,
+)]
 pub struct TuplePtr {
     pub buf_key: BufKey,
     pub buf_offset: usize,
@@ -2,7 +2,7 @@ use db_state::DbState;
 use error::{Error, Result};
 use index::Index;
 use internal_types::ID;
-use std::fs::{File, metadata};
+use std::fs::{File, metadata, read_dir};
 use std::io::{Write, ErrorKind};
 
 #[macro_export]
@@ -31,17 +31,17 @@ pub fn file_exists(fname: &str) -> bool {
     }
 }
 
-pub fn create_file(fname: &str) -> Result<()> {
+pub fn create_file(fname: &str, page_size: usize) -> Result<()> {
     use storage::buf_page::BufPage;
 
     let mut file = File::create(fname)?;
-    file.write_all(&BufPage::default_buf())?;
+    file.write_all(&BufPage::default_buf(page_size))?;
     Ok(())
 }
 
-pub fn num_pages(fname: &str) -> Result<u64> {
-    use storage::PAGE_SIZE;
-    Ok(file_len(fname)? / PAGE_SIZE as u64 - 1)
+/// Number of pages after the first page of a file
+pub fn num_pages(fname: &str, page_size: usize) -> Result<u64> {
+    Ok(file_len(fname)? / page_size as u64 - 1)
 }
 
 pub fn file_len(fname: &str) -> Result<u64> {
@@ -56,13 +56,51 @@ pub fn file_len(fname: &str) -> Result<u64> {
     }
 }
 
+/// Largest ID among the files in data_dir, temp files are not included
+pub fn max_file_id(data_dir: &str) -> Result<Option<ID>> {
+    Ok(data_file_ids(data_dir)?.into_iter().max())
+}
+
+/// IDs of the .dat files in data_dir
+pub fn data_file_ids(data_dir: &str) -> Result<Vec<ID>> {
+    let mut ids = vec![];
+    for entry in read_dir(data_dir)? {
+        let id = entry?
+            .file_name()
+            .to_str()
+            .and_then(|name| name.strip_suffix(".dat"))
+            .and_then(|id| id.parse::<ID>().ok());
+        ids.extend(id);
+    }
+    Ok(ids)
+}
+
 pub fn get_table_id(name: String, db_state: &mut DbState) -> Result<ID> {
+    match find_table_id(name, db_state)? {
+        Some(id) => Ok(id),
+        None => Err(Error::Internal("Invalid table name".to_string())),
+    }
+}
+
+/// Same as get_table_id, but a missing table is not an error
+pub fn find_table_id(
+    name: String,
+    db_state: &mut DbState,
+) -> Result<Option<ID>> {
     let index = db_state.meta.table_index.clone();
     let ptrs = index.get(&bincode::serialize(&name)?, db_state)?;
     match ptrs.len() {
-        1 => {
-            Ok(ptrs[0].buf_key.file_id)
-        }
+        0 => Ok(None),
+        1 => Ok(Some(ptrs[0].buf_key.file_id)),
         _ => Err(Error::Internal("Invalid table name".to_string())),
     }
 }
+
+pub fn get_all_table_ids(db_state: &mut DbState) -> Result<Vec<ID>> {
+    let index = db_state.meta.table_index.clone();
+    Ok(index
+        .all_ptrs(db_state)?
+        .into_iter()
+        .map(|ptr| ptr.buf_key.file_id)
+        .collect())
+}